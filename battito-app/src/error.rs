@@ -1,4 +1,6 @@
+use battito_lib::pattern::error::{Error, LimitError, TidalError};
 use nannou_osc::CommunicationError;
+use std::fmt;
 use std::io;
 
 #[derive(Debug)]
@@ -7,6 +9,29 @@ pub enum BattitoError {
     UDPError,
     ParsingError,
     OSCPacketError,
+    // Construct without an equivalent in the other notation
+    Unsupported(String),
+    // A limit of the transformation was exceeded, raised with the --max-* options
+    Limit(LimitError),
+}
+
+impl fmt::Display for BattitoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BattitoError::InputError => write!(f, "Invalid input"),
+            BattitoError::UDPError => write!(f, "Could not send the OSC message"),
+            BattitoError::ParsingError => write!(f, "Invalid pattern"),
+            BattitoError::OSCPacketError => write!(f, "Invalid OSC packet"),
+            BattitoError::Unsupported(construct) => write!(f, "Unsupported: {}", construct),
+            BattitoError::Limit(limit) => match limit {
+                LimitError::TooManyMeasures => write!(f, "Too many measures, see --max-measures"),
+                LimitError::TooManyEvents => write!(f, "Too many events, see --max-events"),
+                LimitError::TooDeep => write!(f, "Pattern nested too deeply, see --max-depth"),
+                LimitError::SubdivisionTooLarge => write!(f, "Subdivision too large, see --max-subdivision"),
+                LimitError::Overflow => write!(f, "Length or offset out of range"),
+            },
+        }
+    }
 }
 
 impl From<io::Error> for BattitoError {
@@ -21,9 +46,13 @@ impl From<CommunicationError> for BattitoError {
     }
 }
 
-impl From<battito_lib::pattern::error::Error> for BattitoError {
-    fn from(_: battito_lib::pattern::error::Error) -> Self {
-        BattitoError::ParsingError
+impl From<Error> for BattitoError {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::TidalError(TidalError::Unsupported(construct)) => BattitoError::Unsupported(construct),
+            Error::LimitError(limit) => BattitoError::Limit(limit),
+            _ => BattitoError::ParsingError,
+        }
     }
 }
 
@@ -1,17 +1,35 @@
 mod error;
+#[allow(dead_code)]
 mod line;
 
 use crate::error::BattitoError;
+use battito_lib::pattern::equivalence;
+use battito_lib::pattern::grid::{self, Charset, GridOptions};
+use battito_lib::pattern::humanize::Humanize;
+use battito_lib::pattern::limits::Limits;
+use battito_lib::pattern::midi::{self, ImportOptions, MidiFormat, MidiOptions, NoteMap, Track, Velocity};
+use battito_lib::pattern::nudge::Boundary;
 use battito_lib::pattern::pattern::Pattern;
-use battito_lib::pattern::{transform, OutputFormat};
+use battito_lib::pattern::schema;
+use battito_lib::pattern::simplify;
+use battito_lib::pattern::tempo::{Tempo, DEFAULT_BPM};
+use battito_lib::pattern::tidal;
+use battito_lib::pattern::{format, transform_with_options, Options, OutputFormat};
 use nannou_osc as osc;
 use nannou_osc::rosc::OscMessage;
 use nannou_osc::rosc::OscType;
 use nannou_osc::{Connected, Sender};
 use osc::Receiver;
 use std::io;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
+// Exit codes of the commands, equiv exits with EXIT_DIFFERENT when the patterns differ
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_ERROR: i32 = 1;
+const EXIT_DIFFERENT: i32 = 2;
+
 pub struct Config {
     host: String,
     sender_port: u16,
@@ -38,10 +56,231 @@ struct Opt {
     subdivision: u32,
     #[structopt(short, long)]
     osc: Option<bool>,
+    /// Also send the exact length in cycles and the first tick of every measure, to the address of
+    /// the pattern followed by "/measures"
+    #[structopt(long)]
+    osc_measures: bool,
+    /// Wrap events nudged outside of the sequence, and ratchet repetitions past its end, around instead
+    /// of clamping or dropping them
+    #[structopt(long)]
+    wrap: bool,
+    /// Random timing and velocity offsets, as "ticks:velocity". humanize(ticks, velocity)[...] in
+    /// a pattern takes precedence
+    #[structopt(long, parse(try_from_str = parse_humanize))]
+    humanize: Option<(u32, u8)>,
+    /// Seed of the humanize offsets
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+    /// Tempo, adds onsets and durations in milliseconds to the output
+    #[structopt(long)]
+    bpm: Option<f64>,
+    /// Cycles in a bar of four beats, 2 plays every cycle in half a bar
+    #[structopt(long, default_value = "1")]
+    cycles_per_bar: f64,
+    /// Largest number of measures of a pattern
+    #[structopt(long)]
+    max_measures: Option<usize>,
+    /// Largest number of events of a pattern
+    #[structopt(long)]
+    max_events: Option<usize>,
+    /// Deepest nesting of groups, alternates and functions
+    #[structopt(long)]
+    max_depth: Option<usize>,
+    /// Largest subdivision
+    #[structopt(long)]
+    max_subdivision: Option<u32>,
+    /// Output of the patterns read from standard input: "steps" or "grid"
+    #[structopt(long, default_value = "steps", parse(try_from_str = parse_view))]
+    view: View,
+    /// Width of the grid view, the terminal width by default
+    #[structopt(long)]
+    width: Option<usize>,
+    /// Draw the grid view with Unicode block characters
+    #[structopt(long)]
+    unicode: bool,
+    /// Format of the steps view and of the write command: "max", "json", "csv", "msgpack" or
+    /// "binary". The steps view prints the binary formats in hexadecimal
+    #[structopt(long, default_value = "max", parse(try_from_str = parse_format))]
+    format: OutputFormat,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum View {
+    Steps,
+    Grid,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Write the patterns to a Standard MIDI File, one track per pattern
+    Midi {
+        /// File to write
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+        /// Write a type 0 file, with every pattern in the same track
+        #[structopt(long)]
+        single_track: bool,
+        /// Velocity of the notes: "event", "probability" or a number
+        #[structopt(long, default_value = "event", parse(try_from_str = parse_velocity))]
+        velocity: Velocity,
+        /// MIDI channel of the notes, from 1 to 16
+        #[structopt(long, default_value = "1")]
+        channel: u8,
+        /// Note played by an event name, as "name=note", on top of the General MIDI drums
+        #[structopt(long = "note", number_of_values = 1, parse(try_from_str = parse_note))]
+        notes: Vec<(String, u8)>,
+        /// Names of the tracks, separated by commas
+        #[structopt(long)]
+        names: Option<String>,
+        patterns: Vec<String>,
+    },
+    /// Print the expressions reproducing a Standard MIDI File, one per layer of notes
+    Import {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Steps of a cycle, the notes are moved to the nearest one
+        #[structopt(long, default_value = "16")]
+        grid: u32,
+        /// Event name of a note, as "name=note", on top of the General MIDI drums
+        #[structopt(long = "note", number_of_values = 1, parse(try_from_str = parse_note))]
+        notes: Vec<(String, u8)>,
+    },
+    /// Print the patterns with normalized spacing, one per line
+    Fmt { patterns: Vec<String> },
+    /// Print the shortest expression of each pattern giving the same events, one per line
+    Simplify { patterns: Vec<String> },
+    /// Print the patterns in the mini-notation of TidalCycles and Strudel, one per line
+    ToTidal { patterns: Vec<String> },
+    /// Print the patterns written in the mini-notation of TidalCycles and Strudel, one per line
+    FromTidal { patterns: Vec<String> },
+    /// Tell whether two patterns play the same events, looping them until they end together
+    Equiv {
+        first: String,
+        second: String,
+        /// Steps of a cycle the onsets and durations are rounded to
+        #[structopt(long)]
+        resolution: Option<NonZeroU32>,
+    },
+    /// Print the JSON Schema of the patterns written as JSON
+    Schema,
+    /// Write a pattern to a file in the format given by --format
+    Write {
+        /// File to write
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+        pattern: String,
+    },
+}
+
+// The notes given first win over the default ones
+fn note_map(notes: &[(String, u8)]) -> NoteMap {
+    let mut note_map = NoteMap(notes.to_vec());
+    note_map.0.extend(NoteMap::default().0);
+    note_map
+}
+
+fn parse_velocity(input: &str) -> Result<Velocity, String> {
+    match input {
+        "event" => Ok(Velocity::Event),
+        "probability" => Ok(Velocity::Probability),
+        v => v
+            .parse()
+            .map(Velocity::Fixed)
+            .map_err(|_| format!("Invalid velocity: {}", input)),
+    }
+}
+
+fn parse_view(input: &str) -> Result<View, String> {
+    match input {
+        "steps" => Ok(View::Steps),
+        "grid" => Ok(View::Grid),
+        _ => Err(format!("Invalid view: {}", input)),
+    }
+}
+
+fn parse_format(input: &str) -> Result<OutputFormat, String> {
+    match input {
+        "max" => Ok(OutputFormat::Max),
+        "json" => Ok(OutputFormat::Json),
+        "csv" => Ok(OutputFormat::Csv),
+        "msgpack" => Ok(OutputFormat::MessagePack),
+        "binary" => Ok(OutputFormat::Binary),
+        _ => Err(format!("Invalid format: {}", input)),
+    }
+}
+
+fn parse_note(input: &str) -> Result<(String, u8), String> {
+    let invalid = || format!("Invalid note: {}", input);
+    let (name, note) = input.split_once('=').ok_or_else(invalid)?;
+    Ok((name.to_string(), note.parse().map_err(|_| invalid())?))
+}
+
+fn parse_humanize(input: &str) -> Result<(u32, u8), String> {
+    let invalid = || format!("Invalid humanize amounts: {}", input);
+    let (ticks, velocity) = input.split_once(':').ok_or_else(invalid)?;
+    Ok((
+        ticks.parse().map_err(|_| invalid())?,
+        velocity.parse().map_err(|_| invalid())?,
+    ))
+}
+
+impl Opt {
+    fn options(&self) -> Result<Options, BattitoError> {
+        Ok(Options {
+            subdivision: Some(self.subdivision),
+            boundary: if self.wrap { Boundary::Wrap } else { Boundary::Clamp },
+            humanize: self
+                .humanize
+                .map(|(ticks, velocity)| Humanize::new(ticks, velocity, self.seed)),
+            tempo: self.bpm.map(|bpm| Tempo::new(bpm, self.cycles_per_bar)).transpose()?,
+            limits: self.limits(),
+            complete: false,
+        })
+    }
+
+    // Arguments of the commands are whole patterns, trailing text is an error
+    fn command_options(&self) -> Result<Options, BattitoError> {
+        Ok(Options {
+            complete: true,
+            ..self.options()?
+        })
+    }
+
+    fn grid_options(&self) -> GridOptions {
+        let columns = std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok());
+        GridOptions {
+            width: self.width.or(columns).unwrap_or(GridOptions::default().width),
+            charset: if self.unicode { Charset::Unicode } else { Charset::Ascii },
+        }
+    }
+
+    fn limits(&self) -> Limits {
+        let default = Limits::default();
+        Limits {
+            max_measures: self.max_measures.unwrap_or(default.max_measures),
+            max_events: self.max_events.unwrap_or(default.max_events),
+            max_depth: self.max_depth.unwrap_or(default.max_depth),
+            max_subdivision: self.max_subdivision.unwrap_or(default.max_subdivision),
+        }
+    }
 }
 
 fn main() -> std::io::Result<()> {
     let opt = Opt::from_args();
+    if let Some(command) = &opt.command {
+        match run(command, &opt) {
+            Ok((done, code)) => {
+                println!("{}", done);
+                std::process::exit(code)
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(EXIT_ERROR)
+            }
+        }
+    }
     let config = Config {
         host: "127.0.0.1".to_string(),
         sender_port: 1234,
@@ -54,15 +293,16 @@ fn main() -> std::io::Result<()> {
                 let receiver = config.receiver();
                 match process(&receiver, &sender, &opt) {
                     Ok(sent_packet) => println!("{:?}", sent_packet),
-                    Err(error) => println!("{:?}", error),
+                    Err(error) => eprintln!("{}", error),
                 }
             }
             _ => {
                 let mut buffer = String::new();
                 io::stdin().read_line(&mut buffer)?;
-                match process_stdin(buffer, &opt) {
-                    Ok(sent_packet) => println!("{:?}", sent_packet),
-                    Err(error) => println!("{:?}", error),
+                match (process_stdin(buffer, &opt), opt.view, opt.format) {
+                    (Ok(sent_packet), View::Steps, OutputFormat::Max) => println!("{:?}", sent_packet),
+                    (Ok(output), _, _) => println!("{}", output),
+                    (Err(error), _, _) => eprintln!("{}", error),
                 }
             }
         }
@@ -72,18 +312,133 @@ fn main() -> std::io::Result<()> {
 fn process(receiver: &Receiver, sender: &Sender<Connected>, opt: &Opt) -> Result<OscMessage, BattitoError> {
     let (packet, _) = receiver.recv()?;
     let (input_pattern, osc_address) = parse_osc(packet)?;
-    let pattern = transform(&input_pattern, Some(opt.subdivision))?;
+    let pattern = transform_with_options(&input_pattern, &opt.options()?)?;
     let steps = pattern.format_steps(OutputFormat::Max);
+    if opt.osc_measures {
+        sender
+            .send(to_measures_message(&osc_address, &pattern))
+            .map_err(BattitoError::from)?;
+    }
     let osc_message = to_osc_message(osc_address, pattern, steps);
     let _ = sender.send(osc_message.clone()).map_err(BattitoError::from)?;
 
     Ok(osc_message)
 }
 
+// The output of the command and its exit code
+fn run(command: &Command, opt: &Opt) -> Result<(String, i32), BattitoError> {
+    match command {
+        Command::Midi {
+            output,
+            single_track,
+            velocity,
+            channel,
+            notes,
+            names,
+            patterns,
+        } => {
+            let names: Vec<&str> = names.as_deref().map(|n| n.split(',').collect()).unwrap_or_default();
+            let channel = channel.checked_sub(1).ok_or(BattitoError::InputError)?;
+            let tracks = patterns
+                .iter()
+                .enumerate()
+                .map(|(i, input)| {
+                    let pattern = transform_with_options(input, &opt.command_options()?)?;
+                    let name = names
+                        .get(i)
+                        .map(|n| n.to_string())
+                        .unwrap_or_else(|| format!("{}", i + 1));
+                    Ok(Track {
+                        channel,
+                        ..Track::new(&name, pattern)
+                    })
+                })
+                .collect::<Result<Vec<Track>, BattitoError>>()?;
+            let options = MidiOptions {
+                format: if *single_track {
+                    MidiFormat::Single
+                } else {
+                    MidiFormat::Multi
+                },
+                tempo: Tempo::new(opt.bpm.unwrap_or(DEFAULT_BPM), opt.cycles_per_bar)?,
+                notes: note_map(notes),
+                velocity: *velocity,
+            };
+            std::fs::write(output, midi::write(&tracks, &options)?)?;
+            Ok((
+                format!("Wrote {} tracks to {}", tracks.len(), output.display()),
+                EXIT_SUCCESS,
+            ))
+        }
+        Command::Import { input, grid, notes } => {
+            let options = ImportOptions {
+                grid: *grid,
+                tempo: Tempo::new(DEFAULT_BPM, opt.cycles_per_bar)?,
+                notes: note_map(notes),
+                limits: opt.limits(),
+            };
+            let layers = midi::import(&std::fs::read(input)?, &options)?;
+            let lines: Vec<String> = match &layers[..] {
+                [layer] => vec![layer.expression.clone()],
+                layers => layers.iter().map(|l| format!("{}: {}", l.name, l.expression)).collect(),
+            };
+            Ok((lines.join("\n"), EXIT_SUCCESS))
+        }
+        Command::Fmt { patterns } => {
+            let formatted = patterns
+                .iter()
+                .map(|input| format(input))
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok((formatted.join("\n"), EXIT_SUCCESS))
+        }
+        Command::Simplify { patterns } => {
+            let options = opt.command_options()?;
+            let simplified = patterns
+                .iter()
+                .map(|input| simplify::simplify(input, &options))
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok((simplified.join("\n"), EXIT_SUCCESS))
+        }
+        Command::ToTidal { patterns } => {
+            let converted = patterns
+                .iter()
+                .map(|input| tidal::export(input))
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok((converted.join("\n"), EXIT_SUCCESS))
+        }
+        Command::FromTidal { patterns } => {
+            let converted = patterns
+                .iter()
+                .map(|input| tidal::import(input))
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok((converted.join("\n"), EXIT_SUCCESS))
+        }
+        Command::Equiv {
+            first,
+            second,
+            resolution,
+        } => match equivalence::difference(first, second, &opt.command_options()?, *resolution)? {
+            None => Ok(("Equivalent".to_string(), EXIT_SUCCESS)),
+            Some(difference) => Ok((format!("Different {}", difference), EXIT_DIFFERENT)),
+        },
+        Command::Schema => Ok((format!("{:#}", schema::json_schema()), EXIT_SUCCESS)),
+        Command::Write { output, pattern } => {
+            let bytes = transform_with_options(pattern, &opt.command_options()?)?.encode(opt.format);
+            std::fs::write(output, &bytes)?;
+            Ok((
+                format!("Wrote {} bytes to {}", bytes.len(), output.display()),
+                EXIT_SUCCESS,
+            ))
+        }
+    }
+}
+
 fn process_stdin(input: String, opt: &Opt) -> Result<String, BattitoError> {
-    let pattern = transform(&input, Some(opt.subdivision))?;
-    let steps = pattern.format_steps(OutputFormat::Max);
-    Ok(steps)
+    let pattern = transform_with_options(&input, &opt.options()?)?;
+    match opt.view {
+        View::Steps => Ok(pattern.format_steps(opt.format)),
+        View::Grid => Ok(grid::render(&pattern, &opt.grid_options())),
+    }
 }
 
 fn parse_osc(packet: osc::Packet) -> Result<(String, String), BattitoError> {
@@ -103,13 +458,25 @@ fn parse_osc(packet: osc::Packet) -> Result<(String, String), BattitoError> {
     Ok((input_pattern, osc_address))
 }
 
+// The length is given in whole cycles, a fractional length is rounded up so that every step fits.
+// The exact length is sent by `to_measures_message`
 fn to_osc_message(address: String, pattern: Pattern, steps: String) -> OscMessage {
     OscMessage {
         addr: address,
         args: Some(vec![
-            OscType::Int(pattern.length as i32),
+            OscType::Int(pattern.length.ceil() as i32),
             OscType::Int(pattern.subdivision as i32),
             OscType::String(steps),
         ]),
     }
 }
+
+fn to_measures_message(address: &str, pattern: &Pattern) -> OscMessage {
+    OscMessage {
+        addr: format!("{}/measures", address),
+        args: Some(vec![
+            OscType::String(pattern.length.to_string()),
+            OscType::String(pattern.format_measures()),
+        ]),
+    }
+}
@@ -0,0 +1,129 @@
+// Time and memory of the expansion of large alternates and polymetric measures.
+// Run with `cargo bench -p battito-lib --bench expansion`, it fails when the memory used to build a
+// pattern grows faster than the pattern itself, or when walking the ticks keeps them in memory
+use battito_lib::pattern::transform;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(size: usize) {
+    let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(current, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    // Only the difference counts, a growing Vec is not held twice
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            match new_size >= layout.size() {
+                true => grow(new_size - layout.size()),
+                false => {
+                    CURRENT.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+                }
+            }
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const RUNS: u32 = 10;
+
+// Ticks per cycle, small enough for the baseline to fit in memory
+const SUBDIVISION: u32 = 192;
+
+// Memory of the parse tree and of a few measures at a time, whatever the number of cycles
+const WORKING_SET: usize = 256 * 1024;
+
+// What stays allocated after `f` and the peak above it, in bytes
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize, usize) {
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let result = f();
+    let retained = CURRENT.load(Ordering::Relaxed).saturating_sub(before);
+    let peak = PEAK.load(Ordering::Relaxed).saturating_sub(before);
+    (result, retained, peak - retained)
+}
+
+fn bench(name: &str, input: &str) {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        transform(input, Some(SUBDIVISION)).unwrap();
+    }
+    let elapsed = start.elapsed() / RUNS;
+
+    // Building the pattern only needs its steps and a bounded working set on top of them
+    let (pattern, retained, working) = measure(|| transform(input, Some(SUBDIVISION)).unwrap());
+    // Walking every tick needs one event at a time. The baseline is the same walk kept in memory,
+    // a buffer of length * subdivision events
+    let (sounding, _, walk) = measure(|| pattern.events().filter(|e| e.probability != 0).count());
+    let (_, _, baseline) = measure(|| pattern.events().collect::<Vec<_>>().len());
+
+    println!(
+        "{:<24} {:>10.3?} {:>6} measures {:>8} events {:>8} KiB pattern {:>6} KiB working {:>6} B walk {:>8} KiB baseline",
+        name,
+        elapsed,
+        pattern.measures.len(),
+        sounding,
+        retained / 1024,
+        working / 1024,
+        walk,
+        baseline / 1024
+    );
+    assert!(
+        working <= retained + WORKING_SET,
+        "{}: {} bytes to build a pattern of {} bytes",
+        name,
+        working,
+        retained
+    );
+    assert!(
+        walk < WORKING_SET && walk * 100 < baseline,
+        "{}: {} bytes to walk the ticks, {} bytes to keep them",
+        name,
+        walk,
+        baseline
+    );
+}
+
+fn main() {
+    bench("alternates", "<1,2,3,4,5,6,7> <1,2,3,4,5,6,7,8,9,10,11>");
+    bench(
+        "nested alternates",
+        "<1,2,3,4,5,6,7> [<1,2,3,4,5,6,7,8,9,10,11> <1,2,3,4,5,6,7,8,9,10,11,12,13>]",
+    );
+    bench("polymetric", "{<1,2,3> <4,5,6,7> 8 9 10}%7");
+    bench(
+        "polymetric alternates",
+        "{<1,2,3,4,5> <1,2,3,4,5,6,7> <1,2,3,4,5,6,7,8,9,10,11>}%13",
+    );
+    bench(
+        "arithmetic",
+        "\"<0,1,2,3,4,5,6> <0,1,2>\" + \"<0,12,24,36,48,60,72,84,96,108,120>\"",
+    );
+    bench(
+        "many cycles",
+        "<1,2,3,4,5,6,7,8> <1,2,3,4,5,6,7,8,9> <1,2,3,4,5> <1,2,3,4,5,6,7>",
+    );
+}
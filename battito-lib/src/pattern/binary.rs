@@ -0,0 +1,105 @@
+use crate::pattern::pattern::Pattern;
+use std::collections::HashMap;
+
+// Compact layout for small devices, every number little-endian:
+//
+//   header, 28 bytes
+//     0  [u8; 4]  magic "BATT"
+//     4  u16      layout version
+//     6  u16      reserved, 0
+//     8  u32      subdivision, ticks per cycle
+//     12 u32      length in ticks
+//     16 u32      number of measures
+//     20 u32      number of events
+//     24 u32      number of values
+//   measures, u32 each: index of the first tick
+//   events, 16 bytes each
+//     0  u32      index of the onset tick, from 1
+//     4  u32      duration in ticks
+//     8  u32      position of the value in the table of values
+//     12 u8       probability, [0, 100]
+//     13 u8       velocity, [0, 127]
+//     14 u16      reserved, 0
+//   values, in order of first appearance: u32 length in bytes, then the UTF-8 bytes
+//
+// Everything before the values is aligned to 4 bytes, so measures and events can be read in
+// place. Ratchet ids and times in milliseconds are left out
+pub const MAGIC: [u8; 4] = *b"BATT";
+pub const LAYOUT_VERSION: u16 = 1;
+pub const HEADER_SIZE: usize = 28;
+pub const EVENT_SIZE: usize = 16;
+
+pub fn write(pattern: &Pattern) -> Vec<u8> {
+    let mut values: Vec<&str> = Vec::new();
+    let mut table: HashMap<&str, u32> = HashMap::new();
+    let positions: Vec<u32> = pattern
+        .steps
+        .iter()
+        .map(|te| {
+            *table.entry(&te.event.value).or_insert_with(|| {
+                values.push(&te.event.value);
+                values.len() as u32 - 1
+            })
+        })
+        .collect();
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + 4 * pattern.measures.len() + EVENT_SIZE * positions.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&LAYOUT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+    bytes.extend_from_slice(&pattern.subdivision.to_le_bytes());
+    bytes.extend_from_slice(&pattern.ticks().to_le_bytes());
+    bytes.extend_from_slice(&(pattern.measures.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(pattern.steps.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(values.len() as u32).to_le_bytes());
+    for measure in &pattern.measures {
+        bytes.extend_from_slice(&measure.to_le_bytes());
+    }
+    for (te, position) in pattern.steps.iter().zip(positions) {
+        bytes.extend_from_slice(&te.index.to_le_bytes());
+        bytes.extend_from_slice(&te.duration.to_le_bytes());
+        bytes.extend_from_slice(&position.to_le_bytes());
+        bytes.push(te.event.probability);
+        bytes.push(te.event.velocity);
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+    }
+    for value in values {
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(value.as_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write;
+    use crate::pattern::pattern::{Pattern, TimedEvent};
+    use crate::pattern::rational::Rational;
+
+    #[test]
+    fn layout() {
+        let pattern = Pattern {
+            steps: vec![
+                TimedEvent::new(1, "bd", 100, 2),
+                TimedEvent::new(3, "36", 50, 1).with_velocity(90),
+                TimedEvent::new(4, "bd", 100, 1),
+            ],
+            length: Rational::integer(1),
+            subdivision: 4,
+            measures: vec![1],
+        };
+        #[rustfmt::skip]
+        let expected: Vec<u8> = vec![
+            b'B', b'A', b'T', b'T', 1, 0, 0, 0,
+            4, 0, 0, 0, 4, 0, 0, 0,
+            1, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0,
+            1, 0, 0, 0,
+            1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 100, 100, 0, 0,
+            3, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 50, 90, 0, 0,
+            4, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 100, 100, 0, 0,
+            2, 0, 0, 0, b'b', b'd',
+            2, 0, 0, 0, b'3', b'6',
+        ];
+        assert_eq!(expected, write(&pattern));
+    }
+}
@@ -0,0 +1,192 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::num::NonZeroU32;
+
+use crate::pattern::error::{Error, LimitError};
+use crate::pattern::limits::Limits;
+use crate::pattern::measure::DEFAULT_VELOCITY;
+use crate::pattern::pattern::{Occurrence, Pattern};
+use crate::pattern::rational::Rational;
+use crate::pattern::utils::lcm;
+use crate::pattern::{transform_with_options, Options};
+use gcd::Gcd;
+
+// First event played by only one of two patterns
+#[derive(Debug, PartialEq, Clone)]
+pub struct Difference {
+    // In cycles from the start of the first loop
+    pub onset: Rational,
+    // Event of each pattern starting there, None when it plays nothing different there
+    pub first: Option<Occurrence>,
+    pub second: Option<Occurrence>,
+}
+
+// What is compared of an occurrence: ratchet ids, modifiers and times in milliseconds are left out
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    onset: Rational,
+    value: String,
+    probability: u8,
+    velocity: u8,
+    duration: Rational,
+    ratcheted: bool,
+}
+
+// First difference between the patterns of two expressions, None when they play the same events.
+// See `pattern_difference`
+pub fn difference(
+    first: &str,
+    second: &str,
+    options: &Options,
+    resolution: Option<NonZeroU32>,
+) -> Result<Option<Difference>, Error> {
+    let first = transform_with_options(first, options)?;
+    let second = transform_with_options(second, options)?;
+    pattern_difference(&first, &second, resolution, &options.limits)
+}
+
+// First difference between two looping patterns, compared in cycles so that their subdivisions do
+// not matter. A pattern repeating the other one is the same: both are looped until they end
+// together. With a resolution, onsets and durations are rounded to that many steps per cycle
+pub fn pattern_difference(
+    first: &Pattern,
+    second: &Pattern,
+    resolution: Option<NonZeroU32>,
+    limits: &Limits,
+) -> Result<Option<Difference>, Error> {
+    let span = common_length(first.length, second.length)?;
+    let first = keys(first, span, resolution, limits)?;
+    let second = keys(second, span, resolution, limits)?;
+    let (mut i, mut j) = (0, 0);
+    loop {
+        match (first.get(i), second.get(j)) {
+            (None, None) => return Ok(None),
+            (Some(a), Some(b)) if a.0 == b.0 => {
+                i += 1;
+                j += 1;
+            }
+            (a, b) => {
+                let onset = match (a, b) {
+                    (Some(a), Some(b)) => a.0.onset.min(b.0.onset),
+                    (a, b) => a.or(b).map_or_else(Rational::zero, |k| k.0.onset),
+                };
+                return Ok(Some(Difference {
+                    onset,
+                    first: unmatched(&first[i..], &second[j..], onset),
+                    second: unmatched(&second[j..], &first[i..], onset),
+                }));
+            }
+        }
+    }
+}
+
+// An event starting at `onset` that is played more times by the first side
+fn unmatched(xs: &[(Key, Occurrence)], ys: &[(Key, Occurrence)], onset: Rational) -> Option<Occurrence> {
+    let at = |keys: &[(Key, Occurrence)], key: &Key| keys.iter().filter(|(k, _)| k == key).count();
+    xs.iter()
+        .take_while(|(k, _)| k.onset == onset)
+        .find(|(k, _)| at(xs, k) > at(ys, k))
+        .map(|(_, o)| o.clone())
+}
+
+// Least common multiple of two lengths in cycles
+fn common_length(a: Rational, b: Rational) -> Result<Rational, Error> {
+    let numerator = |r: Rational| u32::try_from(r.numerator()).map_err(|_| Error::LimitError(LimitError::Overflow));
+    let denominator = (a.denominator() as u64).gcd(b.denominator() as u64);
+    let numerator = lcm(numerator(a)?, numerator(b)?)?;
+    Ok(Rational::new(numerator as i64, denominator as i64))
+}
+
+fn keys(
+    pattern: &Pattern,
+    span: Rational,
+    resolution: Option<NonZeroU32>,
+    limits: &Limits,
+) -> Result<Vec<(Key, Occurrence)>, Error> {
+    if pattern.length <= Rational::zero() {
+        return Ok(vec![]);
+    }
+    let loops = usize::try_from(span.checked_div(pattern.length)?.floor())
+        .map_err(|_| Error::LimitError(LimitError::Overflow))?;
+    limits.check_events(pattern.steps.len().saturating_mul(loops))?;
+    let round = |r: Rational| match resolution {
+        Some(steps) => {
+            let steps = Rational::from(steps.get());
+            let rounded = r.checked_mul(steps)?.checked_add(Rational::new(1, 2))?.floor();
+            Rational::integer(rounded).checked_div(steps)
+        }
+        None => Ok(r),
+    };
+    let mut keys = pattern
+        .query(Rational::zero(), span, limits)?
+        .into_iter()
+        .map(|o| {
+            let event = &o.timed_event.event;
+            let key = Key {
+                onset: round(o.onset)?,
+                value: event.value.clone(),
+                probability: event.probability,
+                velocity: event.velocity,
+                duration: round(o.duration)?,
+                ratcheted: o.timed_event.ratchet.is_some(),
+            };
+            Ok((key, o))
+        })
+        .collect::<Result<Vec<(Key, Occurrence)>, Error>>()?;
+    keys.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(keys)
+}
+
+fn describe(occurrence: &Option<Occurrence>) -> String {
+    let occurrence = match occurrence {
+        Some(occurrence) => occurrence,
+        None => return "nothing".to_string(),
+    };
+    let event = &occurrence.timed_event.event;
+    let mut description = format!("{} lasting {}", event.value, occurrence.duration);
+    if event.probability != 100 {
+        description.push_str(&format!(", probability {}", event.probability));
+    }
+    if event.velocity != DEFAULT_VELOCITY {
+        description.push_str(&format!(", velocity {}", event.velocity));
+    }
+    if occurrence.timed_event.ratchet.is_some() {
+        description.push_str(", ratcheted");
+    }
+    description
+}
+
+// "at 1/2: b lasting 1/4 / c lasting 1/4"
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "at {}: {} / {}",
+            self.onset,
+            describe(&self.first),
+            describe(&self.second)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::common_length;
+    use crate::pattern::rational::Rational;
+
+    #[test]
+    fn lengths() {
+        assert_eq!(
+            Ok(Rational::integer(3)),
+            common_length(Rational::integer(1), Rational::integer(3))
+        );
+        assert_eq!(
+            Ok(Rational::integer(3)),
+            common_length(Rational::new(3, 4), Rational::integer(3))
+        );
+        assert_eq!(
+            Ok(Rational::new(3, 2)),
+            common_length(Rational::new(3, 4), Rational::new(1, 2))
+        );
+    }
+}
@@ -4,6 +4,10 @@ use std::num::ParseIntError;
 pub enum Error {
     EventParsingError,
     DSLParsingError(ParsingError),
+    ArithmeticError(ArithmeticError),
+    LimitError(LimitError),
+    MidiError(MidiError),
+    TidalError(TidalError),
     UnexpectedError,
 }
 
@@ -11,6 +15,13 @@ pub enum Error {
 pub enum ParsingError {
     Generic,
     EuclideanError(EuclideanError),
+    ScaleError(ScaleError),
+    RangeError(RangeError),
+    ConditionError(ConditionError),
+    SwingError,
+    RatchetError,
+    LengthError,
+    TempoError,
 }
 
 #[derive(Debug, PartialEq)]
@@ -19,6 +30,64 @@ pub enum EuclideanError {
     RGreaterEqualThanM,
 }
 
+#[derive(Debug, PartialEq)]
+pub enum ScaleError {
+    UnknownScale,
+    InvalidRoot,
+    // A degree gives a note outside of [0, 127]
+    NoteOutOfRange,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArithmeticError {
+    NonNumericValue(String),
+    Overflow,
+}
+
+// A limit of the transformation was exceeded, see `Limits`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LimitError {
+    TooManyMeasures,
+    TooManyEvents,
+    TooDeep,
+    SubdivisionTooLarge,
+    // Cycle lengths or subdivisions that do not fit in the integer types
+    Overflow,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MidiError {
+    // Event value that is neither a note number nor a name of the note map
+    UnmappedValue(String),
+    NoteOutOfRange(String),
+    InvalidChannel(u8),
+    // Not a Standard MIDI File, or a truncated one
+    InvalidFile,
+    // Divisions in SMPTE frames instead of ticks per quarter note
+    UnsupportedDivision,
+    // A delta time or a length larger than a Standard MIDI File can hold
+    TooLong,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TidalError {
+    // Construct without an equivalent on the other side, e.g. "ratchet" or "stack"
+    Unsupported(String),
+    // Mini-notation that could not be parsed
+    InvalidSyntax,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RangeError {
+    ZeroStep,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConditionError {
+    ZeroCycles,
+    InvalidTrig,
+}
+
 impl From<ParseIntError> for Error {
     fn from(_: ParseIntError) -> Self {
         Error::EventParsingError
@@ -0,0 +1,175 @@
+use crate::pattern::pattern::{Pattern, TimedEvent};
+use gcd::Gcd;
+
+// Characters the grid is drawn with
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Charset {
+    Ascii,
+    Unicode,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GridOptions {
+    // Characters of a line, labels included. Larger patterns are zoomed out to fit
+    pub width: usize,
+    pub charset: Charset,
+}
+
+impl Default for GridOptions {
+    fn default() -> Self {
+        GridOptions {
+            width: 80,
+            charset: Charset::Ascii,
+        }
+    }
+}
+
+// Content of a step. When a step covers more events the greatest wins, so an onset is never hidden
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Cell {
+    Empty,
+    // An event started before is still playing
+    Held,
+    // An event starts, with its probability
+    Onset(u8),
+}
+
+impl Charset {
+    fn cell(&self, cell: Cell) -> char {
+        match (self, cell) {
+            (Charset::Ascii, Cell::Empty) => '.',
+            (Charset::Ascii, Cell::Held) => '-',
+            (Charset::Ascii, Cell::Onset(p)) => shade(p, [':', '+', 'o', 'x']),
+            (Charset::Unicode, Cell::Empty) => '·',
+            (Charset::Unicode, Cell::Held) => '─',
+            (Charset::Unicode, Cell::Onset(p)) => shade(p, ['░', '▒', '▓', '█']),
+        }
+    }
+
+    fn separator(&self) -> char {
+        match self {
+            Charset::Ascii => '|',
+            Charset::Unicode => '│',
+        }
+    }
+}
+
+// The last shade is for certain events, the others split the lower probabilities in thirds
+fn shade(probability: u8, shades: [char; 4]) -> char {
+    match probability {
+        p if p >= 100 => shades[3],
+        p if p >= 67 => shades[2],
+        p if p >= 34 => shades[1],
+        _ => shades[0],
+    }
+}
+
+// Step grid of the pattern, one row per value. A column is the longest step every onset, end and
+// measure boundary falls on, or several of them when the pattern is too wide. Numeric values are
+// sorted from the highest, as on a piano roll, other values by first appearance
+pub fn render(pattern: &Pattern, options: &GridOptions) -> String {
+    let rows = rows(pattern);
+    let label_width = rows.iter().map(|(v, _)| v.chars().count()).max().unwrap_or(0);
+    let total = pattern.ticks();
+    let starts: Vec<u32> = pattern.measures.iter().map(|m| m - 1).collect();
+    if rows.is_empty() || total == 0 || starts.is_empty() {
+        return String::new();
+    }
+    let ends: Vec<u32> = starts.iter().skip(1).copied().chain(std::iter::once(total)).collect();
+    let step = starts
+        .iter()
+        .chain(ends.iter())
+        .copied()
+        .chain(pattern.steps.iter().flat_map(|te| vec![te.index - 1, end(te, total)]))
+        .fold(0, |acc: u32, t| acc.gcd(t));
+    let lengths: Vec<u32> = starts.iter().zip(ends.iter()).map(|(s, e)| (e - s) / step).collect();
+    let zoom = zoom(&lengths, options.width.saturating_sub(label_width + 1));
+
+    // First column of every measure, measures being preceded by a separator
+    let mut offsets = Vec::with_capacity(lengths.len());
+    let mut columns = 0;
+    for (i, length) in lengths.iter().enumerate() {
+        columns += (i > 0) as usize;
+        offsets.push(columns);
+        columns += length.div_ceil(zoom) as usize;
+    }
+    let mut separators = vec![false; columns];
+    offsets[1..].iter().for_each(|o| separators[o - 1] = true);
+    let column = |tick: u32| {
+        let m = starts.partition_point(|s| *s <= tick) - 1;
+        offsets[m] + ((tick - starts[m]) / step / zoom) as usize
+    };
+
+    let lines: Vec<String> = rows
+        .iter()
+        .map(|(value, events)| {
+            let mut cells = vec![Cell::Empty; columns];
+            for te in events.iter().filter(|te| te.index - 1 < total) {
+                let first = column(te.index - 1);
+                cells[first] = cells[first].max(Cell::Onset(te.event.probability));
+                let last = column(end(te, total).max(te.index) - 1);
+                for cell in cells[first + 1..=last.max(first)].iter_mut() {
+                    *cell = (*cell).max(Cell::Held);
+                }
+            }
+            let mut line = format!("{:<width$} ", value, width = label_width);
+            for (cell, separator) in cells.iter().zip(separators.iter()) {
+                match separator {
+                    true => line.push(options.charset.separator()),
+                    false => line.push(options.charset.cell(*cell)),
+                }
+            }
+            line
+        })
+        .collect();
+    lines.join("\n")
+}
+
+// Tick where the event stops, within the pattern
+fn end(te: &TimedEvent, total: u32) -> u32 {
+    (te.index - 1).saturating_add(te.duration).min(total)
+}
+
+fn rows(pattern: &Pattern) -> Vec<(String, Vec<&TimedEvent>)> {
+    let mut rows: Vec<(String, Vec<&TimedEvent>)> = Vec::new();
+    for te in &pattern.steps {
+        match rows.iter_mut().find(|(v, _)| *v == te.event.value) {
+            Some((_, events)) => events.push(te),
+            None => rows.push((te.event.value.clone(), vec![te])),
+        }
+    }
+    let numbers: Option<Vec<f64>> = rows.iter().map(|(v, _)| v.parse::<f64>().ok()).collect();
+    if numbers.is_some() {
+        rows.sort_by(|(a, _), (b, _)| b.parse::<f64>().unwrap().total_cmp(&a.parse::<f64>().unwrap()));
+    }
+    rows
+}
+
+// Steps per column, the fewest fitting the measures and their separators in `width` characters.
+// When even a column per measure is too wide, that is the zoom
+fn zoom(lengths: &[u32], width: usize) -> u32 {
+    let columns = |zoom: u32| lengths.iter().map(|l| l.div_ceil(zoom) as usize).sum::<usize>() + lengths.len() - 1;
+    let (mut low, mut high) = (1, lengths.iter().copied().max().unwrap_or(1).max(1));
+    while low < high {
+        let middle = low + (high - low) / 2;
+        match columns(middle) <= width {
+            true => high = middle,
+            false => low = middle + 1,
+        }
+    }
+    low
+}
+
+#[cfg(test)]
+mod tests {
+    use super::zoom;
+
+    #[test]
+    fn zoom_to_width() {
+        assert_eq!(1, zoom(&[16], 16));
+        assert_eq!(2, zoom(&[16], 15));
+        assert_eq!(2, zoom(&[16, 16], 17));
+        assert_eq!(16, zoom(&[16, 16], 2));
+        assert_eq!(16, zoom(&[16, 16], 0));
+    }
+}
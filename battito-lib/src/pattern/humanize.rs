@@ -0,0 +1,114 @@
+use crate::pattern::error::Error;
+use crate::pattern::nudge::{Boundary, Nudge};
+use crate::pattern::pattern::TimedEvent;
+use crate::pattern::utils::Rng;
+
+// Random offsets of at most `ticks` on the onsets and `velocity` on the velocities. The offsets only
+// depend on the seed and on the cycle, so the same pattern always sounds the same way
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Humanize {
+    pub ticks: u32,
+    pub velocity: u8,
+    pub seed: u64,
+}
+
+impl Humanize {
+    pub fn new(ticks: u32, velocity: u8, seed: u64) -> Humanize {
+        Humanize { ticks, velocity, seed }
+    }
+
+    // Uniform value in [-amount, amount]
+    fn jitter(rng: &mut Rng, amount: u32) -> i64 {
+        rng.below(2 * amount as u64 + 1) as i64 - amount as i64
+    }
+}
+
+// Humanizes events one at a time, in order of onset. The events written in humanize(...)[...] use
+// their own humanize instead of `global`
+pub struct Humanizer {
+    global: Option<Humanize>,
+    subdivision: u32,
+    total: u32,
+    boundary: Boundary,
+    cycle: Option<u32>,
+    // One random sequence per seed in every cycle
+    rngs: Vec<(u64, Rng)>,
+}
+
+impl Humanizer {
+    pub fn new(global: Option<Humanize>, subdivision: u32, total: u32, boundary: Boundary) -> Humanizer {
+        Humanizer {
+            global,
+            subdivision,
+            total,
+            boundary,
+            cycle: None,
+            rngs: Vec::new(),
+        }
+    }
+
+    pub fn apply(&mut self, te: &mut TimedEvent) -> Result<(), Error> {
+        let humanize = match te.event.modifiers.humanize.or(self.global) {
+            Some(humanize) => humanize,
+            None => return Ok(()),
+        };
+        let current = (te.index - 1) / self.subdivision;
+        if self.cycle != Some(current) {
+            self.cycle = Some(current);
+            self.rngs.clear();
+        }
+        let position = match self.rngs.iter().position(|(seed, _)| *seed == humanize.seed) {
+            Some(position) => position,
+            None => {
+                let rng = Rng::new(humanize.seed ^ (current as u64).rotate_left(32));
+                self.rngs.push((humanize.seed, rng));
+                self.rngs.len() - 1
+            }
+        };
+        let rng = &mut self.rngs[position].1;
+        let offset = Humanize::jitter(rng, humanize.ticks);
+        let velocity = te.event.velocity as i64 + Humanize::jitter(rng, humanize.velocity as u32);
+        Nudge::Ticks(offset).apply(te, self.subdivision, self.total, self.boundary)?;
+        te.event.velocity = velocity.clamp(0, 127) as u8;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Humanize, Humanizer};
+    use crate::pattern::nudge::Boundary;
+    use crate::pattern::pattern::TimedEvent;
+
+    fn steps() -> Vec<TimedEvent> {
+        (0..8).map(|i| TimedEvent::new(i * 480 + 1, "a", 100, 480)).collect()
+    }
+
+    fn humanized(humanize: Humanize) -> Vec<TimedEvent> {
+        let mut humanizer = Humanizer::new(Some(humanize), 1920, 3840, Boundary::Clamp);
+        let mut steps = steps();
+        steps.iter_mut().for_each(|te| humanizer.apply(te).unwrap());
+        steps
+    }
+
+    #[test]
+    fn bounded() {
+        let small = humanized(Humanize::new(10, 20, 42));
+        small.iter().zip(steps().iter()).for_each(|(h, s)| {
+            assert!((h.index as i64 - s.index as i64).abs() <= 10);
+            assert!((80..=120).contains(&h.event.velocity));
+        });
+        assert_ne!(small, steps());
+
+        assert!(humanized(Humanize::new(u32::MAX, 255, 42))
+            .iter()
+            .all(|te| (1..=3840).contains(&te.index)));
+    }
+
+    #[test]
+    fn deterministic() {
+        let seeded = |seed| humanized(Humanize::new(10, 20, seed));
+        assert_eq!(seeded(1), seeded(1));
+        assert_ne!(seeded(1), seeded(2));
+    }
+}
@@ -0,0 +1,99 @@
+use crate::pattern::error::{Error, LimitError};
+
+// Bounds on the size of a transformation, so that a hostile or mistyped input fails with an error
+// instead of exhausting the memory or overflowing
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Limits {
+    // Measures of the sequence, after the alternates are expanded
+    pub max_measures: usize,
+    // Events of the pattern, and events created by a single expansion
+    pub max_events: usize,
+    // Nesting of groups, alternates, polymetric measures and functions
+    pub max_depth: usize,
+    // Ticks in a cycle
+    pub max_subdivision: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_measures: 10_000,
+            max_events: 1_000_000,
+            max_depth: 64,
+            max_subdivision: 192_000,
+        }
+    }
+}
+
+impl Limits {
+    pub(crate) fn check_measures(&self, measures: usize) -> Result<usize, Error> {
+        match measures > self.max_measures {
+            true => Err(Error::LimitError(LimitError::TooManyMeasures)),
+            false => Ok(measures),
+        }
+    }
+
+    pub(crate) fn check_events(&self, events: usize) -> Result<usize, Error> {
+        match events > self.max_events {
+            true => Err(Error::LimitError(LimitError::TooManyEvents)),
+            false => Ok(events),
+        }
+    }
+
+    pub(crate) fn check_subdivision(&self, subdivision: u32) -> Result<u32, Error> {
+        match subdivision > self.max_subdivision {
+            true => Err(Error::LimitError(LimitError::SubdivisionTooLarge)),
+            false => Ok(subdivision),
+        }
+    }
+
+    // The nesting is checked on the input, before the recursive parsers can exhaust the stack.
+    // "<" and ">" are nudges instead of alternates when they are attached to an event or a number
+    pub(crate) fn check_depth(&self, input: &str) -> Result<(), Error> {
+        let chars: Vec<char> = input.chars().collect();
+        let mut depth: usize = 0;
+        for (i, c) in chars.iter().enumerate() {
+            let previous = if i == 0 { None } else { chars.get(i - 1) };
+            let next = chars.get(i + 1);
+            let opens = match c {
+                '[' | '{' | '(' => true,
+                '<' => previous.is_none_or(|p| !p.is_alphanumeric() && *p != '_' && *p != '.'),
+                _ => false,
+            };
+            let closes = match c {
+                ']' | '}' | ')' => true,
+                '>' => next.is_none_or(|n| !n.is_ascii_digit()),
+                _ => false,
+            };
+            if opens {
+                depth += 1;
+                if depth > self.max_depth {
+                    return Err(Error::LimitError(LimitError::TooDeep));
+                }
+            } else if closes {
+                depth = depth.saturating_sub(1);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Limits;
+    use crate::pattern::error::{Error, LimitError};
+
+    #[test]
+    fn depth() {
+        let limits = Limits {
+            max_depth: 2,
+            ..Limits::default()
+        };
+        assert_eq!(Ok(()), limits.check_depth("[a <b,c>] [[d e] f]"));
+        assert_eq!(Ok(()), limits.check_depth("a<10 b<10 c<10 <d,e>>10"));
+        assert_eq!(
+            Err(Error::LimitError(LimitError::TooDeep)),
+            limits.check_depth("[a [b <c,d>]]")
+        );
+    }
+}
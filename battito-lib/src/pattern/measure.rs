@@ -1,31 +1,106 @@
+use crate::pattern::error::{ArithmeticError, Error, LimitError};
+use crate::pattern::humanize::Humanize;
 use crate::pattern::measure::Measure::Group;
+use crate::pattern::nudge::Nudge;
 use crate::pattern::pattern::TimedEvent;
+use crate::pattern::ratchet::Ratchet;
+use crate::pattern::swing::Swing;
 use crate::pattern::utils::lcm;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
+// Modifiers change where an event is placed on the timeline, they are applied and then discarded
+// when the pattern is generated
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Modifiers {
+    pub swing: Option<Swing>,
+    pub nudge: Option<Nudge>,
+    pub ratchet: Option<Ratchet>,
+    pub tie: Option<Tie>,
+    pub humanize: Option<Humanize>,
+}
+
+// a_ _ b: the "a" is held during the slot of "_"
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Tie {
+    // The event continues on the next "_"
+    Start,
+    // Placeholder extending the previous tied event
+    Continue,
+}
+
+// Value of the "_" placeholder
+pub const CONTINUATION: &str = "_";
+
+pub const DEFAULT_VELOCITY: u8 = 100;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Event {
     pub value: String,
     pub probability: u8, // [0, 100]
+    // [0, 127], left out of the serialized event when it is the default
+    #[serde(default = "default_velocity", skip_serializing_if = "is_default_velocity")]
+    pub velocity: u8,
+    #[serde(skip)]
+    pub modifiers: Modifiers,
+}
+
+fn default_velocity() -> u8 {
+    DEFAULT_VELOCITY
+}
+
+fn is_default_velocity(velocity: &u8) -> bool {
+    *velocity == DEFAULT_VELOCITY
 }
 
 impl Event {
     pub fn advance(&self, index: u32, length: u32) -> u32 {
         let mut i = index;
         for _ in 1..length {
-            i = i + 1;
+            i += 1;
         }
         i
     }
 
+    pub fn is_continuation(&self) -> bool {
+        self.modifiers.tie == Some(Tie::Continue)
+    }
+
     pub fn empty() -> Event {
         Event {
             value: "".into(),
             probability: 0,
+            velocity: DEFAULT_VELOCITY,
+            modifiers: Modifiers::default(),
         }
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+}
+
+impl Operator {
+    pub fn apply(&self, left: &str, right: &str) -> Result<String, Error> {
+        let parse = |v: &str| {
+            v.parse::<i64>()
+                .map_err(|_| Error::ArithmeticError(ArithmeticError::NonNumericValue(v.to_string())))
+        };
+        let (a, b) = (parse(left)?, parse(right)?);
+        let result = match self {
+            Operator::Add => a.checked_add(b),
+            Operator::Subtract => a.checked_sub(b),
+            Operator::Multiply => a.checked_mul(b),
+        };
+        result
+            .map(|r| r.to_string())
+            .ok_or(Error::ArithmeticError(ArithmeticError::Overflow))
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Measure {
     Event(Event),
@@ -33,28 +108,107 @@ pub enum Measure {
 }
 
 impl Measure {
-    pub fn subdivision(&self) -> u32 {
+    // Finest subdivision of the measure, fails when it does not fit in a u32
+    pub fn subdivision(&self) -> Result<u32, Error> {
         match self {
-            Measure::Event(_) => 1,
+            Measure::Event(_) => Ok(1),
             Measure::Group(elements) => Self::recurse_tree(1, 1, elements),
         }
     }
 
-    pub fn timed_events(&self, start: u32, length_multiplier: f32, subdivision: u32) -> Vec<TimedEvent> {
-        let timed_events = match self {
+    // Events of this measure, when it starts at tick `start` and lasts `ticks`
+    pub fn timed_events(&self, start: u32, ticks: u32) -> Vec<TimedEvent> {
+        match self {
             Measure::Event(event) if event.probability != 0 => vec![TimedEvent {
-                index: 1,
+                index: start,
+                duration: ticks,
+                ratchet: None,
+                time: None,
                 event: event.clone(),
             }],
             Measure::Group(empty) if empty.is_empty() => vec![],
             Measure::Group(measures) => {
                 let mut vec: Vec<TimedEvent> = Vec::new();
-                Measure::timed_event(subdivision, 1, &mut vec, start, measures, length_multiplier);
+                Measure::timed_event(ticks, 1, &mut vec, start, measures);
                 vec
             }
             _ => vec![],
-        };
-        timed_events
+        }
+    }
+
+    pub fn map_events(&self, f: &dyn Fn(&Event) -> Event) -> Measure {
+        match self {
+            Measure::Event(event) => Measure::Event(f(event)),
+            Measure::Group(measures) => Measure::Group(measures.iter().map(|m| m.map_events(f)).collect()),
+        }
+    }
+
+    pub fn try_map_events(&self, f: &dyn Fn(&Event) -> Result<Event, Error>) -> Result<Measure, Error> {
+        match self {
+            Measure::Event(event) => f(event).map(Measure::Event),
+            Measure::Group(measures) => measures
+                .iter()
+                .map(|m| m.try_map_events(f))
+                .collect::<Result<Vec<Measure>, Error>>()
+                .map(Measure::Group),
+        }
+    }
+
+    // Combine the values of this measure with the ones of `other`: the structure comes from this
+    // measure, and every event takes the value of the event of `other` sounding at its onset
+    pub fn combine(&self, other: &Measure, operator: Operator) -> Result<Measure, Error> {
+        // Positions are compared in units of the finest subdivision of both measures
+        let length = lcm(self.subdivision()?, other.subdivision()?)?;
+        let mut spans: Vec<(u32, u32, &Event)> = Vec::new();
+        other.spans(0, length, &mut spans);
+        self.combine_rec(0, length, &spans, operator)
+    }
+
+    fn spans<'a>(&'a self, start: u32, length: u32, out: &mut Vec<(u32, u32, &'a Event)>) {
+        match self {
+            Measure::Event(event) if event.probability != 0 && !event.is_continuation() => {
+                out.push((start, start + length, event))
+            }
+            Measure::Event(_) => (),
+            Measure::Group(xs) if xs.is_empty() => (),
+            Measure::Group(xs) => {
+                let step = length / xs.len() as u32;
+                for (i, x) in xs.iter().enumerate() {
+                    x.spans(start + i as u32 * step, step, out);
+                }
+            }
+        }
+    }
+
+    fn combine_rec(
+        &self,
+        start: u32,
+        length: u32,
+        spans: &[(u32, u32, &Event)],
+        operator: Operator,
+    ) -> Result<Measure, Error> {
+        match self {
+            Measure::Event(event) if event.probability != 0 && !event.is_continuation() => {
+                match spans.iter().find(|(s, e, _)| *s <= start && start < *e) {
+                    Some((_, _, right)) => Ok(Measure::Event(Event {
+                        value: operator.apply(&event.value, &right.value)?,
+                        ..event.clone()
+                    })),
+                    None => Ok(self.clone()),
+                }
+            }
+            Measure::Event(_) => Ok(self.clone()),
+            Measure::Group(xs) if xs.is_empty() => Ok(self.clone()),
+            Measure::Group(xs) => {
+                let step = length / xs.len() as u32;
+                let combined: Result<Vec<Measure>, Error> = xs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| x.combine_rec(start + i as u32 * step, step, spans, operator))
+                    .collect();
+                Ok(Measure::Group(combined?))
+            }
+        }
     }
 
     fn timed_event(
@@ -62,15 +216,21 @@ impl Measure {
         acc_value: u32,
         out: &mut Vec<TimedEvent>,
         index: u32,
-        elements: &Vec<Measure>,
-        length_multiplier: f32,
+        elements: &[Measure],
     ) -> u32 {
+        // An empty group is a rest as long as its slot
+        if elements.is_empty() {
+            return index + subdivision / acc_value;
+        }
         let value = acc_value * elements.len() as u32;
         let length = subdivision / value;
         elements.iter().fold(index, |i, e| match e {
             Measure::Event(event) if event.probability != 0 => {
                 let timed_event = TimedEvent {
-                    index: ((i - 1) as f32 * length_multiplier) as u32 + 1,
+                    index: i,
+                    duration: length,
+                    ratchet: None,
+                    time: None,
                     event: event.clone(),
                 };
                 let new_i = event.advance(i, length);
@@ -78,13 +238,16 @@ impl Measure {
                 new_i + 1
             }
             Measure::Event(event) => event.advance(i, length) + 1,
-            Group(xs) => Measure::timed_event(subdivision, value, out, i, xs, length_multiplier),
+            Group(xs) => Measure::timed_event(subdivision, value, out, i, xs),
         })
     }
 
-    fn recurse_tree(acc_value: u32, acc_lcm: u32, elements: &Vec<Measure>) -> u32 {
-        let value = acc_value * elements.len() as u32;
-        elements.iter().fold(acc_lcm, |l, e| match e {
+    fn recurse_tree(acc_value: u32, acc_lcm: u32, elements: &[Measure]) -> Result<u32, Error> {
+        let value = u32::try_from(elements.len())
+            .ok()
+            .and_then(|len| acc_value.checked_mul(len))
+            .ok_or(Error::LimitError(LimitError::Overflow))?;
+        elements.iter().try_fold(acc_lcm, |l, e| match e {
             Measure::Event(_) => lcm(l, value),
             Measure::Group(xs) => Self::recurse_tree(value, l, xs),
         })
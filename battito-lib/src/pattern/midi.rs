@@ -0,0 +1,543 @@
+use std::convert::TryFrom;
+
+use crate::pattern::error::{Error, MidiError};
+use crate::pattern::limits::Limits;
+use crate::pattern::pattern::Pattern;
+use crate::pattern::rational::Rational;
+use crate::pattern::simplify::{self, Slot};
+use crate::pattern::tempo::Tempo;
+use crate::pattern::utils::lcm_vec;
+use crate::pattern::{transform_with_options, Options};
+
+// Ticks per quarter note used when the subdivision gives no whole number of ticks per quarter
+const FALLBACK_DIVISION: u16 = 960;
+
+// Largest delta time or length of a Standard MIDI File
+const MAX_VARIABLE_LENGTH: u32 = 0x0FFF_FFFF;
+
+// Tick and bytes of a track event
+type TrackEvent = (u32, Vec<u8>);
+
+// Type 0 files have a single track holding every note, type 1 files have a track for the tempo
+// followed by one track per layer
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MidiFormat {
+    Single,
+    Multi,
+}
+
+// Where the velocity of the notes comes from
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Velocity {
+    // Velocity of the event, as changed by ratchets and humanize
+    Event,
+    // Probability of the event, scaled to 0..127
+    Probability,
+    Fixed(u8),
+}
+
+// Notes played by the event values: numbers are notes already, names are looked up here
+#[derive(Debug, PartialEq, Clone)]
+pub struct NoteMap(pub Vec<(String, u8)>);
+
+impl Default for NoteMap {
+    // General MIDI drum names
+    fn default() -> Self {
+        let notes = [
+            ("bd", 36),
+            ("rim", 37),
+            ("sn", 38),
+            ("cp", 39),
+            ("hh", 42),
+            ("lt", 45),
+            ("oh", 46),
+            ("mt", 47),
+            ("cr", 49),
+            ("ht", 50),
+            ("rd", 51),
+            ("cb", 56),
+        ];
+        NoteMap(notes.iter().map(|(name, note)| (name.to_string(), *note)).collect())
+    }
+}
+
+impl NoteMap {
+    pub fn note(&self, value: &str) -> Result<u8, Error> {
+        if let Ok(number) = value.parse::<i64>() {
+            return match number {
+                0..=127 => Ok(number as u8),
+                _ => Err(Error::MidiError(MidiError::NoteOutOfRange(value.to_string()))),
+            };
+        }
+        self.0
+            .iter()
+            .find(|(name, _)| name == value)
+            .map(|(_, note)| *note)
+            .ok_or_else(|| Error::MidiError(MidiError::UnmappedValue(value.to_string())))
+    }
+
+    // Name of a note, the first one mapped to it or the number itself
+    pub fn name(&self, note: u8) -> String {
+        self.0
+            .iter()
+            .find(|(_, n)| *n == note)
+            .map(|(name, _)| name.clone())
+            .unwrap_or_else(|| note.to_string())
+    }
+}
+
+// A layer of the file, written as its own track in type 1 files
+#[derive(Debug, PartialEq)]
+pub struct Track {
+    pub name: String,
+    pub pattern: Pattern,
+    // 0..15
+    pub channel: u8,
+}
+
+impl Track {
+    pub fn new(name: &str, pattern: Pattern) -> Track {
+        Track {
+            name: name.to_string(),
+            pattern,
+            channel: 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MidiOptions {
+    pub format: MidiFormat,
+    pub tempo: Tempo,
+    pub notes: NoteMap,
+    pub velocity: Velocity,
+}
+
+impl Default for MidiOptions {
+    fn default() -> Self {
+        MidiOptions {
+            format: MidiFormat::Multi,
+            tempo: Tempo::default(),
+            notes: NoteMap::default(),
+            velocity: Velocity::Event,
+        }
+    }
+}
+
+// Standard MIDI File with one loop of every track. Every event is played, whatever its
+// probability, for as long as its duration
+pub fn write(tracks: &[Track], options: &MidiOptions) -> Result<Vec<u8>, Error> {
+    let beats_per_cycle = options.tempo.beats_per_cycle();
+    // Every track falls on the ticks of the least common multiple of the subdivisions
+    let subdivisions: Vec<u32> = tracks.iter().map(|t| t.pattern.subdivision).collect();
+    let division = lcm_vec(&subdivisions)
+        .ok()
+        .filter(|_| !tracks.is_empty())
+        .map(|subdivision| subdivision as f64 / beats_per_cycle)
+        .filter(|d| d.fract() == 0.0 && *d >= 1.0 && *d <= i16::MAX as f64)
+        .map(|d| d as u16)
+        .unwrap_or(FALLBACK_DIVISION);
+    // Pattern ticks to MIDI ticks, for a given subdivision
+    let scale = |tick: u32, subdivision: u32| -> u32 {
+        (tick as f64 * division as f64 * beats_per_cycle / subdivision as f64).round() as u32
+    };
+
+    let mut layers: Vec<(String, Vec<TrackEvent>)> = Vec::new();
+    for track in tracks {
+        if track.channel > 15 {
+            return Err(Error::MidiError(MidiError::InvalidChannel(track.channel)));
+        }
+        let subdivision = track.pattern.subdivision;
+        let mut events: Vec<TrackEvent> = Vec::new();
+        for te in &track.pattern.steps {
+            let note = options.notes.note(&te.event.value)?;
+            let velocity = match options.velocity {
+                Velocity::Event => te.event.velocity,
+                Velocity::Probability => (te.event.probability as u32 * 127 / 100) as u8,
+                Velocity::Fixed(velocity) => velocity,
+            };
+            // A note on with velocity 0 would be a note off
+            let velocity = velocity.clamp(1, 127);
+            let onset = scale(te.index - 1, subdivision);
+            let end = scale(te.index - 1 + te.duration, subdivision).max(onset.saturating_add(1));
+            events.push((onset, vec![0x90 | track.channel, note, velocity]));
+            events.push((end, vec![0x80 | track.channel, note, 0x40]));
+        }
+        // The loop lasts the whole pattern, even when it ends with a rest
+        events.push((scale(track.pattern.ticks(), subdivision), vec![]));
+        layers.push((track.name.clone(), events));
+    }
+
+    let mut tempo_events: Vec<TrackEvent> = vec![
+        (0, meta(0x58, &[4, 2, 24, 8])?),
+        (0, meta(0x51, &microseconds_per_quarter(options.tempo.bpm))?),
+    ];
+    let chunks: Vec<Vec<u8>> = match options.format {
+        MidiFormat::Single => {
+            let name = layers.first().map(|(name, _)| name.clone()).unwrap_or_default();
+            tempo_events.insert(0, (0, meta(0x03, name.as_bytes())?));
+            tempo_events.extend(layers.into_iter().flat_map(|(_, events)| events));
+            vec![track_chunk(tempo_events)?]
+        }
+        MidiFormat::Multi => std::iter::once(track_chunk(tempo_events))
+            .chain(layers.into_iter().map(|(name, mut events)| {
+                events.insert(0, (0, meta(0x03, name.as_bytes())?));
+                track_chunk(events)
+            }))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let format: u16 = match options.format {
+        MidiFormat::Single => 0,
+        MidiFormat::Multi => 1,
+    };
+    let mut bytes = b"MThd".to_vec();
+    bytes.extend_from_slice(&6u32.to_be_bytes());
+    bytes.extend_from_slice(&format.to_be_bytes());
+    bytes.extend_from_slice(&(chunks.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(&division.to_be_bytes());
+    chunks.iter().for_each(|chunk| bytes.extend_from_slice(chunk));
+    Ok(bytes)
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImportOptions {
+    // Steps of the grid in a cycle, every note is moved to the nearest step
+    pub grid: u32,
+    // Only the length of a cycle in beats is used
+    pub tempo: Tempo,
+    pub notes: NoteMap,
+    pub limits: Limits,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            grid: 16,
+            tempo: Tempo::default(),
+            notes: NoteMap::default(),
+            limits: Limits::default(),
+        }
+    }
+}
+
+// Expression reproducing a voice of an imported file
+#[derive(Debug, PartialEq, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub expression: String,
+}
+
+// Notes of a Standard MIDI File, with their onset in ticks
+#[derive(Debug, PartialEq, Clone)]
+pub struct MidiFile {
+    // Ticks per quarter note
+    pub division: u16,
+    pub name: Option<String>,
+    pub notes: Vec<(u32, u8)>,
+    // Last tick of the longest track
+    pub end: u32,
+}
+
+// Only note ons, track names and the ends of the tracks are kept, the other events are skipped
+pub fn read(bytes: &[u8]) -> Result<MidiFile, Error> {
+    let mut reader = Reader { bytes, position: 0 };
+    let (header, header_length) = reader.chunk()?;
+    if header != *b"MThd" || header_length < 6 {
+        return Err(Error::MidiError(MidiError::InvalidFile));
+    }
+    let header = reader.take(header_length)?;
+    let tracks = u16::from_be_bytes([header[2], header[3]]);
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    // Divisions in SMPTE frames have the high bit set
+    if division & 0x8000 != 0 || division == 0 {
+        return Err(Error::MidiError(MidiError::UnsupportedDivision));
+    }
+
+    let mut file = MidiFile {
+        division,
+        name: None,
+        notes: Vec::new(),
+        end: 0,
+    };
+    for _ in 0..tracks {
+        let (kind, length) = reader.chunk()?;
+        let data = reader.take(length)?;
+        if kind == *b"MTrk" {
+            read_track(data, &mut file)?;
+        }
+    }
+    file.notes.sort_unstable();
+    Ok(file)
+}
+
+fn read_track(data: &[u8], file: &mut MidiFile) -> Result<(), Error> {
+    let mut reader = Reader {
+        bytes: data,
+        position: 0,
+    };
+    let mut tick: u32 = 0;
+    let mut running: Option<u8> = None;
+    while reader.position < data.len() {
+        tick = tick.saturating_add(reader.variable_length()?);
+        let mut status = reader.byte()?;
+        if status < 0x80 {
+            // Running status: the byte read is the first data byte of the previous status
+            status = running.ok_or(Error::MidiError(MidiError::InvalidFile))?;
+            reader.position -= 1;
+        }
+        match status {
+            0xFF => {
+                let kind = reader.byte()?;
+                let length = reader.variable_length()? as usize;
+                let payload = reader.take(length)?;
+                match kind {
+                    0x03 if file.name.is_none() => file.name = Some(String::from_utf8_lossy(payload).into_owned()),
+                    0x2F => break,
+                    _ => (),
+                }
+            }
+            0xF0 | 0xF7 => {
+                let length = reader.variable_length()? as usize;
+                reader.take(length)?;
+            }
+            // System common and real-time messages do not belong in a track
+            0xF1..=0xFE => return Err(Error::MidiError(MidiError::InvalidFile)),
+            _ => {
+                running = Some(status);
+                let data_bytes = match status & 0xF0 {
+                    0xC0 | 0xD0 => 1,
+                    _ => 2,
+                };
+                let message = reader.take(data_bytes)?;
+                if status & 0xF0 == 0x90 && message[1] != 0 {
+                    file.notes.push((tick, message[0]));
+                }
+            }
+        }
+    }
+    file.end = file.end.max(tick);
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(Error::MidiError(MidiError::InvalidFile))?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn chunk(&mut self) -> Result<([u8; 4], usize), Error> {
+        let header = self.take(8)?;
+        let kind = [header[0], header[1], header[2], header[3]];
+        Ok((
+            kind,
+            u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize,
+        ))
+    }
+
+    fn variable_length(&mut self) -> Result<u32, Error> {
+        let mut value: u32 = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::MidiError(MidiError::InvalidFile))
+    }
+}
+
+// Battito expressions playing the notes of the file, quantized to the grid. Notes sounding
+// together are split in one layer per note, otherwise a single layer holds all of them
+pub fn import(bytes: &[u8], options: &ImportOptions) -> Result<Vec<Layer>, Error> {
+    let file = read(bytes)?;
+    let grid = options.grid.max(1) as u64;
+    let cycle = file.division as f64 * options.tempo.beats_per_cycle();
+    let step = |tick: u32| (tick as f64 * grid as f64 / cycle).round() as u64;
+    let last = file.notes.iter().map(|(tick, _)| step(*tick) + 1).max().unwrap_or(0);
+    let cycles = step(file.end).max(last).div_ceil(grid).max(1);
+    let steps = options.limits.check_events(cycles.saturating_mul(grid) as usize)?;
+
+    // Notes moved to the last step of the loop start it again
+    let mut notes: Vec<(usize, u8)> = file
+        .notes
+        .iter()
+        .map(|(tick, note)| (step(*tick) as usize % steps, *note))
+        .collect();
+    notes.sort_unstable();
+    notes.dedup();
+    let together = notes.windows(2).any(|w| w[0].0 == w[1].0);
+    let mut voices: Vec<u8> = notes.iter().map(|(_, note)| *note).collect();
+    voices.sort_unstable();
+    voices.dedup();
+    let layers: Vec<(String, Vec<u8>)> = match together {
+        true => voices.iter().map(|v| (options.notes.name(*v), vec![*v])).collect(),
+        false => vec![(file.name.clone().unwrap_or_default(), voices)],
+    };
+
+    Ok(layers
+        .into_iter()
+        .map(|(name, voice)| {
+            // Drum hits are written without their lengths
+            let mut slots: Vec<Slot> = vec![Slot::Free; steps];
+            let mut expected: Vec<(usize, String)> = notes
+                .iter()
+                .filter(|(_, note)| voice.contains(note))
+                .map(|(s, note)| (*s, options.notes.name(*note)))
+                .collect();
+            expected
+                .iter()
+                .for_each(|(s, value)| slots[*s] = Slot::Onset(value.clone()));
+            expected.sort_unstable();
+            let measures: Vec<Vec<Slot>> = slots.chunks(grid as usize).map(|m| m.to_vec()).collect();
+            // The shortest expression is kept only if it plays the notes of the grid
+            let expression = simplify::measures(&measures);
+            let expression = match onsets(&expression, grid, cycles, &options.limits) {
+                Some(onsets) if onsets == expected => expression,
+                _ => simplify::literal(&measures),
+            };
+            Layer { name, expression }
+        })
+        .collect())
+}
+
+// Steps of the grid where the expression starts an event over `cycles` cycles, with its value.
+// None when an event starts between two steps
+fn onsets(expression: &str, grid: u64, cycles: u64, limits: &Limits) -> Option<Vec<(usize, String)>> {
+    let options = Options {
+        limits: *limits,
+        ..Options::default()
+    };
+    let pattern = transform_with_options(expression, &options).ok()?;
+    let grid = Rational::integer(i64::try_from(grid).ok()?);
+    let end = Rational::integer(i64::try_from(cycles).ok()?);
+    let mut onsets = pattern
+        .query(Rational::zero(), end, limits)
+        .ok()?
+        .into_iter()
+        .filter(|o| o.timed_event.event.probability != 0)
+        .map(|o| {
+            let step = o.onset.checked_mul(grid).ok()?;
+            match step.denominator() {
+                1 => Some((usize::try_from(step.numerator()).ok()?, o.timed_event.event.value)),
+                _ => None,
+            }
+        })
+        .collect::<Option<Vec<(usize, String)>>>()?;
+    onsets.sort_unstable();
+    Some(onsets)
+}
+
+fn microseconds_per_quarter(bpm: f64) -> [u8; 3] {
+    let microseconds = (60_000_000.0 / bpm).round().clamp(1.0, 0xFF_FFFF as f64) as u32;
+    let bytes = microseconds.to_be_bytes();
+    [bytes[1], bytes[2], bytes[3]]
+}
+
+fn meta(kind: u8, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let length = u32::try_from(data.len()).map_err(|_| Error::MidiError(MidiError::TooLong))?;
+    let mut bytes = vec![0xFF, kind];
+    bytes.extend(variable_length(length)?);
+    bytes.extend_from_slice(data);
+    Ok(bytes)
+}
+
+// Events sorted by tick, with note offs before note ons so that repeated notes are not cut. Empty
+// events only move the end of the track
+fn track_chunk(mut events: Vec<TrackEvent>) -> Result<Vec<u8>, Error> {
+    events.sort_by_key(|(tick, data)| (*tick, data.first().is_some_and(|status| status & 0xF0 != 0x80)));
+    let mut data: Vec<u8> = Vec::new();
+    let mut last = 0;
+    for (tick, event) in events.iter().filter(|(_, event)| !event.is_empty()) {
+        data.extend(variable_length(tick - last)?);
+        data.extend_from_slice(event);
+        last = *tick;
+    }
+    let end = events.iter().map(|(tick, _)| *tick).max().unwrap_or(0);
+    data.extend(variable_length(end - last)?);
+    data.extend(meta(0x2F, &[])?);
+    let length = u32::try_from(data.len()).map_err(|_| Error::MidiError(MidiError::TooLong))?;
+
+    let mut chunk = b"MTrk".to_vec();
+    chunk.extend_from_slice(&length.to_be_bytes());
+    chunk.extend(data);
+    Ok(chunk)
+}
+
+// Seven bits per byte, most significant first, the last byte has the high bit clear. At most four
+// bytes, so up to MAX_VARIABLE_LENGTH
+pub(crate) fn variable_length(value: u32) -> Result<Vec<u8>, Error> {
+    if value > MAX_VARIABLE_LENGTH {
+        return Err(Error::MidiError(MidiError::TooLong));
+    }
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        bytes.insert(0, (rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{microseconds_per_quarter, onsets, variable_length, NoteMap};
+    use crate::pattern::error::{Error, MidiError};
+    use crate::pattern::limits::Limits;
+
+    #[test]
+    fn variable_length_quantities() {
+        assert_eq!(Ok(vec![0x00]), variable_length(0));
+        assert_eq!(Ok(vec![0x7F]), variable_length(127));
+        assert_eq!(Ok(vec![0x81, 0x00]), variable_length(128));
+        assert_eq!(Ok(vec![0xFF, 0x7F]), variable_length(16383));
+        assert_eq!(Ok(vec![0x81, 0x80, 0x00]), variable_length(16384));
+        assert_eq!(Ok(vec![0xFF, 0xFF, 0xFF, 0x7F]), variable_length(0x0FFF_FFFF));
+        assert_eq!(Err(Error::MidiError(MidiError::TooLong)), variable_length(0x1000_0000));
+        assert_eq!([0x07, 0xA1, 0x20], microseconds_per_quarter(120.0));
+    }
+
+    #[test]
+    fn grid_onsets() {
+        let onsets = |expression: &str, cycles: u64| onsets(expression, 4, cycles, &Limits::default());
+        let steps = |steps: &[(usize, &str)]| Some(steps.iter().map(|(s, v)| (*s, v.to_string())).collect());
+        assert_eq!(steps(&[(0, "bd"), (3, "sn")]), onsets("bd ~ ~ sn", 1));
+        // The loop is repeated over the cycles
+        assert_eq!(steps(&[(0, "bd"), (2, "sn"), (4, "bd"), (6, "sn")]), onsets("bd sn", 2));
+        // Between two steps
+        assert_eq!(None, onsets("bd*3", 1));
+    }
+
+    #[test]
+    fn notes() {
+        let notes = NoteMap::default();
+        assert_eq!(Ok(36), notes.note("bd"));
+        assert_eq!(Ok(60), notes.note("60"));
+        assert_eq!(
+            Err(Error::MidiError(MidiError::NoteOutOfRange("128".to_string()))),
+            notes.note("128")
+        );
+        assert_eq!(
+            Err(Error::MidiError(MidiError::UnmappedValue("kick".to_string()))),
+            notes.note("kick")
+        );
+        assert_eq!("sn", notes.name(38));
+        assert_eq!("60", notes.name(60));
+    }
+}
@@ -1,21 +1,97 @@
-use self::{error::Error, parser::parse, pattern::Pattern, sequence::Sequence};
+use self::{
+    error::Error, humanize::Humanize, limits::Limits, nudge::Boundary, parser::parse, parser::parse_complete,
+    parser::printer, pattern::Pattern, sequence::Sequence, tempo::Tempo,
+};
 
+pub mod binary;
+pub mod equivalence;
 pub mod error;
+pub mod grid;
+pub mod humanize;
+pub mod limits;
 mod measure;
+pub mod midi;
+pub mod nudge;
 mod parser;
+#[allow(clippy::module_inception)]
 pub mod pattern;
+pub mod ratchet;
+pub mod rational;
+pub mod scale;
+pub mod schema;
 pub mod sequence;
+pub mod simplify;
+pub mod swing;
+pub mod tempo;
+pub mod tidal;
 mod utils;
 
 pub const DEFAULT_SUBDIVISION: u32 = 1920;
 
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub subdivision: Option<u32>,
+    // Policy for events nudged before the start or after the end of the sequence
+    pub boundary: Boundary,
+    pub humanize: Option<Humanize>,
+    // Adds onsets and durations in milliseconds to the events
+    pub tempo: Option<Tempo>,
+    pub limits: Limits,
+    // The whole input has to be a pattern, instead of the pattern it starts with. Its spacing is
+    // normalized first, as in `format`
+    pub complete: bool,
+}
+
 pub fn transform(input: &str, subdivision: Option<u32>) -> Result<Pattern, Error> {
-    let parsed_sequence = parse(input)?;
-    let sequence = Sequence::from_parsed_sequence(&parsed_sequence, subdivision);
-    Ok(sequence.to_pattern())
+    transform_with_options(
+        input,
+        &Options {
+            subdivision,
+            ..Options::default()
+        },
+    )
+}
+
+pub fn transform_with_options(input: &str, options: &Options) -> Result<Pattern, Error> {
+    options.limits.check_depth(input)?;
+    let parsed_sequence = if options.complete {
+        parse_complete(&printer::spacing(input), &options.limits)?
+    } else {
+        parse(input, &options.limits)?
+    };
+    let mut sequence = Sequence::from_parsed_sequence(&parsed_sequence, options.subdivision, &options.limits)?;
+    sequence.boundary = options.boundary;
+    sequence.humanize = options.humanize;
+    sequence.tempo = options.tempo;
+    sequence.to_pattern()
+}
+
+// Canonical source of the input: only the spacing and the separators of the arguments change, the
+// constructs are written as they are
+pub fn format(input: &str) -> Result<String, Error> {
+    let limits = Limits::default();
+    limits.check_depth(input)?;
+    let formatted = printer::arguments(&printer::spacing(input));
+    parse_complete(&formatted, &limits)?;
+    Ok(formatted)
+}
+
+// Source of the input with the expansions applied and without redundant brackets, see
+// `printer::print`
+pub(crate) fn expanded(input: &str, limits: &Limits) -> Result<String, Error> {
+    limits.check_depth(input)?;
+    let parsed_sequence = parse_complete(&printer::spacing(input), limits)?;
+    Ok(printer::print(&printer::normalize(parsed_sequence)))
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum OutputFormat {
     Json,
     Max,
+    // One line per event after a header, without the length and the measures, see `CSV_HEADER`
+    Csv,
+    // Same fields as Json
+    MessagePack,
+    // Little-endian layout for small devices, see `binary`
+    Binary,
 }
@@ -0,0 +1,94 @@
+use crate::pattern::error::{Error, LimitError};
+use crate::pattern::pattern::TimedEvent;
+
+// Largest offset of a nudge, in ticks or in cycles. An onset is a u32 tick, so larger nudges can
+// only end up at the boundary
+pub const MAX_NUDGE: u32 = u32::MAX;
+
+// sn>10, sn<5: offset in ticks, sn@+0.02, sn@-0.01: offset in cycles
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Nudge {
+    Ticks(i64),
+    Cycles(f64),
+}
+
+// What happens to an event nudged outside of the sequence
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Boundary {
+    // The event is kept at the first or last tick of the sequence
+    #[default]
+    Clamp,
+    // The event continues on the other side, as if the sequence was looping
+    Wrap,
+}
+
+impl Nudge {
+    pub fn ticks(&self, subdivision: u32) -> Result<i64, Error> {
+        match self {
+            Nudge::Ticks(t) => Ok(*t),
+            Nudge::Cycles(c) => {
+                let ticks = (c * subdivision as f64).round();
+                match ticks.is_finite() && ticks.abs() < i64::MAX as f64 {
+                    true => Ok(ticks as i64),
+                    false => Err(Error::LimitError(LimitError::Overflow)),
+                }
+            }
+        }
+    }
+
+    // total is the length of the sequence in ticks
+    pub fn apply(
+        &self,
+        timed_event: &mut TimedEvent,
+        subdivision: u32,
+        total: u32,
+        boundary: Boundary,
+    ) -> Result<(), Error> {
+        let onset = ((timed_event.index - 1) as i64)
+            .checked_add(self.ticks(subdivision)?)
+            .ok_or(Error::LimitError(LimitError::Overflow))?;
+        let onset = match boundary {
+            Boundary::Clamp => onset.clamp(0, total as i64 - 1),
+            Boundary::Wrap => onset.rem_euclid(total as i64),
+        } as u32;
+        // Cut at the end of the sequence, a wrapped event does not run into the next loop either
+        timed_event.duration = timed_event.duration.min(total - onset);
+        timed_event.index = onset + 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Boundary, Nudge};
+    use crate::pattern::error::{Error, LimitError};
+    use crate::pattern::pattern::TimedEvent;
+
+    #[test]
+    fn boundaries() {
+        let nudged = |nudge: Nudge, index: u32, boundary: Boundary| {
+            let mut te = TimedEvent::new(index, "a", 100, 480);
+            nudge.apply(&mut te, 1920, 1920, boundary).unwrap();
+            (te.index, te.duration)
+        };
+        assert_eq!((11, 480), nudged(Nudge::Ticks(10), 1, Boundary::Clamp));
+        assert_eq!((1, 480), nudged(Nudge::Ticks(-10), 1, Boundary::Clamp));
+        assert_eq!((1911, 10), nudged(Nudge::Ticks(-10), 1, Boundary::Wrap));
+        assert_eq!((1541, 380), nudged(Nudge::Ticks(100), 1441, Boundary::Clamp));
+        assert_eq!((1920, 1), nudged(Nudge::Ticks(1000), 1441, Boundary::Clamp));
+        assert_eq!((521, 480), nudged(Nudge::Ticks(1000), 1441, Boundary::Wrap));
+        assert_eq!((1821, 100), nudged(Nudge::Ticks(-100), 1, Boundary::Wrap));
+        assert_eq!((40, 480), nudged(Nudge::Cycles(0.02), 2, Boundary::Clamp));
+
+        let overflow = Err(Error::LimitError(LimitError::Overflow));
+        let mut te = TimedEvent::new(2, "a", 100, 480);
+        assert_eq!(
+            overflow,
+            Nudge::Ticks(i64::MAX).apply(&mut te, 1920, 1920, Boundary::Clamp)
+        );
+        assert_eq!(
+            overflow,
+            Nudge::Cycles(1e300).apply(&mut te, 1920, 1920, Boundary::Wrap)
+        );
+    }
+}
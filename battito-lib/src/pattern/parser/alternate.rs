@@ -1,38 +1,47 @@
-use crate::pattern::parser::parser_event;
+use crate::pattern::limits::Limits;
+use crate::pattern::parser::{parser_event, ParserResult};
 use nom::branch::alt;
 use nom::character::complete::char;
 use nom::combinator::map;
 use nom::multi::{separated_list0, separated_list1};
 use nom::sequence::{preceded, terminated};
-use nom::IResult;
 
 use super::parsed_measure::{ParsedMeasure, Single};
 use super::primitives::{Alternate, PrimitiveGroup};
+use super::range;
 
-pub(crate) fn parser_alternate(input: &str) -> IResult<&str, ParsedMeasure> {
+pub(crate) fn parser_alternate<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedMeasure> {
     map(
         preceded(
             char('<'),
-            terminated(separated_list1(char(','), parser_primitive), char('>')),
+            terminated(separated_list1(char(','), |i| parser_primitives(i, limits)), char('>')),
         ),
-        |primitives| ParsedMeasure::Single(Single::Alternate(Alternate(primitives))),
+        |primitives| ParsedMeasure::Single(Single::Alternate(Alternate(primitives.concat()))),
     )(input)
 }
 
-fn parser_primitive_event(input: &str) -> IResult<&str, PrimitiveGroup> {
+fn parser_primitive_event(input: &str) -> ParserResult<'_, PrimitiveGroup> {
     map(parser_event, |pm| PrimitiveGroup::from_parsed_measure(&pm))(input)
 }
 
-fn parser_primitive_group(input: &str) -> IResult<&str, PrimitiveGroup> {
-    preceded(char('['), terminated(parser_group_inner, char(']')))(input)
+fn parser_primitive_group<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, PrimitiveGroup> {
+    preceded(char('['), terminated(|i| parser_group_inner(i, limits), char(']')))(input)
 }
 
-pub(crate) fn parser_group_inner(input: &str) -> IResult<&str, PrimitiveGroup> {
-    map(separated_list0(char(' '), parser_primitive), |x| {
-        PrimitiveGroup::Group(x)
+pub(crate) fn parser_group_inner<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, PrimitiveGroup> {
+    map(separated_list0(char(' '), |i| parser_primitives(i, limits)), |x| {
+        PrimitiveGroup::Group(x.concat())
     })(input)
 }
 
-pub(crate) fn parser_primitive(input: &str) -> IResult<&str, PrimitiveGroup> {
-    alt((parser_primitive_group, parser_primitive_event))(input)
+pub(crate) fn parser_primitive<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, PrimitiveGroup> {
+    alt((|i| parser_primitive_group(i, limits), parser_primitive_event))(input)
+}
+
+// Ranges are spliced in the enclosing alternate or group: <0..2,7> is <0,1,2,7>
+fn parser_primitives<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Vec<PrimitiveGroup>> {
+    alt((
+        map(|i| range::parser(i, limits), |r| r.to_primitive_groups()),
+        map(|i| parser_primitive(i, limits), |p| vec![p]),
+    ))(input)
 }
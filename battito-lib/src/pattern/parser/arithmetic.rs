@@ -0,0 +1,59 @@
+use super::parsed_measure::{Arithmetic, Parsed, ParsedMeasure};
+use super::{parser_group, parser_measure, parser_value, ParserResult};
+use crate::pattern::limits::Limits;
+use crate::pattern::measure::Operator;
+use nom::branch::alt;
+use nom::character::complete::{char, space0};
+use nom::combinator::{map, value};
+use nom::multi::many1;
+use nom::sequence::{delimited, tuple};
+
+// "0 2 4" + "<0,12>", (0 3 7)+5, "0 1"*2
+// Operators are applied from left to right. The left side must be quoted or parenthesized: a bare
+// x*2 is the repetition [x x], to double x write "x"*2 or (x)*2
+pub(crate) fn parser_arithmetic<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Parsed> {
+    map(
+        tuple((
+            |i| parser_pattern_operand(i, limits),
+            many1(tuple((delimited(space0, parser_operator, space0), |i| {
+                parser_operand(i, limits)
+            }))),
+        )),
+        |(first, rest)| {
+            rest.into_iter().fold(first, |left, (operator, right)| {
+                Parsed::Arithmetic(Arithmetic {
+                    left: Box::new(left),
+                    operator,
+                    right: Box::new(right),
+                })
+            })
+        },
+    )(input)
+}
+
+fn parser_operator(input: &str) -> ParserResult<'_, Operator> {
+    alt((
+        value(Operator::Add, char('+')),
+        value(Operator::Subtract, char('-')),
+        value(Operator::Multiply, char('*')),
+    ))(input)
+}
+
+fn parser_pattern_operand<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Parsed> {
+    alt((
+        delimited(char('"'), |i| parser_measure(i, limits), char('"')),
+        map(
+            delimited(char('('), |i| parser_group(i, limits), char(')')),
+            Parsed::ParsedMeasure,
+        ),
+    ))(input)
+}
+
+fn parser_operand<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Parsed> {
+    alt((
+        |i| parser_pattern_operand(i, limits),
+        map(parser_value, |v| {
+            Parsed::ParsedMeasure(ParsedMeasure::Group(vec![ParsedMeasure::event(v)]))
+        }),
+    ))(input)
+}
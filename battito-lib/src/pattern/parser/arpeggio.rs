@@ -0,0 +1,29 @@
+use super::expansion::arpeggio::{ArpMode, Arpeggio};
+use super::{parser_group, ParserResult};
+use crate::pattern::limits::Limits;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, space0};
+use nom::combinator::{map, value};
+use nom::sequence::{delimited, tuple};
+
+// arp(up, 0 4 7)
+pub(crate) fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Arpeggio> {
+    map(
+        tuple((
+            delimited(tag("arp("), parser_mode, tuple((char(','), space0))),
+            |i| parser_group(i, limits),
+            char(')'),
+        )),
+        |(mode, value, _)| Arpeggio { mode, value },
+    )(input)
+}
+
+fn parser_mode(input: &str) -> ParserResult<'_, ArpMode> {
+    alt((
+        value(ArpMode::UpDown, tag("updown")),
+        value(ArpMode::Up, tag("up")),
+        value(ArpMode::Down, tag("down")),
+        value(ArpMode::Random, tag("random")),
+    ))(input)
+}
@@ -0,0 +1,96 @@
+use super::expansion::conditional::{Condition, Conditional, Transformation, Trig};
+use super::parsed_measure::ParsedMeasure;
+use super::{inner_parser_group, parser_event, parser_group, parser_parsed_measure, parser_suffix, ParserResult};
+use crate::pattern::error::{Error, ParsingError};
+use crate::pattern::limits::Limits;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, digit1, space0, space1};
+use nom::combinator::{cut, map, map_res, opt, recognize, value};
+use nom::multi::many0;
+use nom::sequence::{pair, preceded, separated_pair, terminated, tuple};
+
+// every(4, rev, [a b c d]), whenmod(8, 6, fast 2, a b c d)
+pub(crate) fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Conditional> {
+    map(
+        tuple((
+            terminated(parser_condition, parser_comma),
+            cut(terminated(parser_transformation, parser_comma)),
+            cut(terminated(|i| parser_value(i, limits), char(')'))),
+        )),
+        |(condition, transformation, value)| Conditional {
+            condition,
+            transformation,
+            value,
+        },
+    )(input)
+}
+
+fn parser_comma(input: &str) -> ParserResult<'_, char> {
+    terminated(char(','), space0)(input)
+}
+
+fn parser_number(input: &str) -> ParserResult<'_, u32> {
+    map_res(digit1, |d: &str| d.parse())(input)
+}
+
+fn parser_condition(input: &str) -> ParserResult<'_, Condition> {
+    alt((
+        preceded(
+            tag("every("),
+            cut(map_res(parser_number, |n| Condition::Every(n).create())),
+        ),
+        preceded(
+            tag("whenmod("),
+            cut(map_res(
+                separated_pair(parser_number, parser_comma, parser_number),
+                |(a, b)| Condition::WhenMod(a, b).create(),
+            )),
+        ),
+    ))(input)
+}
+
+fn parser_transformation(input: &str) -> ParserResult<'_, Transformation> {
+    alt((
+        value(Transformation::Reverse, tag("rev")),
+        value(Transformation::Palindrome, tag("palin")),
+        map_res(
+            preceded(pair(tag("fast"), space1), digit1),
+            |d: &str| -> Result<Transformation, Error> {
+                match d.parse()? {
+                    0 => Err(Error::DSLParsingError(ParsingError::Generic)),
+                    repetitions => Ok(Transformation::Fast(repetitions)),
+                }
+            },
+        ),
+        map_res(
+            preceded(pair(tag("rot"), space1), recognize(pair(opt(char('-')), digit1))),
+            |d: &str| -> Result<Transformation, Error> { Ok(Transformation::Rotate(d.parse()?)) },
+        ),
+    ))(input)
+}
+
+// Either a single bracketed group or the elements of a group. The elements are parsed once: a
+// group followed by more elements, as in "[a b]*2 c", is kept as the first of them
+fn parser_value<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedMeasure> {
+    match inner_parser_group(input, limits) {
+        Ok((rest, group)) if rest.starts_with(')') => Ok((rest, group)),
+        Ok((rest, group)) => {
+            let (rest, first) = parser_suffix(rest, group, limits)?;
+            let (rest, others) = many0(preceded(char(' '), |i| parser_parsed_measure(i, limits)))(rest)?;
+            let elements = first.into_iter().chain(others.into_iter().flatten()).collect();
+            Ok((rest, ParsedMeasure::Group(elements)))
+        }
+        Err(nom::Err::Error(_)) => parser_group(input, limits),
+        Err(e) => Err(e),
+    }
+}
+
+// sn@1:4, sn?50@3:4
+pub(crate) fn parser_trig(input: &str) -> ParserResult<'_, Trig> {
+    let (input, value) = terminated(parser_event, char('@'))(input)?;
+    cut(map_res(
+        separated_pair(digit1, char(':'), digit1),
+        move |(a, b): (&str, &str)| -> Result<Trig, Error> { Trig::create(value.clone(), a.parse()?, b.parse()?) },
+    ))(input)
+}
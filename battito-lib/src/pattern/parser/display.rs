@@ -19,7 +19,7 @@ pub trait Fmt {
 
 impl Fmt for ParsedEvent {
     fn fmt(&self, level: u8, buf: &mut impl Write) -> std::io::Result<()> {
-        write!(buf, "{}Event: {} - {},\n", inden(level), self.value, self.probability)
+        writeln!(buf, "{}Event: {} - {},", inden(level), self.value, self.probability)
     }
 }
 
@@ -28,11 +28,11 @@ impl Fmt for PrimitiveGroup {
         match self {
             PrimitiveGroup::Single(e) => e.fmt(level, b),
             PrimitiveGroup::Group(g) => {
-                write!(b, "{}PrimitiveGroup: [\n", inden(level))?;
+                writeln!(b, "{}PrimitiveGroup: [", inden(level))?;
                 g.iter()
                     .map(|a| a.fmt(level + 1, b))
                     .collect::<std::io::Result<Vec<()>>>()?;
-                write!(b, "{}],\n", inden(level))
+                writeln!(b, "{}],", inden(level))
             }
         }
     }
@@ -43,11 +43,11 @@ impl Fmt for Single {
         match self {
             Single::Event(e) => e.fmt(level, b),
             Single::Alternate(a) => {
-                write!(b, "{}Alternate: [\n", inden(level))?;
+                writeln!(b, "{}Alternate: [", inden(level))?;
                 a.0.iter()
                     .map(|a| a.fmt(level + 1, b))
                     .collect::<std::io::Result<Vec<()>>>()?;
-                write!(b, "{}],\n", inden(level))
+                writeln!(b, "{}],", inden(level))
             }
         }
     }
@@ -58,11 +58,11 @@ impl Fmt for ParsedMeasure {
         match self {
             ParsedMeasure::Single(s) => s.fmt(level, b),
             ParsedMeasure::Group(g) => {
-                write!(b, "{}Group: [\n", inden(level))?;
+                writeln!(b, "{}Group: [", inden(level))?;
                 g.iter()
                     .map(|a| a.fmt(level + 1, b))
                     .collect::<std::io::Result<Vec<()>>>()?;
-                write!(b, "{}],\n", inden(level))
+                writeln!(b, "{}],", inden(level))
             }
         }
     }
@@ -72,13 +72,19 @@ impl Fmt for Parsed {
     fn fmt(&self, level: u8, b: &mut impl Write) -> std::io::Result<()> {
         match self {
             Parsed::ParsedMeasure(p) => p.fmt(level, b),
+            Parsed::Arithmetic(a) => {
+                writeln!(b, "{}Arithmetic({:?}): [", inden(level), a.operator)?;
+                a.left.fmt(level + 1, b)?;
+                a.right.fmt(level + 1, b)?;
+                writeln!(b, "{}],", inden(level))
+            }
             Parsed::Polymetric(p) => {
-                write!(b, "{}Polymetric({}): [\n", inden(level), p.length)?;
+                writeln!(b, "{}Polymetric({}): [", inden(level), p.length)?;
                 p.elements
                     .iter()
                     .map(|a| a.fmt(level + 1, b))
                     .collect::<std::io::Result<Vec<()>>>()?;
-                write!(b, "{}],\n", inden(level))
+                writeln!(b, "{}],", inden(level))
             }
         }
     }
@@ -98,15 +104,18 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "10".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Group(vec![
                 PrimitiveGroup::Single(ParsedEvent {
                     value: "20".to_string(),
                     probability: 100,
+                    ..Default::default()
                 }),
                 PrimitiveGroup::Single(ParsedEvent {
                     value: "30".to_string(),
                     probability: 100,
+                    ..Default::default()
                 }),
             ]),
         ]);
@@ -121,15 +130,18 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "10".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Group(vec![
                 PrimitiveGroup::Single(ParsedEvent {
                     value: "20".to_string(),
                     probability: 100,
+                    ..Default::default()
                 }),
                 PrimitiveGroup::Single(ParsedEvent {
                     value: "30".to_string(),
                     probability: 100,
+                    ..Default::default()
                 }),
             ]),
         ]);
@@ -138,6 +150,7 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "50".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
         ]));
         let mut w = Vec::new();
@@ -0,0 +1,207 @@
+use crate::pattern::error::Error;
+use crate::pattern::limits::Limits;
+use crate::pattern::measure::Tie;
+use crate::pattern::parser::arpeggio;
+use crate::pattern::parser::parsed_measure::{ParsedMeasure, Single};
+use crate::pattern::parser::primitives::{Alternate, ParsedEvent, PrimitiveGroup};
+use crate::pattern::parser::ParserResult;
+use crate::pattern::utils::{lcm, Rng};
+use gcd::Gcd;
+use std::cmp::Ordering;
+
+use super::Expansion;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ArpMode {
+    Up,
+    Down,
+    UpDown,
+    Random,
+}
+
+// arp(up, 0 4 7): the notes of the group are played in the order given by the mode, keeping the
+// rhythm of the group. When the order is longer than the group (updown, random) it continues on
+// the following cycles.
+pub struct Arpeggio {
+    pub(crate) mode: ArpMode,
+    pub(crate) value: ParsedMeasure,
+}
+
+impl Arpeggio {
+    fn order(&self, notes: &[ParsedEvent], cycle: usize) -> Vec<ParsedEvent> {
+        let mut up = notes.to_vec();
+        up.sort_by(compare_values);
+        match self.mode {
+            ArpMode::Up => up,
+            ArpMode::Down => up.into_iter().rev().collect(),
+            ArpMode::UpDown => {
+                let down: Vec<ParsedEvent> = up
+                    .iter()
+                    .rev()
+                    .skip(1)
+                    .take(up.len().saturating_sub(2))
+                    .cloned()
+                    .collect();
+                up.extend(down);
+                up
+            }
+            ArpMode::Random => {
+                let mut rng = Rng::new(cycle as u64);
+                for i in (1..up.len()).rev() {
+                    up.swap(i, rng.below(i as u64 + 1) as usize);
+                }
+                up
+            }
+        }
+    }
+
+    // Number of cycles after which the order assigned to the notes repeats
+    fn period(&self, notes: usize) -> u32 {
+        match self.mode {
+            ArpMode::Up | ArpMode::Down => 1,
+            ArpMode::UpDown => {
+                let length = (2 * notes).saturating_sub(2).max(1) as u32;
+                length / length.gcd(notes as u32)
+            }
+            ArpMode::Random => notes.max(1) as u32,
+        }
+    }
+}
+
+// Numbers are compared by value and come before names
+fn compare_values(a: &ParsedEvent, b: &ParsedEvent) -> Ordering {
+    match (a.value.parse::<f64>(), b.value.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.value.cmp(&b.value),
+    }
+}
+
+// The "_" of a tie is not a note, it holds the note before it
+fn is_note(e: &ParsedEvent) -> bool {
+    e.probability != 0 && e.modifiers.tie != Some(Tie::Continue)
+}
+
+fn sounding_events(pm: &ParsedMeasure, out: &mut Vec<ParsedEvent>) {
+    match pm {
+        ParsedMeasure::Single(Single::Event(e)) if is_note(e) => out.push(e.clone()),
+        ParsedMeasure::Group(xs) => xs.iter().for_each(|x| sounding_events(x, out)),
+        _ => (),
+    }
+}
+
+// The notes take the slots of the group in order, a slot starting a tie keeps starting it
+fn replace_sounding_events(pm: &ParsedMeasure, events: &mut dyn Iterator<Item = ParsedEvent>) -> ParsedMeasure {
+    match pm {
+        ParsedMeasure::Single(Single::Event(e)) if is_note(e) => {
+            let mut note = events.next().unwrap_or_else(|| e.clone());
+            note.modifiers.tie = e.modifiers.tie;
+            ParsedMeasure::Single(Single::Event(note))
+        }
+        ParsedMeasure::Group(xs) => {
+            ParsedMeasure::Group(xs.iter().map(|x| replace_sounding_events(x, events)).collect())
+        }
+        _ => pm.clone(),
+    }
+}
+
+impl Expansion for Arpeggio {
+    fn expand(&self, limits: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        let variants: Vec<(ParsedMeasure, Vec<ParsedEvent>)> = self
+            .value
+            .variants(limits)?
+            .into_iter()
+            .map(|v| {
+                let mut notes = Vec::new();
+                sounding_events(&v, &mut notes);
+                (v, notes)
+            })
+            .collect();
+        let cycles = variants.iter().try_fold(variants.len() as u32, |acc, (_, notes)| {
+            lcm(acc, self.period(notes.len()))
+        })?;
+        let cycles = limits.check_measures(cycles as usize)?;
+
+        let arpeggiated: Vec<ParsedMeasure> = (0..cycles)
+            .map(|cycle| {
+                let (variant, notes) = &variants[cycle % variants.len()];
+                let order = self.order(notes, cycle);
+                let mut sequence = order.iter().cycle().skip(cycle * notes.len()).cloned();
+                replace_sounding_events(variant, &mut sequence)
+            })
+            .collect();
+
+        match &arpeggiated[..] {
+            [single] => Ok(vec![single.clone()]),
+            _ => Ok(vec![ParsedMeasure::Single(Single::Alternate(Alternate(
+                arpeggiated.iter().map(PrimitiveGroup::from_parsed_measure).collect(),
+            )))]),
+        }
+    }
+
+    fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Self>
+    where
+        Self: Sized,
+    {
+        arpeggio::parser(input, limits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::measure::Modifiers;
+    use crate::pattern::parser::expansion::arpeggio::{ArpMode, Arpeggio};
+    use crate::pattern::parser::parsed_measure::ParsedMeasure;
+    use crate::pattern::parser::primitives::ParsedEvent;
+
+    fn arp(mode: ArpMode) -> Arpeggio {
+        Arpeggio {
+            mode,
+            value: ParsedMeasure::Group(vec![]),
+        }
+    }
+
+    fn order(mode: ArpMode, values: &[&str], cycle: usize) -> Vec<String> {
+        let notes: Vec<ParsedEvent> = values
+            .iter()
+            .map(|v| ParsedEvent {
+                value: v.to_string(),
+                probability: 100,
+                modifiers: Modifiers::default(),
+            })
+            .collect();
+        arp(mode).order(&notes, cycle).into_iter().map(|e| e.value).collect()
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(vec!["0", "4", "7", "12"], order(ArpMode::Up, &["7", "0", "12", "4"], 0));
+        assert_eq!(
+            vec!["12", "7", "4", "0"],
+            order(ArpMode::Down, &["7", "0", "12", "4"], 0)
+        );
+        assert_eq!(vec!["0", "4", "7", "4"], order(ArpMode::UpDown, &["7", "0", "4"], 0));
+        assert_eq!(
+            vec!["-3", "2", "10", "b", "s"],
+            order(ArpMode::Up, &["s", "10", "b", "-3", "2"], 0)
+        );
+    }
+
+    #[test]
+    fn random() {
+        let values = ["0", "1", "2", "3", "4", "5"];
+        let mut shuffled = order(ArpMode::Random, &values, 3);
+        assert_eq!(shuffled, order(ArpMode::Random, &values, 3));
+        shuffled.sort();
+        assert_eq!(values.to_vec(), shuffled);
+    }
+
+    #[test]
+    fn period() {
+        assert_eq!(1, arp(ArpMode::Up).period(3));
+        assert_eq!(4, arp(ArpMode::UpDown).period(3));
+        assert_eq!(3, arp(ArpMode::UpDown).period(4));
+        assert_eq!(1, arp(ArpMode::UpDown).period(2));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::pattern::error::{ConditionError, Error, ParsingError};
+use crate::pattern::limits::Limits;
+use crate::pattern::parser::conditional;
+use crate::pattern::parser::parsed_measure::{ParsedMeasure, Single};
+use crate::pattern::parser::primitives::{Alternate, ParsedEvent, PrimitiveGroup};
+use crate::pattern::parser::ParserResult;
+use crate::pattern::utils::lcm;
+
+use super::palindrome::Palindrome;
+use super::repeated::Repeated;
+use super::reversed::Reversed;
+use super::rotated::Rotated;
+use super::Expansion;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Transformation {
+    Reverse,
+    Palindrome,
+    Fast(usize),
+    Rotate(i64),
+}
+
+impl Transformation {
+    fn apply(&self, value: &ParsedMeasure, limits: &Limits) -> Result<ParsedMeasure, Error> {
+        let value = value.clone();
+        let expanded = match self {
+            Transformation::Reverse => Reversed { value }.expand(limits),
+            Transformation::Palindrome => Palindrome { value }.expand(limits),
+            Transformation::Fast(repetitions) => Repeated {
+                value,
+                repetitions: *repetitions,
+            }
+            .expand(limits),
+            Transformation::Rotate(amount) => Rotated { value, amount: *amount }.expand(limits),
+        }?;
+        expanded.into_iter().next().ok_or(Error::UnexpectedError)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Condition {
+    // every(4, ...): on the first of every n cycles
+    Every(u32),
+    // whenmod(8, 6, ...): when the cycle modulo a is at least b
+    WhenMod(u32, u32),
+}
+
+impl Condition {
+    pub fn create(self) -> Result<Condition, Error> {
+        match self {
+            Condition::Every(0) | Condition::WhenMod(0, _) => Err(Error::DSLParsingError(
+                ParsingError::ConditionError(ConditionError::ZeroCycles),
+            )),
+            _ => Ok(self),
+        }
+    }
+
+    fn period(&self) -> u32 {
+        match self {
+            Condition::Every(n) => *n,
+            Condition::WhenMod(a, _) => *a,
+        }
+    }
+
+    fn holds(&self, cycle: u32) -> bool {
+        match self {
+            Condition::Every(n) => cycle.is_multiple_of(*n),
+            Condition::WhenMod(a, b) => cycle % a >= *b,
+        }
+    }
+}
+
+// every(4, rev, [a b c d]) -> <[d c b a],[a b c d],[a b c d],[a b c d]>
+pub struct Conditional {
+    pub(crate) condition: Condition,
+    pub(crate) transformation: Transformation,
+    pub(crate) value: ParsedMeasure,
+}
+
+impl Expansion for Conditional {
+    fn expand(&self, limits: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        let variants = self.value.variants(limits)?;
+        let period = limits.check_measures(lcm(self.condition.period(), variants.len() as u32)? as usize)?;
+        // The transformation can introduce alternates too (palin), so every cycle is resolved
+        // again after being transformed
+        let cycle_values: Vec<Vec<ParsedMeasure>> = (0..period as u32)
+            .map(|cycle| {
+                let variant = &variants[cycle as usize % variants.len()];
+                match self.condition.holds(cycle) {
+                    true => self.transformation.apply(variant, limits)?.variants(limits),
+                    false => Ok(vec![variant.clone()]),
+                }
+            })
+            .collect::<Result<Vec<Vec<ParsedMeasure>>, Error>>()?;
+        let cycles = cycle_values
+            .iter()
+            .try_fold(cycle_values.len() as u32, |acc, v| lcm(acc, v.len() as u32))?;
+        let cycles = limits.check_measures(cycles as usize)?;
+
+        let alternates: Vec<PrimitiveGroup> = (0..cycles)
+            .map(|cycle| {
+                let values = &cycle_values[cycle % cycle_values.len()];
+                PrimitiveGroup::from_parsed_measure(&values[cycle % values.len()])
+            })
+            .collect();
+        Ok(vec![ParsedMeasure::Single(Single::Alternate(Alternate(alternates)))])
+    }
+
+    fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Self>
+    where
+        Self: Sized,
+    {
+        conditional::parser(input, limits)
+    }
+}
+
+// sn@1:4 -> <sn,~,~,~>: the event is played on the a-th of every b cycles
+pub struct Trig {
+    pub(crate) value: ParsedMeasure,
+    pub(crate) a: u32,
+    pub(crate) b: u32,
+}
+
+impl Trig {
+    pub fn create(value: ParsedMeasure, a: u32, b: u32) -> Result<Trig, Error> {
+        if a == 0 || a > b {
+            Err(Error::DSLParsingError(ParsingError::ConditionError(
+                ConditionError::InvalidTrig,
+            )))
+        } else {
+            Ok(Trig { value, a, b })
+        }
+    }
+}
+
+impl Expansion for Trig {
+    fn expand(&self, limits: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        // One alternate for each of the b cycles
+        limits.check_measures(self.b as usize)?;
+        let alternates: Vec<PrimitiveGroup> = (1..=self.b)
+            .map(|cycle| match cycle == self.a {
+                true => PrimitiveGroup::from_parsed_measure(&self.value),
+                false => PrimitiveGroup::Single(ParsedEvent::empty()),
+            })
+            .collect();
+        Ok(vec![ParsedMeasure::Single(Single::Alternate(Alternate(alternates)))])
+    }
+
+    fn parser<'a>(input: &'a str, _: &Limits) -> ParserResult<'a, Self>
+    where
+        Self: Sized,
+    {
+        conditional::parser_trig(input)
+    }
+}
@@ -1,11 +1,12 @@
 use crate::pattern::error::EuclideanError::{NGreaterThanM, RGreaterEqualThanM};
 use crate::pattern::error::{Error, ParsingError};
 // use crate::pattern::parser::expansion::Expansion;
+use crate::pattern::limits::Limits;
 use crate::pattern::parser::euclidean;
 use crate::pattern::parser::parsed_measure::{ParsedMeasure, Single};
 use crate::pattern::parser::primitives::{Alternate, ParsedEvent, PrimitiveGroup};
+use crate::pattern::parser::ParserResult;
 use crate::pattern::utils::lcm_vec;
-use nom::IResult;
 use std::collections::VecDeque;
 
 use super::Expansion;
@@ -59,9 +60,14 @@ pub struct Euclidean {
 
 impl Euclidean {
     // b(<1,2,4>,<4,8>,<0,1>) -> <b(1,4,0),b(2,8,1),b(4,4,0),b(1,8,1),b(2,4,0),b(4,8,1)> -> <[b ~ ~ ~], [b
-    pub fn to_single_pattern(&self) -> Result<Single, Error> {
-        let alternates: Result<Vec<PrimitiveGroup>, Error> =
-            self.expand_alternate().iter().map(|e| e.to_primitive_group()).collect();
+    pub fn to_single_pattern(&self, limits: &Limits) -> Result<Single, Error> {
+        // Every rhythm has as many steps as its largest count
+        limits.check_events(*self.m.max_value()? as usize)?;
+        let alternates: Result<Vec<PrimitiveGroup>, Error> = self
+            .expand_alternate(limits)?
+            .iter()
+            .map(|e| e.to_primitive_group())
+            .collect();
         Ok(Single::Alternate(Alternate(alternates?)))
     }
 
@@ -80,7 +86,7 @@ impl Euclidean {
             counts.push(divisor / remainders[level as usize]);
             remainders.push(divisor % remainders[level as usize]);
             divisor = remainders[level as usize];
-            level = level + 1;
+            level += 1;
             if remainders[level as usize] <= 1 {
                 break;
             }
@@ -93,7 +99,7 @@ impl Euclidean {
             counts: &Vec<u32>,
             pattern: &mut VecDeque<PrimitiveGroup>,
             remainders: &Vec<u32>,
-        ) -> () {
+        ) {
             match level {
                 -1 => pattern.push_back(PrimitiveGroup::Single(ParsedEvent::empty())),
                 -2 => pattern.push_back(euclidean.value.clone()),
@@ -123,24 +129,42 @@ impl Euclidean {
         Ok(PrimitiveGroup::Group(Vec::from(pattern)))
     }
 
+    // Steps of x(pulses,steps,rotation) holding an event
+    pub(crate) fn hits(pulses: u32, steps: u32, rotation: u32) -> Result<Vec<bool>, Error> {
+        let euclidean = Euclidean::create(
+            PrimitiveGroup::Single(ParsedEvent {
+                probability: 100,
+                ..ParsedEvent::empty()
+            }),
+            EuclideanPrimitive::Single(pulses),
+            EuclideanPrimitive::Single(steps),
+            Some(EuclideanPrimitive::Single(rotation)),
+        )?;
+        match euclidean.to_primitive_group()? {
+            PrimitiveGroup::Group(steps) => Ok(steps
+                .iter()
+                .map(|step| matches!(step, PrimitiveGroup::Single(e) if e.probability != 0))
+                .collect()),
+            _ => Err(Error::UnexpectedError),
+        }
+    }
+
     fn count_replications(&self) -> [u32; 3] {
         [self.n.replications(), self.m.replications(), self.r.replications()]
     }
 
-    fn expand_alternate(&self) -> Vec<Euclidean> {
-        let n = lcm_vec(&self.count_replications());
-        let mut replicated: Vec<Euclidean> = vec![self.clone(); n as usize];
-        let mut i: usize = 0;
-        for e in &mut replicated {
+    fn expand_alternate(&self, limits: &Limits) -> Result<Vec<Euclidean>, Error> {
+        let n = limits.check_measures(lcm_vec(&self.count_replications())? as usize)?;
+        let mut replicated: Vec<Euclidean> = vec![self.clone(); n];
+        for (i, e) in replicated.iter_mut().enumerate() {
             *e = Euclidean {
                 value: e.clone().value,
                 n: EuclideanPrimitive::Single(e.n.next(i)),
                 m: EuclideanPrimitive::Single(e.m.next(i)),
                 r: EuclideanPrimitive::Single(e.r.next(i)),
             };
-            i = i + 1;
         }
-        replicated
+        Ok(replicated)
     }
 
     pub fn create(
@@ -166,18 +190,19 @@ impl Euclidean {
 }
 
 impl Expansion for Euclidean {
-    fn expand(&self) -> Result<Vec<ParsedMeasure>, Error> {
-        let single = self.to_single_pattern()?;
+    fn expand(&self, limits: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        let single = self.to_single_pattern(limits)?;
         Ok(vec![ParsedMeasure::Single(single)])
     }
 
-    fn parser(input: &str) -> IResult<&str, Self> {
-        euclidean::parser_euclidean(input)
+    fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Self> {
+        euclidean::parser_euclidean(input, limits)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::pattern::limits::Limits;
     use crate::pattern::parser::{
         expansion::euclidean::{Euclidean, EuclideanPrimitive},
         primitives::{ParsedEvent, PrimitiveGroup},
@@ -189,17 +214,19 @@ mod tests {
             value: PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             n: EuclideanPrimitive::Single(3),
             m: EuclideanPrimitive::Alternate(vec![4, 8]),
             r: EuclideanPrimitive::Alternate(vec![0, 1, 2]),
         };
-        let out = e.expand_alternate();
+        let out = e.expand_alternate(&Limits::default()).unwrap();
         let expected = vec![
             Euclidean {
                 value: PrimitiveGroup::Single(ParsedEvent {
                     value: "x".to_string(),
                     probability: 100,
+                    ..Default::default()
                 }),
                 n: EuclideanPrimitive::Single(3),
                 m: EuclideanPrimitive::Single(4),
@@ -209,6 +236,7 @@ mod tests {
                 value: PrimitiveGroup::Single(ParsedEvent {
                     value: "x".to_string(),
                     probability: 100,
+                    ..Default::default()
                 }),
                 n: EuclideanPrimitive::Single(3),
                 m: EuclideanPrimitive::Single(8),
@@ -218,6 +246,7 @@ mod tests {
                 value: PrimitiveGroup::Single(ParsedEvent {
                     value: "x".to_string(),
                     probability: 100,
+                    ..Default::default()
                 }),
                 n: EuclideanPrimitive::Single(3),
                 m: EuclideanPrimitive::Single(4),
@@ -227,6 +256,7 @@ mod tests {
                 value: PrimitiveGroup::Single(ParsedEvent {
                     value: "x".to_string(),
                     probability: 100,
+                    ..Default::default()
                 }),
                 n: EuclideanPrimitive::Single(3),
                 m: EuclideanPrimitive::Single(8),
@@ -236,6 +266,7 @@ mod tests {
                 value: PrimitiveGroup::Single(ParsedEvent {
                     value: "x".to_string(),
                     probability: 100,
+                    ..Default::default()
                 }),
                 n: EuclideanPrimitive::Single(3),
                 m: EuclideanPrimitive::Single(4),
@@ -245,6 +276,7 @@ mod tests {
                 value: PrimitiveGroup::Single(ParsedEvent {
                     value: "x".to_string(),
                     probability: 100,
+                    ..Default::default()
                 }),
                 n: EuclideanPrimitive::Single(3),
                 m: EuclideanPrimitive::Single(8),
@@ -260,6 +292,7 @@ mod tests {
             value: PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             n: EuclideanPrimitive::Single(3),
             m: EuclideanPrimitive::Single(8),
@@ -269,34 +302,42 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
         ]));
         assert_eq!(expected, e.to_primitive_group());
@@ -305,6 +346,7 @@ mod tests {
             value: PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             n: EuclideanPrimitive::Single(2),
             m: EuclideanPrimitive::Single(4),
@@ -314,18 +356,22 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
         ]));
         assert_eq!(expected, e.to_primitive_group());
@@ -334,6 +380,7 @@ mod tests {
             value: PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             n: EuclideanPrimitive::Single(4),
             m: EuclideanPrimitive::Single(4),
@@ -343,18 +390,22 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
         ]));
         assert_eq!(expected, e.to_primitive_group());
@@ -363,6 +414,7 @@ mod tests {
             value: PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             n: EuclideanPrimitive::Single(7),
             m: EuclideanPrimitive::Single(8),
@@ -372,34 +424,42 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
         ]));
         assert_eq!(expected, e.to_primitive_group());
@@ -412,15 +472,18 @@ mod tests {
                 PrimitiveGroup::Single(ParsedEvent {
                     value: "a".to_string(),
                     probability: 114,
+                    ..Default::default()
                 }),
                 PrimitiveGroup::Single(ParsedEvent {
                     value: "ll".to_string(),
                     probability: 63,
+                    ..Default::default()
                 }),
             ]),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "b".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
         ]);
         let e = Euclidean {
@@ -434,11 +497,13 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             value.clone(),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
         ]));
         assert_eq!(expected, e.to_primitive_group());
@@ -450,6 +515,7 @@ mod tests {
             value: PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             n: EuclideanPrimitive::Single(3),
             m: EuclideanPrimitive::Single(8),
@@ -459,34 +525,42 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
         ]));
         assert_eq!(expected, e.to_primitive_group());
@@ -495,6 +569,7 @@ mod tests {
             value: PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             n: EuclideanPrimitive::Single(2),
             m: EuclideanPrimitive::Single(4),
@@ -504,18 +579,22 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "0".to_string(),
                 probability: 0,
+                ..Default::default()
             }),
         ]));
         assert_eq!(expected, e.to_primitive_group());
@@ -524,6 +603,7 @@ mod tests {
             value: PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             n: EuclideanPrimitive::Single(4),
             m: EuclideanPrimitive::Single(4),
@@ -533,18 +613,22 @@ mod tests {
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
             PrimitiveGroup::Single(ParsedEvent {
                 value: "x".to_string(),
                 probability: 100,
+                ..Default::default()
             }),
         ]));
         assert_eq!(expected, e.to_primitive_group());
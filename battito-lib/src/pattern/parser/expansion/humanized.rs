@@ -0,0 +1,32 @@
+use crate::pattern::error::Error;
+use crate::pattern::humanize::Humanize;
+use crate::pattern::limits::Limits;
+use crate::pattern::parser::humanized;
+use crate::pattern::parser::parsed_measure::ParsedMeasure;
+use crate::pattern::parser::ParserResult;
+
+use super::Expansion;
+
+// humanize(10, 20)[a b c d]: the events of the group get random offsets when the pattern is
+// generated, see `Humanize`. A humanize set on an inner group takes precedence
+pub struct Humanized {
+    pub(crate) value: ParsedMeasure,
+    pub(crate) humanize: Humanize,
+}
+
+impl Expansion for Humanized {
+    fn expand(&self, _: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        Ok(vec![self.value.map_events(&|event| {
+            let mut event = event.clone();
+            event.modifiers.humanize = event.modifiers.humanize.or(Some(self.humanize));
+            event
+        })])
+    }
+
+    fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Self>
+    where
+        Self: Sized,
+    {
+        humanized::parser(input, limits)
+    }
+}
@@ -1,24 +1,32 @@
 use crate::pattern::error::Error;
-use nom::combinator::map_res;
-use nom::IResult;
+use crate::pattern::limits::Limits;
 
 use super::parsed_measure::ParsedMeasure;
+use super::{map_limited, ParserResult};
 
+pub mod arpeggio;
+pub mod conditional;
 pub mod euclidean;
+pub mod humanized;
+pub mod palindrome;
+pub mod range;
 pub mod repeated;
 pub mod replicated;
+pub mod reversed;
+pub mod rotated;
+pub mod swung;
 
 pub trait Expansion {
-    fn expand(&self) -> Result<Vec<ParsedMeasure>, Error>;
+    fn expand(&self, limits: &Limits) -> Result<Vec<ParsedMeasure>, Error>;
 
-    fn parser(input: &str) -> IResult<&str, Self>
+    fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Self>
     where
         Self: Sized;
 
-    fn parse(input: &str) -> IResult<&str, Vec<ParsedMeasure>>
+    fn parse<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Vec<ParsedMeasure>>
     where
         Self: Sized,
     {
-        map_res(Self::parser, |t| t.expand())(input)
+        map_limited(|i| Self::parser(i, limits), |t| t.expand(limits))(input)
     }
 }
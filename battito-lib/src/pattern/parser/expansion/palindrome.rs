@@ -0,0 +1,40 @@
+use crate::pattern::error::Error;
+use crate::pattern::limits::Limits;
+use crate::pattern::parser::palindrome;
+use crate::pattern::parser::parsed_measure::{ParsedMeasure, Single};
+use crate::pattern::parser::primitives::{Alternate, PrimitiveGroup};
+use crate::pattern::parser::ParserResult;
+use crate::pattern::utils::lcm;
+
+use super::reversed::reverse;
+use super::Expansion;
+
+// palin[a b c] -> <[a b c],[c b a]>: the group is played forwards and backwards on alternate cycles
+pub struct Palindrome {
+    pub(crate) value: ParsedMeasure,
+}
+
+impl Expansion for Palindrome {
+    fn expand(&self, limits: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        // Alternates inside the group are resolved for every cycle before reversing
+        let variants = self.value.variants(limits)?;
+        let cycles = limits.check_measures(lcm(variants.len() as u32, 2)? as usize)?;
+        let alternates: Vec<PrimitiveGroup> = (0..cycles)
+            .map(|cycle| {
+                let variant = &variants[cycle % variants.len()];
+                match cycle % 2 {
+                    0 => PrimitiveGroup::from_parsed_measure(variant),
+                    _ => PrimitiveGroup::from_parsed_measure(&reverse(variant)),
+                }
+            })
+            .collect();
+        Ok(vec![ParsedMeasure::Single(Single::Alternate(Alternate(alternates)))])
+    }
+
+    fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Self>
+    where
+        Self: Sized,
+    {
+        palindrome::parser(input, limits)
+    }
+}
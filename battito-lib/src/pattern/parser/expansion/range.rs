@@ -0,0 +1,86 @@
+use crate::pattern::error::{Error, ParsingError, RangeError};
+use crate::pattern::limits::Limits;
+use crate::pattern::parser::parsed_measure::ParsedMeasure;
+use crate::pattern::parser::primitives::PrimitiveGroup;
+use crate::pattern::parser::range;
+use crate::pattern::parser::ParserResult;
+
+use super::Expansion;
+
+// 0..12:3 -> 0 3 6 9 12, descending when start > end
+#[derive(Debug, PartialEq, Clone)]
+pub struct Range {
+    start: i64,
+    end: i64,
+    step: u32,
+}
+
+impl Range {
+    pub fn create(start: i64, end: i64, step: Option<u32>, limits: &Limits) -> Result<Self, Error> {
+        match step.unwrap_or(1) {
+            0 => Err(Error::DSLParsingError(ParsingError::RangeError(RangeError::ZeroStep))),
+            step => {
+                let values = (end as i128 - start as i128).unsigned_abs() / step as u128 + 1;
+                limits.check_events(values.min(usize::MAX as u128) as usize)?;
+                Ok(Range { start, end, step })
+            }
+        }
+    }
+
+    fn values(&self) -> Vec<i64> {
+        let step = self.step as usize;
+        if self.start <= self.end {
+            (self.start..=self.end).step_by(step).collect()
+        } else {
+            (self.end..=self.start).rev().step_by(step).collect()
+        }
+    }
+
+    pub fn to_primitive_groups(&self) -> Vec<PrimitiveGroup> {
+        self.values()
+            .iter()
+            .map(|v| PrimitiveGroup::from_parsed_measure(&ParsedMeasure::event(&v.to_string())))
+            .collect()
+    }
+}
+
+impl Expansion for Range {
+    fn expand(&self, _: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        Ok(self
+            .values()
+            .iter()
+            .map(|v| ParsedMeasure::event(&v.to_string()))
+            .collect())
+    }
+
+    fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Self>
+    where
+        Self: Sized,
+    {
+        range::parser(input, limits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::limits::Limits;
+    use crate::pattern::parser::expansion::range::Range;
+
+    #[test]
+    fn values() {
+        let limits = Limits::default();
+        assert_eq!(vec![0, 1, 2, 3], Range::create(0, 3, None, &limits).unwrap().values());
+        assert_eq!(
+            vec![3, 2, 1, 0, -1],
+            Range::create(3, -1, None, &limits).unwrap().values()
+        );
+        assert_eq!(
+            vec![0, 3, 6, 9, 12],
+            Range::create(0, 12, Some(3), &limits).unwrap().values()
+        );
+        assert_eq!(vec![0, 4, 8], Range::create(0, 10, Some(4), &limits).unwrap().values());
+        assert_eq!(vec![10, 6, 2], Range::create(10, 0, Some(4), &limits).unwrap().values());
+        assert_eq!(vec![5], Range::create(5, 5, None, &limits).unwrap().values());
+        assert!(Range::create(0, 4, Some(0), &limits).is_err());
+    }
+}
@@ -1,24 +1,17 @@
 use crate::pattern::error::Error;
+use crate::pattern::limits::Limits;
 use crate::pattern::parser::parsed_measure::ParsedMeasure;
-use crate::pattern::parser::repeated;
-use nom::IResult;
-
-use super::Expansion;
 
+// a*2 -> [a a], parsed as a suffix of the element, see `parser_repeated`
 pub struct Repeated {
     pub(crate) value: ParsedMeasure,
     pub(crate) repetitions: usize,
 }
 
-impl Expansion for Repeated {
-    fn expand(&self) -> Result<Vec<ParsedMeasure>, Error> {
+impl Repeated {
+    pub fn expand(&self, limits: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        let events = self.value.events_count().saturating_mul(self.repetitions);
+        limits.check_events(events)?;
         Ok(vec![ParsedMeasure::Group(vec![self.value.clone(); self.repetitions])])
     }
-
-    fn parser(input: &str) -> IResult<&str, Self>
-    where
-        Self: Sized,
-    {
-        repeated::parser(input)
-    }
 }
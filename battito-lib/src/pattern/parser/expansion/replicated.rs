@@ -1,24 +1,17 @@
 use crate::pattern::error::Error;
+use crate::pattern::limits::Limits;
 use crate::pattern::parser::parsed_measure::ParsedMeasure;
-use crate::pattern::parser::replicated;
-use nom::IResult;
-
-use super::Expansion;
 
+// a!2 -> a a, parsed as a suffix of the element, see `parser_repeated`
 pub struct Replicated {
     pub value: ParsedMeasure,
     pub replications: usize,
 }
 
-impl Expansion for Replicated {
-    fn expand(&self) -> Result<Vec<ParsedMeasure>, Error> {
+impl Replicated {
+    pub fn expand(&self, limits: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        let events = self.value.events_count().saturating_mul(self.replications);
+        limits.check_events(events)?;
         Ok(vec![self.value.clone(); self.replications])
     }
-
-    fn parser(input: &str) -> IResult<&str, Self>
-    where
-        Self: Sized,
-    {
-        replicated::parser(input)
-    }
 }
@@ -0,0 +1,45 @@
+use crate::pattern::error::Error;
+use crate::pattern::limits::Limits;
+use crate::pattern::parser::parsed_measure::{ParsedMeasure, Single};
+use crate::pattern::parser::primitives::{Alternate, PrimitiveGroup};
+use crate::pattern::parser::reversed;
+use crate::pattern::parser::ParserResult;
+
+use super::Expansion;
+
+// rev[a [b c]] -> [[c b] a]
+pub struct Reversed {
+    pub(crate) value: ParsedMeasure,
+}
+
+// Reverse the time of a ParsedMeasure: the elements of every nested group, including the groups
+// inside alternates, are played backwards
+pub(crate) fn reverse(pm: &ParsedMeasure) -> ParsedMeasure {
+    match pm {
+        ParsedMeasure::Single(Single::Event(_)) => pm.clone(),
+        ParsedMeasure::Single(Single::Alternate(a)) => ParsedMeasure::Single(Single::Alternate(Alternate(
+            a.0.iter().map(reverse_primitive).collect(),
+        ))),
+        ParsedMeasure::Group(xs) => ParsedMeasure::Group(xs.iter().rev().map(reverse).collect()),
+    }
+}
+
+fn reverse_primitive(pg: &PrimitiveGroup) -> PrimitiveGroup {
+    match pg {
+        PrimitiveGroup::Single(_) => pg.clone(),
+        PrimitiveGroup::Group(xs) => PrimitiveGroup::Group(xs.iter().rev().map(reverse_primitive).collect()),
+    }
+}
+
+impl Expansion for Reversed {
+    fn expand(&self, _: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        Ok(vec![reverse(&self.value)])
+    }
+
+    fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Self>
+    where
+        Self: Sized,
+    {
+        reversed::parser(input, limits)
+    }
+}
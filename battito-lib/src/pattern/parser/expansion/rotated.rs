@@ -0,0 +1,33 @@
+use crate::pattern::error::Error;
+use crate::pattern::limits::Limits;
+use crate::pattern::parser::parsed_measure::ParsedMeasure;
+use crate::pattern::parser::rotated;
+use crate::pattern::parser::ParserResult;
+
+use super::Expansion;
+
+// rot(1)[a b c d] -> [b c d a], negative amounts rotate to the right
+pub struct Rotated {
+    pub(crate) value: ParsedMeasure,
+    pub(crate) amount: i64,
+}
+
+impl Expansion for Rotated {
+    fn expand(&self, _: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        match &self.value {
+            ParsedMeasure::Group(xs) if !xs.is_empty() => {
+                let mut rotated = xs.clone();
+                rotated.rotate_left(self.amount.rem_euclid(xs.len() as i64) as usize);
+                Ok(vec![ParsedMeasure::Group(rotated)])
+            }
+            _ => Ok(vec![self.value.clone()]),
+        }
+    }
+
+    fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Self>
+    where
+        Self: Sized,
+    {
+        rotated::parser(input, limits)
+    }
+}
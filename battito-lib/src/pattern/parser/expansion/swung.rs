@@ -0,0 +1,32 @@
+use crate::pattern::error::Error;
+use crate::pattern::limits::Limits;
+use crate::pattern::parser::parsed_measure::ParsedMeasure;
+use crate::pattern::parser::swung;
+use crate::pattern::parser::ParserResult;
+use crate::pattern::swing::Swing;
+
+use super::Expansion;
+
+// swing(16, 0.6)[a b c d]: the events of the group are placed with the given swing when the
+// pattern is generated, a swing set on an inner group takes precedence
+pub struct Swung {
+    pub(crate) value: ParsedMeasure,
+    pub(crate) swing: Swing,
+}
+
+impl Expansion for Swung {
+    fn expand(&self, _: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        Ok(vec![self.value.map_events(&|event| {
+            let mut event = event.clone();
+            event.modifiers.swing = event.modifiers.swing.or(Some(self.swing));
+            event
+        })])
+    }
+
+    fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Self>
+    where
+        Self: Sized,
+    {
+        swung::parser(input, limits)
+    }
+}
@@ -0,0 +1,82 @@
+use crate::pattern::error::{Error, ParsingError};
+use crate::pattern::scale::{parse_root, Scale};
+use crate::pattern::swing::Swing;
+
+use super::settings::parse_bpm;
+use super::ParserResult;
+use nom::branch::alt;
+use nom::bytes::complete::take_while1;
+use nom::character::complete::{alpha1, char};
+use nom::combinator::{map, map_res, not};
+use nom::multi::separated_list1;
+use nom::sequence::{preceded, separated_pair, terminated};
+
+// Sequence wide settings given before the measures, e.g. "@scale=dorian root=c3 swing=16:0.6 bpm=120 0 2 4"
+#[derive(Debug, PartialEq)]
+pub struct Header {
+    pub scale: Option<Scale>,
+    pub swing: Option<Swing>,
+    pub bpm: Option<f64>,
+}
+
+impl Header {
+    fn from_pairs(pairs: Vec<(&str, &str)>) -> Result<Header, Error> {
+        let mut scale_name: Option<&str> = None;
+        let mut root: Option<i32> = None;
+        let mut swing: Option<Swing> = None;
+        let mut bpm: Option<f64> = None;
+        for (key, value) in pairs {
+            match key {
+                "scale" => scale_name = Some(value),
+                "root" => root = Some(parse_root(value)?),
+                "swing" => swing = Some(parse_swing(value)?),
+                "bpm" => {
+                    let tempo = value
+                        .parse()
+                        .map_err(|_| Error::DSLParsingError(ParsingError::TempoError))?;
+                    bpm = Some(parse_bpm(tempo)?)
+                }
+                _ => return Err(Error::DSLParsingError(ParsingError::Generic)),
+            }
+        }
+        let scale = match (scale_name, root) {
+            (Some(name), root) => Some(Scale::new(name, root)?),
+            (None, Some(_)) => return Err(Error::DSLParsingError(ParsingError::Generic)),
+            (None, None) => None,
+        };
+        Ok(Header { scale, swing, bpm })
+    }
+}
+
+// "16:0.6", grid and amount
+fn parse_swing(input: &str) -> Result<Swing, Error> {
+    let invalid = || Error::DSLParsingError(ParsingError::SwingError);
+    let (grid, amount) = input.split_once(':').ok_or_else(invalid)?;
+    Swing::create(
+        grid.parse().map_err(|_| invalid())?,
+        amount.parse().map_err(|_| invalid())?,
+    )
+}
+
+fn parser_value(input: &str) -> ParserResult<'_, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '#' || c == '-' || c == '.' || c == ':')(input)
+}
+
+fn parser_key_value(input: &str) -> ParserResult<'_, (&str, &str)> {
+    separated_pair(alpha1, char('='), parser_value)(input)
+}
+
+fn parser_header_inner(input: &str) -> ParserResult<'_, Header> {
+    map_res(
+        terminated(
+            preceded(char('@'), separated_list1(char(' '), parser_key_value)),
+            alt((char(' '), char('\n'))),
+        ),
+        Header::from_pairs,
+    )(input)
+}
+
+// The header is optional, but input starting with '@' must be a valid header
+pub(crate) fn parser_header(input: &str) -> ParserResult<'_, Option<Header>> {
+    alt((map(parser_header_inner, Some), map(not(char('@')), |_| None)))(input)
+}
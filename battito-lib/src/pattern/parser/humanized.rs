@@ -0,0 +1,42 @@
+use super::expansion::humanized::Humanized;
+use super::{parser_group, ParserResult};
+use crate::pattern::humanize::Humanize;
+use crate::pattern::limits::Limits;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, digit1, space0};
+use nom::combinator::{map, map_res, opt};
+use nom::sequence::{delimited, preceded, terminated, tuple};
+
+// humanize(10, 20)[a b c d], humanize(10, 20, 7)[a b c d] with a seed. Without the group it is an
+// euclidean rhythm of the value "humanize"
+pub(crate) fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Humanized> {
+    map(
+        tuple((
+            preceded(tag("humanize("), terminated(parser_humanize, tag(")["))),
+            |i| parser_group(i, limits),
+            char(']'),
+        )),
+        |(humanize, value, _)| Humanized { value, humanize },
+    )(input)
+}
+
+fn parser_humanize(input: &str) -> ParserResult<'_, Humanize> {
+    map_res(
+        tuple((
+            digit1,
+            preceded(parser_separator, digit1),
+            opt(preceded(parser_separator, digit1)),
+        )),
+        |(ticks, velocity, seed): (&str, &str, Option<&str>)| {
+            Ok::<Humanize, std::num::ParseIntError>(Humanize::new(
+                ticks.parse()?,
+                velocity.parse()?,
+                seed.map(|s| s.parse()).transpose()?.unwrap_or(0),
+            ))
+        },
+    )(input)
+}
+
+fn parser_separator(input: &str) -> ParserResult<'_, char> {
+    delimited(space0, char(','), space0)(input)
+}
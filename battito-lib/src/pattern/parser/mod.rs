@@ -1,47 +1,156 @@
 mod alternate;
-pub(crate) mod display;
+mod arithmetic;
+mod arpeggio;
+mod conditional;
+// Tree printer for debugging the parser
+#[cfg(test)]
+mod display;
 mod euclidean;
-mod expansion;
+pub(crate) mod expansion;
+mod header;
+mod humanized;
+mod nudge;
+mod palindrome;
 pub(crate) mod parsed_measure;
 pub(crate) mod primitives;
+pub(crate) mod printer;
+mod range;
+mod ratchet;
 mod repeated;
 mod replicated;
+mod reversed;
+mod rotated;
+mod scale;
+mod settings;
+mod swung;
 
 use self::parsed_measure::{Parsed, ParsedMeasure, Polymetric};
 
-use super::error::{Error, ParsingError};
+use super::error::{Error, LimitError, ParsingError};
+use super::limits::Limits;
+use super::measure::Tie;
 use super::parser::alternate::parser_alternate;
+use super::parser::arithmetic::parser_arithmetic;
+use super::parser::expansion::arpeggio::Arpeggio;
+use super::parser::expansion::conditional::{Conditional, Trig};
 use super::parser::expansion::euclidean::Euclidean;
+use super::parser::expansion::humanized::Humanized;
+use super::parser::expansion::palindrome::Palindrome;
+use super::parser::expansion::range::Range;
 use super::parser::expansion::repeated::Repeated;
 use super::parser::expansion::replicated::Replicated;
+use super::parser::expansion::reversed::Reversed;
+use super::parser::expansion::rotated::Rotated;
+use super::parser::expansion::swung::Swung;
 use super::parser::expansion::Expansion;
-use nom::combinator::map_res;
+use super::parser::header::parser_header;
+use super::parser::nudge::parser_nudge;
+use super::parser::ratchet::parser_ratchet;
+use super::parser::scale::parser_scale;
+use super::parser::settings::parser_measure_with_settings;
+use super::scale::Scale;
+use super::sequence::MeasureSettings;
+use super::swing::Swing;
+use nom::combinator::{map_res, success};
+use nom::error::{ErrorKind, FromExternalError, ParseError};
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{alphanumeric1, char, digit1},
-    combinator::{map, opt},
+    combinator::{map, opt, recognize},
     multi::separated_list0,
-    sequence::{preceded, terminated, tuple},
-    IResult,
+    sequence::{pair, preceded, terminated, tuple},
+    IResult, Parser,
 };
 
 #[derive(Debug, PartialEq)]
 pub struct ParsedSequence {
     pub measures: Vec<Parsed>,
+    // Length and tempo of every measure, from "(3/4) a b c" or "a b c @3/4 @140bpm"
+    pub settings: Vec<MeasureSettings>,
     pub length: Option<u32>,
+    pub scale: Option<Scale>,
+    pub swing: Option<Swing>,
 }
 
-pub fn parse(input: &str) -> Result<ParsedSequence, Error> {
-    match parser(input) {
+// Error of the parsers. An exceeded limit is a failure, so that the alternatives are not tried
+// and it reaches the caller instead of being taken for a syntax error, see `map_limited`
+#[derive(Debug, PartialEq)]
+pub(crate) enum ParserError {
+    Syntax,
+    Limit(LimitError),
+}
+
+impl<I> ParseError<I> for ParserError {
+    fn from_error_kind(_: I, _: ErrorKind) -> Self {
+        ParserError::Syntax
+    }
+
+    fn append(_: I, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I, E> FromExternalError<I, E> for ParserError {
+    fn from_external_error(_: I, _: ErrorKind, _: E) -> Self {
+        ParserError::Syntax
+    }
+}
+
+pub(crate) type ParserResult<'a, T> = IResult<&'a str, T, ParserError>;
+
+// Like `map_res`, but an exceeded limit fails the whole parsing
+pub(crate) fn map_limited<'a, O1, O2, F, G>(mut parser: F, mut f: G) -> impl FnMut(&'a str) -> ParserResult<'a, O2>
+where
+    F: Parser<&'a str, O1, ParserError>,
+    G: FnMut(O1) -> Result<O2, Error>,
+{
+    move |input: &'a str| {
+        let (rest, value) = parser.parse(input)?;
+        match f(value) {
+            Ok(value) => Ok((rest, value)),
+            Err(Error::LimitError(e)) => Err(nom::Err::Failure(ParserError::Limit(e))),
+            Err(_) => Err(nom::Err::Error(ParserError::Syntax)),
+        }
+    }
+}
+
+fn parsing_error(error: nom::Err<ParserError>) -> Error {
+    match error {
+        nom::Err::Failure(ParserError::Limit(e)) => Error::LimitError(e),
+        _ => Error::DSLParsingError(ParsingError::Generic),
+    }
+}
+
+pub fn parse(input: &str, limits: &Limits) -> Result<ParsedSequence, Error> {
+    match parser(input, limits) {
         Ok(e) => Ok(e.1),
-        Err(_) => Err(Error::DSLParsingError(ParsingError::Generic)),
+        Err(e) => Err(parsing_error(e)),
     }
 }
 
-fn parser_event_with_prob(input: &str) -> IResult<&str, ParsedMeasure> {
+// Like `parse`, but the whole input has to be a sequence
+pub fn parse_complete(input: &str, limits: &Limits) -> Result<ParsedSequence, Error> {
+    match parser(input, limits) {
+        Ok(("", e)) => Ok(e),
+        Ok(_) => Err(Error::DSLParsingError(ParsingError::Generic)),
+        Err(e) => Err(parsing_error(e)),
+    }
+}
+
+// Counts are made of digits, so they only fail to parse when they do not fit in a u32
+pub(crate) fn parse_count(digits: &str) -> Result<u32, Error> {
+    digits.parse().map_err(|_| Error::LimitError(LimitError::Overflow))
+}
+
+// Event values are alphanumeric, negative integers are allowed for scale degrees
+fn parser_value(input: &str) -> ParserResult<'_, &str> {
+    alt((recognize(pair(char('-'), digit1)), alphanumeric1))(input)
+}
+
+fn parser_event_with_prob(input: &str) -> ParserResult<'_, ParsedMeasure> {
     map_res(
-        tuple((alphanumeric1, preceded(char('?'), digit1))),
+        tuple((parser_value, preceded(char('?'), digit1))),
         |(value, prob): (&str, &str)| -> Result<ParsedMeasure, Error> {
             let prob_num: Result<u8, Error> = prob.parse().map_err(Error::from);
             Ok(ParsedMeasure::event_with_probability(value, prob_num?))
@@ -49,70 +158,186 @@ fn parser_event_with_prob(input: &str) -> IResult<&str, ParsedMeasure> {
     )(input)
 }
 
-fn parser_event_no_prob(input: &str) -> IResult<&str, ParsedMeasure> {
-    map(alt((alphanumeric1, tag("~"))), ParsedMeasure::event)(input)
+fn parser_event_no_prob(input: &str) -> ParserResult<'_, ParsedMeasure> {
+    map(alt((parser_value, tag("~"))), ParsedMeasure::event)(input)
 }
 
-fn parser_event(input: &str) -> IResult<&str, ParsedMeasure> {
-    alt((parser_event_with_prob, parser_event_no_prob))(input)
+fn parser_continuation(input: &str) -> ParserResult<'_, ParsedMeasure> {
+    map(char('_'), |_| ParsedMeasure::continuation())(input)
 }
 
-fn parser_single(input: &str) -> IResult<&str, ParsedMeasure> {
-    alt((parser_event, parser_alternate))(input)
+// a?50:r2>10_: value, probability, ratchet, nudge and tie
+fn parser_event(input: &str) -> ParserResult<'_, ParsedMeasure> {
+    alt((
+        map(
+            tuple((
+                alt((parser_event_with_prob, parser_event_no_prob)),
+                opt(parser_ratchet),
+                opt(parser_nudge),
+                opt(char('_')),
+            )),
+            |(event, ratchet, nudge, tie)| {
+                event.map_events(&|e| {
+                    let mut e = e.clone();
+                    e.modifiers.ratchet = ratchet;
+                    e.modifiers.nudge = nudge;
+                    e.modifiers.tie = tie.map(|_| Tie::Start);
+                    e
+                })
+            },
+        ),
+        parser_continuation,
+    ))(input)
+}
+
+fn parser_single<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedMeasure> {
+    alt((parser_event, |i| parser_alternate(i, limits)))(input)
+}
+
+// The expansions below give a single element
+fn first(expanded: Vec<ParsedMeasure>) -> ParsedMeasure {
+    expanded.into_iter().next().unwrap_or(ParsedMeasure::Group(vec![]))
+}
+
+// Element that a*2 can repeat and a!2 can replicate
+fn parser_repeatable<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedMeasure> {
+    alt((
+        map(|i| Arpeggio::parse(i, limits), first),
+        map(|i| Reversed::parse(i, limits), first),
+        map(|i| Rotated::parse(i, limits), first),
+        map(|i| Palindrome::parse(i, limits), first),
+        map(|i| Conditional::parse(i, limits), first),
+        map(|i| Euclidean::parse(i, limits), first),
+        |i| inner_parser_group(i, limits),
+        |i| parser_single(i, limits),
+    ))(input)
+}
+
+// The element is parsed once and its suffix after it, parsing the element again for every suffix
+// would take exponential time on nested groups
+fn parser_repeated<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Vec<ParsedMeasure>> {
+    let (input, value) = parser_repeatable(input, limits)?;
+    parser_suffix(input, value, limits)
+}
+
+// Repetitions or replications of an element already parsed, if any
+fn parser_suffix<'a>(input: &'a str, value: ParsedMeasure, limits: &Limits) -> ParserResult<'a, Vec<ParsedMeasure>> {
+    let result = alt((
+        map_limited(repeated::parser, |repetitions| {
+            Repeated {
+                value: value.clone(),
+                repetitions,
+            }
+            .expand(limits)
+        }),
+        map_limited(replicated::parser, |replications| {
+            Replicated {
+                value: value.clone(),
+                replications,
+            }
+            .expand(limits)
+        }),
+        success(vec![value.clone()]),
+    ))(input);
+    result
 }
 
-fn parser_parsed_measure(input: &str) -> IResult<&str, Vec<ParsedMeasure>> {
+fn parser_parsed_measure<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Vec<ParsedMeasure>> {
     alt((
-        Repeated::parse,
-        Replicated::parse,
-        Euclidean::parse,
-        map(inner_parser_group, |x| vec![x]),
-        map(parser_single, |x| vec![x]),
+        // Before the euclidean rhythms, humanize(1,2) would be one
+        |i| Humanized::parse(i, limits),
+        |i| Range::parse(i, limits),
+        |i| Trig::parse(i, limits),
+        |i| Swung::parse(i, limits),
+        |i| parser_repeated(i, limits),
     ))(input)
 }
 
-fn parser_group(input: &str) -> IResult<&str, ParsedMeasure> {
-    map(separated_list0(char(' '), parser_parsed_measure), |v| {
+fn parser_group<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedMeasure> {
+    map(separated_list0(char(' '), |i| parser_parsed_measure(i, limits)), |v| {
         ParsedMeasure::Group(v.concat())
     })(input)
 }
 
-fn parser_polymetric(input: &str) -> IResult<&str, Parsed> {
-    map(
+fn parser_polymetric<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Parsed> {
+    map_limited(
         tuple((
             preceded(
                 char('{'),
-                terminated(separated_list0(char(' '), parser_parsed_measure), char('}')),
+                terminated(
+                    separated_list0(char(' '), |i| parser_parsed_measure(i, limits)),
+                    char('}'),
+                ),
             ),
             preceded(char('%'), digit1),
         )),
-        |(elements, length)| {
-            Parsed::Polymetric(Polymetric {
-                elements: elements.concat(),
-                length: length.parse().unwrap(),
-            })
+        |(elements, length)| -> Result<Parsed, Error> {
+            match parse_count(length)? {
+                0 => Err(Error::DSLParsingError(ParsingError::Generic)),
+                // Every measure has `length` steps
+                length => Ok(Parsed::Polymetric(Polymetric {
+                    elements: elements.concat(),
+                    length: limits.check_events(length as usize)? as u32,
+                })),
+            }
         },
     )(input)
 }
 
-fn parser_measure(input: &str) -> IResult<&str, Parsed> {
-    alt((parser_polymetric, map(parser_group, Parsed::ParsedMeasure)))(input)
+fn parser_measure<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Parsed> {
+    alt((
+        |i| parser_arithmetic(i, limits),
+        |i| parser_polymetric(i, limits),
+        map(|i| parser_group(i, limits), Parsed::ParsedMeasure),
+    ))(input)
 }
 
-fn parser_measures(input: &str) -> IResult<&str, (Vec<Parsed>, &str)> {
-    map(separated_list0(tag(" | "), parser_measure), |p| (p, input))(input)
+fn parser_measures<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Vec<(Parsed, MeasureSettings)>> {
+    separated_list0(tag(" | "), |i| parser_measure_with_settings(i, limits))(input)
 }
 
-fn inner_parser_group(input: &str) -> IResult<&str, ParsedMeasure> {
-    preceded(char('['), terminated(parser_group, char(']')))(input)
+fn inner_parser_group<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedMeasure> {
+    preceded(char('['), terminated(|i| parser_group(i, limits), char(']')))(input)
 }
 
-fn parser(input: &str) -> IResult<&str, ParsedSequence> {
+fn parser_sequence<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedSequence> {
     map(
-        tuple((parser_measures, opt(preceded(tag(" / "), digit1)))),
+        tuple((
+            |i| parser_measures(i, limits),
+            opt(map_limited(preceded(tag(" / "), digit1), parse_count)),
+        )),
         |(parsed, length)| ParsedSequence {
-            measures: parsed.0,
-            length: length.map(|s| s.parse().unwrap()),
+            settings: parsed.iter().map(|(_, s)| s.clone()).collect(),
+            measures: parsed.into_iter().map(|(m, _)| m).collect(),
+            length,
+            scale: None,
+            swing: None,
+        },
+    )(input)
+}
+
+fn parser_with_header<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedSequence> {
+    map(
+        tuple((parser_header, |i| parser_sequence(i, limits))),
+        |(header, sequence)| match header {
+            // The tempo of the header is the one of the first measure, unless it has its own
+            Some(header) => {
+                let mut settings = sequence.settings.clone();
+                if let Some(first) = settings.first_mut() {
+                    first.bpm = first.bpm.or(header.bpm);
+                }
+                ParsedSequence {
+                    scale: header.scale,
+                    swing: header.swing,
+                    settings,
+                    ..sequence
+                }
+            }
+            None => sequence,
         },
     )(input)
 }
+
+fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedSequence> {
+    alt((|i| parser_scale(i, limits), |i| parser_with_header(i, limits)))(input)
+}
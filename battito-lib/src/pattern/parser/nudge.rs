@@ -0,0 +1,38 @@
+use crate::pattern::error::{Error, LimitError};
+use crate::pattern::nudge::{Nudge, MAX_NUDGE};
+use crate::pattern::parser::{map_limited, ParserResult};
+use nom::branch::alt;
+use nom::character::complete::{char, digit1, one_of};
+use nom::combinator::recognize;
+use nom::number::complete::double;
+use nom::sequence::{pair, preceded};
+
+// sn>10 is played 10 ticks late, sn<5 5 ticks early, sn@+0.02 and sn@-0.02 move it by a fraction of
+// a cycle. The sign tells them from measure lengths and trigs, sn^+0.02 is the same nudge
+pub(crate) fn parser_nudge(input: &str) -> ParserResult<'_, Nudge> {
+    alt((
+        map_limited(preceded(char('>'), digit1), |d: &str| ticks(d).map(Nudge::Ticks)),
+        map_limited(preceded(char('<'), digit1), |d: &str| {
+            ticks(d).map(|t| Nudge::Ticks(-t))
+        }),
+        map_limited(
+            preceded(one_of("@^"), recognize(pair(one_of("+-"), double))),
+            |c: &str| cycles(c).map(Nudge::Cycles),
+        ),
+    ))(input)
+}
+
+// At most MAX_NUDGE ticks, the largest u32
+fn ticks(digits: &str) -> Result<i64, Error> {
+    digits
+        .parse::<u32>()
+        .map(i64::from)
+        .map_err(|_| Error::LimitError(LimitError::Overflow))
+}
+
+fn cycles(number: &str) -> Result<f64, Error> {
+    match number.parse::<f64>() {
+        Ok(cycles) if cycles.is_finite() && cycles.abs() <= MAX_NUDGE as f64 => Ok(cycles),
+        _ => Err(Error::LimitError(LimitError::Overflow)),
+    }
+}
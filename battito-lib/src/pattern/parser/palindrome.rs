@@ -0,0 +1,15 @@
+use super::expansion::palindrome::Palindrome;
+use super::{parser_group, ParserResult};
+use crate::pattern::limits::Limits;
+use nom::bytes::complete::tag;
+use nom::character::complete::char;
+use nom::combinator::map;
+use nom::sequence::delimited;
+
+// palin[a b c]
+pub(crate) fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Palindrome> {
+    map(
+        delimited(tag("palin["), |i| parser_group(i, limits), char(']')),
+        |value| Palindrome { value },
+    )(input)
+}
@@ -1,5 +1,7 @@
-use crate::pattern::measure::{self, Measure};
-use crate::pattern::utils::lcm_vec;
+use crate::pattern::error::{Error, LimitError};
+use crate::pattern::limits::Limits;
+use crate::pattern::measure::{self, Measure, Modifiers, Operator, Tie};
+use crate::pattern::utils::{lcm, lcm_vec};
 
 use super::primitives::{Alternate, ParsedEvent};
 
@@ -14,6 +16,7 @@ impl Single {
         Single::Event(ParsedEvent {
             value: "0".to_string(),
             probability: 0,
+            modifiers: Modifiers::default(),
         })
     }
 }
@@ -24,10 +27,19 @@ pub struct Polymetric {
     pub length: u32,
 }
 
+// "0 2 4" + "<0,12>": the values of the right side are added to the events of the left side
+#[derive(Debug, PartialEq, Clone)]
+pub struct Arithmetic {
+    pub left: Box<Parsed>,
+    pub operator: Operator,
+    pub right: Box<Parsed>,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Parsed {
     ParsedMeasure(ParsedMeasure),
     Polymetric(Polymetric),
+    Arithmetic(Arithmetic),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -36,23 +48,82 @@ pub enum ParsedMeasure {
     Group(Vec<ParsedMeasure>),
 }
 
+// Measures are computed on demand: `measure_count` tells how many measures are produced before
+// the expansion repeats, and `measure(i)` builds only the i-th one
 impl Parsed {
-    pub fn to_measures(&self) -> Vec<Measure> {
+    pub fn measure_count(&self) -> Result<usize, Error> {
+        match self {
+            Parsed::ParsedMeasure(parsed_measure) => parsed_measure.measure_count(),
+            Parsed::Polymetric(polymetric) => polymetric.measure_count(),
+            Parsed::Arithmetic(arithmetic) => arithmetic.measure_count(),
+        }
+    }
+
+    pub fn measure(&self, i: usize) -> Result<Measure, Error> {
         match self {
-            Parsed::ParsedMeasure(parsed_measure) => parsed_measure.to_measures(),
-            Parsed::Polymetric(polymetric) => polymetric.to_measures(),
+            Parsed::ParsedMeasure(parsed_measure) => parsed_measure.measure(i),
+            Parsed::Polymetric(polymetric) => polymetric.measure(i),
+            Parsed::Arithmetic(arithmetic) => arithmetic.measure(i),
         }
     }
 }
 
+impl Arithmetic {
+    // The n-th measure of the left side is combined with the n-th measure of the right side, until
+    // both sides complete their cycles
+    pub fn measure_count(&self) -> Result<usize, Error> {
+        let count = lcm(self.left.measure_count()? as u32, self.right.measure_count()? as u32)?;
+        Ok(count as usize)
+    }
+
+    pub fn measure(&self, i: usize) -> Result<Measure, Error> {
+        let left = self.left.measure(i % self.left.measure_count()?)?;
+        let right = self.right.measure(i % self.right.measure_count()?)?;
+        left.combine(&right, self.operator)
+    }
+}
+
 impl ParsedMeasure {
-    // Transform this parsed measure into a vector of Measure
-    pub fn to_measures(&self) -> Vec<Measure> {
-        let n = lcm_vec(&self.count_replications());
-        // Create n copies of this ParsedMeasure
-        let mut replicated: Vec<ParsedMeasure> = vec![self.clone(); n as usize];
-        Self::expand_alternate(&mut replicated);
-        replicated.iter().map(|p| Self::out(p.clone())).collect()
+    // Apply `f` to every event, including the ones inside alternates
+    pub fn map_events(&self, f: &dyn Fn(&ParsedEvent) -> ParsedEvent) -> Self {
+        match self {
+            ParsedMeasure::Single(Single::Event(event)) => ParsedMeasure::Single(Single::Event(f(event))),
+            ParsedMeasure::Single(Single::Alternate(a)) => ParsedMeasure::Single(Single::Alternate(Alternate(
+                a.0.iter().map(|pg| pg.map_events(f)).collect(),
+            ))),
+            ParsedMeasure::Group(xs) => ParsedMeasure::Group(xs.iter().map(|pm| pm.map_events(f)).collect()),
+        }
+    }
+
+    // Number of cycles needed by the alternates to start over
+    pub fn measure_count(&self) -> Result<usize, Error> {
+        Ok(lcm_vec(&self.count_replications())? as usize)
+    }
+
+    pub fn measure(&self, i: usize) -> Result<Measure, Error> {
+        Self::out(self.variant(i))
+    }
+
+    // Copy of this ParsedMeasure with the alternates resolved for the given cycle
+    pub fn variant(&self, cycle: usize) -> ParsedMeasure {
+        let mut resolved = self.clone();
+        Self::expand_rec(&mut resolved, cycle);
+        resolved
+    }
+
+    // One copy of this ParsedMeasure for each cycle of its alternates, with the alternates resolved
+    pub fn variants(&self, limits: &Limits) -> Result<Vec<ParsedMeasure>, Error> {
+        let count = limits.check_measures(self.measure_count()?)?;
+        Ok((0..count).map(|i| self.variant(i)).collect())
+    }
+
+    // Number of events, including the ones of every alternate
+    pub fn events_count(&self) -> usize {
+        match self {
+            ParsedMeasure::Single(Single::Event(_)) => 1,
+            ParsedMeasure::Single(Single::Alternate(a)) => a.0.iter().map(|pg| pg.events_count()).sum(),
+            ParsedMeasure::Group(xs) => xs.iter().map(|x| x.events_count()).sum(),
+        }
     }
 
     fn count_replications(&self) -> Vec<u32> {
@@ -61,7 +132,7 @@ impl ParsedMeasure {
         reps
     }
 
-    fn _count_replications(acc: &mut Vec<u32>, p: &ParsedMeasure) -> () {
+    fn _count_replications(acc: &mut Vec<u32>, p: &ParsedMeasure) {
         match p {
             ParsedMeasure::Single(Single::Alternate(x)) => acc.push(x.0.len() as u32),
             ParsedMeasure::Group(pms) => {
@@ -73,30 +144,21 @@ impl ParsedMeasure {
         }
     }
 
-    fn out(parsed_measure: ParsedMeasure) -> Measure {
+    // Measure of a parsed measure whose alternates are resolved, see `variant`
+    fn out(parsed_measure: ParsedMeasure) -> Result<Measure, Error> {
         match parsed_measure {
-            Self::Single(Single::Event(n)) => Measure::Event(measure::Event {
-                value: n.value.clone(),
+            Self::Single(Single::Event(n)) => Ok(Measure::Event(measure::Event {
+                value: n.value,
                 probability: n.probability,
-            }),
-            Self::Group(x) => {
-                let nested: Vec<Measure> = x.iter().map(|b| Self::out(b.clone())).collect();
-                Measure::Group(nested)
-            }
-            _ => panic!("Not expected"),
+                velocity: measure::DEFAULT_VELOCITY,
+                modifiers: n.modifiers,
+            })),
+            Self::Group(x) => Ok(Measure::Group(x.into_iter().map(Self::out).collect::<Result<_, _>>()?)),
+            Self::Single(Single::Alternate(_)) => Err(Error::UnexpectedError),
         }
     }
 
-    fn expand_alternate(replicated: &mut Vec<ParsedMeasure>) -> () {
-        // Remove Alternate, Polymetric
-        let mut i: usize = 0;
-        for pm in replicated {
-            Self::expand_rec(pm, i);
-            i = i + 1;
-        }
-    }
-
-    fn expand_rec(pm: &mut ParsedMeasure, iter: usize) -> () {
+    fn expand_rec(pm: &mut ParsedMeasure, iter: usize) {
         match pm {
             ParsedMeasure::Single(Single::Event(_)) => (),
             ParsedMeasure::Single(Single::Alternate(an)) => *pm = an.next(iter).to_parsed_measure(),
@@ -120,6 +182,7 @@ impl ParsedMeasure {
                 Self::Single(Single::Event(ParsedEvent {
                     value: value_parsed.to_string(),
                     probability: probability.unwrap_or(100),
+                    modifiers: Modifiers::default(),
                 }))
             })
             .collect();
@@ -135,6 +198,18 @@ impl ParsedMeasure {
         Self::Single(Single::Event(ParsedEvent {
             value: value_parsed.to_string(),
             probability,
+            modifiers: Modifiers::default(),
+        }))
+    }
+
+    pub fn continuation() -> Self {
+        Self::Single(Single::Event(ParsedEvent {
+            value: measure::CONTINUATION.to_string(),
+            probability: 100,
+            modifiers: Modifiers {
+                tie: Some(Tie::Continue),
+                ..Modifiers::default()
+            },
         }))
     }
 
@@ -146,97 +221,54 @@ impl ParsedMeasure {
         Self::Single(Single::Event(ParsedEvent {
             value: value_parsed.to_string(),
             probability: prob,
+            modifiers: Modifiers::default(),
         }))
     }
 }
 
 impl Polymetric {
-    // Transform this parsed measure into a vector of Measure
-    pub fn to_measures(&self) -> Vec<Measure> {
-        let group = ParsedMeasure::Group(self.elements.clone());
-        let n = lcm_vec(&group.count_replications());
-        // Create n copies of this ParsedMeasure
-        let mut replicated: Vec<ParsedMeasure> = vec![group; n as usize];
-        Self::expand_alternate(&mut replicated);
-        let extracted_and_flattened: Vec<ParsedMeasure> = Self::extract_and_flatten(replicated);
-        let expanded_polymetric: Vec<ParsedMeasure> =
-            Self::expand_polymetric(&extracted_and_flattened, self.length as usize);
-        expanded_polymetric.iter().map(|p| Self::out(p.clone())).collect()
-    }
-
-    // [Group(x,y,z), Group(a,b,c)] => [x,y,z,a,b,c]
-    fn extract_and_flatten(elements: Vec<ParsedMeasure>) -> Vec<ParsedMeasure> {
-        let mut out: Vec<ParsedMeasure> = Vec::new();
-        for i in elements {
-            match i {
-                ParsedMeasure::Single(_) => out.push(i),
-                ParsedMeasure::Group(x) => out.extend(x),
-            }
-        }
-        out
-    }
-
-    fn expand_alternate(replicated: &mut Vec<ParsedMeasure>) -> () {
-        // Remove Alternate
-        let mut i: usize = 0;
-        for pm in replicated {
-            Self::rec(pm, i);
-            i = i + 1;
-        }
+    // The elements of every cycle of the alternates, one after the other, are split in measures of
+    // `length` elements
+    pub fn measure_count(&self) -> Result<usize, Error> {
+        let elements_len = self.elements_count()?;
+        let length = self.length as usize;
+        Ok(if elements_len == 0 {
+            1
+        } else if !elements_len.is_multiple_of(length) && !length.is_multiple_of(elements_len) {
+            elements_len
+        } else if elements_len <= length {
+            1
+        } else {
+            elements_len / length
+        })
     }
 
-    fn rec(pm: &mut ParsedMeasure, iter: usize) -> () {
-        match pm {
-            ParsedMeasure::Single(Single::Event(_)) => (),
-            ParsedMeasure::Single(Single::Alternate(an)) => *pm = an.next(iter).to_parsed_measure(),
-            ParsedMeasure::Group(x) => {
-                for a in x {
-                    Self::rec(a, iter);
-                }
-            }
+    pub fn measure(&self, i: usize) -> Result<Measure, Error> {
+        let elements_len = self.elements_count()?;
+        // Checked against the limits by the parser
+        let length = self.length as usize;
+        if elements_len == 0 {
+            return Ok(Measure::Group(vec![]));
         }
+        let internal: Vec<ParsedMeasure> = (0..length)
+            .map(|x| self.element((i * length + x) % elements_len))
+            .collect();
+        ParsedMeasure::out(ParsedMeasure::Group(internal))
     }
 
-    fn expand_polymetric(elements: &Vec<ParsedMeasure>, length: usize) -> Vec<ParsedMeasure> {
-        let elements_len = elements.len();
-        let number_of_measures = if elements_len % length != 0 && length % elements_len != 0 {
-            elements_len
-        } else {
-            if elements_len <= length {
-                1
-            } else {
-                elements_len / length
-            }
-        };
-        let mut out: Vec<ParsedMeasure> = Vec::with_capacity(number_of_measures);
-        let mut i: usize = 0;
-        for _ in 0..number_of_measures {
-            let mut internal: Vec<ParsedMeasure> = Vec::with_capacity(length as usize);
-            for _ in 0..length {
-                internal.push(Self::next(elements, i));
-                i = i + 1;
-            }
-            out.push(ParsedMeasure::Group(internal));
-        }
-        out
+    fn cycles(&self) -> Result<usize, Error> {
+        let replications: Vec<u32> = self.elements.iter().flat_map(|e| e.count_replications()).collect();
+        Ok(lcm_vec(&replications)? as usize)
     }
 
-    fn next(v: &Vec<ParsedMeasure>, i: usize) -> ParsedMeasure {
-        let index = i % v.len();
-        v.get(index).unwrap().clone()
+    fn elements_count(&self) -> Result<usize, Error> {
+        self.cycles()?
+            .checked_mul(self.elements.len())
+            .ok_or(Error::LimitError(LimitError::Overflow))
     }
 
-    fn out(parsed_measure: ParsedMeasure) -> Measure {
-        match parsed_measure {
-            ParsedMeasure::Single(Single::Event(n)) => Measure::Event(measure::Event {
-                value: n.value.clone(),
-                probability: n.probability,
-            }),
-            ParsedMeasure::Group(x) => {
-                let nested: Vec<Measure> = x.iter().map(|b| Self::out(b.clone())).collect();
-                Measure::Group(nested)
-            }
-            _ => panic!("Not expected"),
-        }
+    // The i-th element of the flattened cycles, with its alternates resolved
+    fn element(&self, i: usize) -> ParsedMeasure {
+        self.elements[i % self.elements.len()].variant(i / self.elements.len())
     }
 }
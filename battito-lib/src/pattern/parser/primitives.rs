@@ -1,10 +1,13 @@
+use crate::pattern::measure::Modifiers;
 use crate::pattern::parser::parsed_measure::{ParsedMeasure, Single};
 use serde::Serialize;
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
 pub struct ParsedEvent {
     pub value: String,
     pub probability: u8, // [0, 100]
+    #[serde(skip)]
+    pub modifiers: Modifiers,
 }
 
 impl ParsedEvent {
@@ -12,6 +15,7 @@ impl ParsedEvent {
         ParsedEvent {
             value: "0".to_string(),
             probability: 0,
+            modifiers: Modifiers::default(),
         }
     }
 }
@@ -25,7 +29,7 @@ impl Alternate {
         self.0.get(index).unwrap().clone()
     }
 
-    pub fn from_parsed_measures(pms: &Vec<ParsedMeasure>) -> Self {
+    pub fn from_parsed_measures(pms: &[ParsedMeasure]) -> Self {
         Alternate(pms.iter().map(PrimitiveGroup::from_parsed_measure).collect())
     }
 }
@@ -51,4 +55,18 @@ impl PrimitiveGroup {
             _ => panic!("Not expected here"),
         }
     }
+
+    pub fn map_events(&self, f: &dyn Fn(&ParsedEvent) -> ParsedEvent) -> Self {
+        match self {
+            PrimitiveGroup::Single(event) => PrimitiveGroup::Single(f(event)),
+            PrimitiveGroup::Group(xs) => PrimitiveGroup::Group(xs.iter().map(|pg| pg.map_events(f)).collect()),
+        }
+    }
+
+    pub fn events_count(&self) -> usize {
+        match self {
+            PrimitiveGroup::Single(_) => 1,
+            PrimitiveGroup::Group(xs) => xs.iter().map(|pg| pg.events_count()).sum(),
+        }
+    }
 }
@@ -0,0 +1,485 @@
+use crate::pattern::humanize::Humanize;
+use crate::pattern::measure::{Operator, Tie, CONTINUATION};
+use crate::pattern::nudge::Nudge;
+use crate::pattern::ratchet::{Decay, Ratchet};
+use crate::pattern::rational::Rational;
+use crate::pattern::scale::DEFAULT_ROOT;
+use crate::pattern::sequence::MeasureSettings;
+use crate::pattern::swing::Swing;
+
+use super::parsed_measure::{Arithmetic, Parsed, ParsedMeasure, Polymetric, Single};
+use super::primitives::{Alternate, ParsedEvent, PrimitiveGroup};
+use super::ParsedSequence;
+
+// Source of a parsed sequence, parsing it again gives the same sequence. Expansions are applied by
+// the parser, so they are written expanded: "bd(3,8)" is printed as "bd ~ ~ bd ~ ~ bd ~". The tempo
+// of a header is written on the first measure, where the parser puts it
+pub fn print(sequence: &ParsedSequence) -> String {
+    let measures: Vec<String> = sequence
+        .measures
+        .iter()
+        .zip(sequence.settings.iter())
+        .map(|(parsed, settings)| measure(parsed, settings))
+        .collect();
+    let length = sequence.length.map(|l| format!(" / {}", l)).unwrap_or_default();
+    format!("{}{}{}", header(sequence), measures.join(" | "), length)
+}
+
+// Redundant brackets are removed: "[[a b]]" is "[a b]", "[a]" is "a" and "<a>" is "a"
+pub fn normalize(sequence: ParsedSequence) -> ParsedSequence {
+    ParsedSequence {
+        measures: sequence.measures.iter().map(normalize_parsed).collect(),
+        ..sequence
+    }
+}
+
+// Spaces are collapsed and removed inside brackets and around commas, where the parser does not
+// allow them
+pub fn spacing(input: &str) -> String {
+    let collapsed = input.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let chars: Vec<char> = collapsed.chars().collect();
+    chars
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| {
+            let previous = if *i == 0 { None } else { chars.get(i - 1) };
+            let next = chars.get(i + 1);
+            **c != ' '
+                || !(matches!(previous, Some('[') | Some('{') | Some('(') | Some('<') | Some(','))
+                    || matches!(next, Some(']') | Some('}') | Some(')') | Some('>') | Some(',')))
+        })
+        .map(|(_, c)| c)
+        .collect()
+}
+
+// A space follows the commas between the arguments of a function, as in "swing(8, 0.6)[a b]".
+// Euclidean rhythms and alternates keep theirs without: "bd(3,8)", "<a,b>"
+pub fn arguments(input: &str) -> String {
+    const FUNCTIONS: &[&str] = &["arp", "every", "whenmod", "humanize", "swing", "scale"];
+    let mut calls: Vec<bool> = Vec::new();
+    let mut name = String::new();
+    let mut written = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '(' => calls.push(FUNCTIONS.contains(&name.as_str())),
+            '[' | '{' | '<' => calls.push(false),
+            ')' | ']' | '}' | '>' => {
+                calls.pop();
+            }
+            _ => {}
+        }
+        written.push(c);
+        if c == ',' && calls.last() == Some(&true) {
+            written.push(' ');
+        }
+        match c.is_alphanumeric() || c == '_' {
+            true => name.push(c),
+            false => name.clear(),
+        }
+    }
+    written
+}
+
+fn header(sequence: &ParsedSequence) -> String {
+    let mut pairs: Vec<String> = Vec::new();
+    if let Some((name, root)) = sequence.scale.as_ref().and_then(|s| s.name().map(|n| (n, s.root))) {
+        pairs.push(format!("scale={}", name));
+        if root != DEFAULT_ROOT {
+            pairs.push(format!("root={}", root));
+        }
+    }
+    if let Some(swing) = sequence.swing {
+        pairs.push(format!("swing={}:{}", swing.grid, swing.amount));
+    }
+    match pairs.is_empty() {
+        true => String::new(),
+        false => format!("@{} ", pairs.join(" ")),
+    }
+}
+
+fn measure(parsed: &Parsed, settings: &MeasureSettings) -> String {
+    let mut written = parsed_source(parsed, false);
+    if settings.length != Rational::integer(1) {
+        written.push_str(&format!(" @{}", settings.length));
+    }
+    if let Some(bpm) = settings.bpm {
+        written.push_str(&format!(" @{}bpm", bpm));
+    }
+    written
+}
+
+// Quotes do not nest, inside them the operands of an arithmetic are parenthesized
+fn parsed_source(parsed: &Parsed, quoted: bool) -> String {
+    match parsed {
+        Parsed::ParsedMeasure(ParsedMeasure::Group(xs)) => elements(xs, None, None),
+        Parsed::ParsedMeasure(pm) => element(pm, None, None),
+        Parsed::Polymetric(p) => format!("{{{}}}%{}", elements(&p.elements, None, None), p.length),
+        Parsed::Arithmetic(a) => arithmetic(a, quoted),
+    }
+}
+
+fn arithmetic(arithmetic: &Arithmetic, quoted: bool) -> String {
+    let left = match &*arithmetic.left {
+        Parsed::Arithmetic(left) => self::arithmetic(left, quoted),
+        left => operand(left, quoted),
+    };
+    let operator = match arithmetic.operator {
+        Operator::Add => '+',
+        Operator::Subtract => '-',
+        Operator::Multiply => '*',
+    };
+    // A single value is written without quotes
+    let right = match &*arithmetic.right {
+        Parsed::ParsedMeasure(ParsedMeasure::Group(xs)) => match xs.as_slice() {
+            [ParsedMeasure::Single(Single::Event(e))]
+                if e.probability == 100 && e.modifiers == Default::default() && e.value != CONTINUATION =>
+            {
+                e.value.clone()
+            }
+            _ => operand(&arithmetic.right, quoted),
+        },
+        right => operand(right, quoted),
+    };
+    format!("{} {} {}", left, operator, right)
+}
+
+fn operand(parsed: &Parsed, quoted: bool) -> String {
+    match quoted {
+        true => format!("({})", parsed_source(parsed, true)),
+        false => format!("\"{}\"", parsed_source(parsed, true)),
+    }
+}
+
+fn elements(xs: &[ParsedMeasure], swing: Option<Swing>, humanize: Option<Humanize>) -> String {
+    let written: Vec<String> = xs.iter().map(|x| element(x, swing, humanize)).collect();
+    written.join(" ")
+}
+
+// The swing of the events is written on the outermost group sharing it, `swing` is the one already
+// written by an enclosing group. A swing inside an alternate can only be written when the whole
+// alternate shares it. The humanize is written the same way, on the group of the events sharing it
+fn element(parsed_measure: &ParsedMeasure, swing: Option<Swing>, humanize: Option<Humanize>) -> String {
+    let own = own_swing(parsed_measure).filter(|s| Some(*s) != swing);
+    let own_humanize = self::own_humanize(parsed_measure).filter(|h| Some(*h) != humanize);
+    let written = match parsed_measure {
+        ParsedMeasure::Group(xs) => {
+            let inner = elements(xs, own.or(swing), own_humanize.or(humanize));
+            match (own, own_humanize) {
+                (None, None) => return format!("[{}]", inner),
+                _ => inner,
+            }
+        }
+        ParsedMeasure::Single(Single::Event(e)) => event(e),
+        ParsedMeasure::Single(Single::Alternate(a)) => alternate(a),
+    };
+    let written = match own_humanize {
+        Some(h) => humanized(h, written),
+        None => written,
+    };
+    match own {
+        Some(s) => format!("{}[{}]", swung(s), written),
+        None => written,
+    }
+}
+
+fn swung(swing: Swing) -> String {
+    format!("swing({}, {})", swing.grid, swing.amount)
+}
+
+fn humanized(humanize: Humanize, written: String) -> String {
+    match humanize.seed {
+        0 => format!("humanize({}, {})[{}]", humanize.ticks, humanize.velocity, written),
+        seed => format!(
+            "humanize({}, {}, {})[{}]",
+            humanize.ticks, humanize.velocity, seed, written
+        ),
+    }
+}
+
+fn alternate(alternate: &Alternate) -> String {
+    let written: Vec<String> = alternate.0.iter().map(primitive).collect();
+    format!("<{}>", written.join(","))
+}
+
+fn primitive(primitive_group: &PrimitiveGroup) -> String {
+    match primitive_group {
+        PrimitiveGroup::Single(e) => event(e),
+        PrimitiveGroup::Group(xs) => {
+            let written: Vec<String> = xs.iter().map(self::primitive).collect();
+            format!("[{}]", written.join(" "))
+        }
+    }
+}
+
+// a?50:r2>10_: value, probability, ratchet, nudge and tie
+fn event(event: &ParsedEvent) -> String {
+    if event.modifiers.tie == Some(Tie::Continue) {
+        return CONTINUATION.to_string();
+    }
+    let mut written = match (event.value.as_str(), event.probability) {
+        ("0", 0) => "~".to_string(),
+        (value, 100) => value.to_string(),
+        (value, probability) => format!("{}?{}", value, probability),
+    };
+    if let Some(ratchet) = event.modifiers.ratchet {
+        written.push_str(&self::ratchet(ratchet));
+    }
+    match event.modifiers.nudge {
+        Some(Nudge::Ticks(t)) if t < 0 => written.push_str(&format!("<{}", -t)),
+        Some(Nudge::Ticks(t)) => written.push_str(&format!(">{}", t)),
+        Some(Nudge::Cycles(c)) => written.push_str(&format!("@{:+}", c)),
+        None => (),
+    }
+    if event.modifiers.tie == Some(Tie::Start) {
+        written.push('_');
+    }
+    written
+}
+
+fn ratchet(ratchet: Ratchet) -> String {
+    match ratchet.decay {
+        Some(Decay::Velocity(d)) => format!(":r{}:v{}", ratchet.count, d),
+        Some(Decay::Probability(d)) => format!(":r{}:p{}", ratchet.count, d),
+        None => format!(":r{}", ratchet.count),
+    }
+}
+
+// The swing shared by all the events, including the ones of the alternates
+fn common_swing(parsed_measure: &ParsedMeasure) -> Option<Swing> {
+    let mut swings: Vec<Option<Swing>> = Vec::new();
+    collect_swings(parsed_measure, &mut swings);
+    match swings.split_first() {
+        Some((first, rest)) if rest.iter().all(|s| s == first) => *first,
+        _ => None,
+    }
+}
+
+// Swing written on a group, the inner groups can override it with their own: "swing(8, 0.7)[a
+// swing(16, 0.6)[b c]]". It is the swing of the events directly in the group, or the one of all
+// its events when it only contains groups
+fn own_swing(parsed_measure: &ParsedMeasure) -> Option<Swing> {
+    match parsed_measure {
+        ParsedMeasure::Group(xs) => {
+            let direct = xs.iter().find(|x| matches!(x, ParsedMeasure::Single(_)));
+            let swing = match direct {
+                Some(single) => common_swing(single),
+                None => common_swing(parsed_measure),
+            };
+            swing.filter(|s| covers(parsed_measure, *s))
+        }
+        single => common_swing(single),
+    }
+}
+
+// Whether the events of the group get the swing of the group or the one of an inner group
+fn covers(parsed_measure: &ParsedMeasure, swing: Swing) -> bool {
+    match parsed_measure {
+        ParsedMeasure::Group(xs) => xs.iter().all(|x| match x {
+            ParsedMeasure::Group(_) => own_swing(x).is_some() || covers(x, swing),
+            single => common_swing(single) == Some(swing),
+        }),
+        single => common_swing(single) == Some(swing),
+    }
+}
+
+// Humanize written on a group, the one shared by the events and alternates directly in it. A group
+// made of groups leaves it to them
+fn own_humanize(parsed_measure: &ParsedMeasure) -> Option<Humanize> {
+    let mut humanizes: Vec<Option<Humanize>> = Vec::new();
+    match parsed_measure {
+        ParsedMeasure::Group(xs) => xs
+            .iter()
+            .filter(|x| matches!(x, ParsedMeasure::Single(_)))
+            .for_each(|x| collect_humanizes(x, &mut humanizes)),
+        single => collect_humanizes(single, &mut humanizes),
+    }
+    match humanizes.split_first() {
+        Some((first, rest)) if rest.iter().all(|h| h == first) => *first,
+        _ => None,
+    }
+}
+
+fn collect_humanizes(parsed_measure: &ParsedMeasure, acc: &mut Vec<Option<Humanize>>) {
+    match parsed_measure {
+        ParsedMeasure::Single(Single::Event(e)) if e.modifiers.tie != Some(Tie::Continue) => {
+            acc.push(e.modifiers.humanize)
+        }
+        ParsedMeasure::Single(Single::Event(_)) => (),
+        ParsedMeasure::Single(Single::Alternate(a)) => {
+            a.0.iter()
+                .for_each(|pg| collect_humanizes(&pg.to_parsed_measure(), acc))
+        }
+        ParsedMeasure::Group(xs) => xs.iter().for_each(|x| collect_humanizes(x, acc)),
+    }
+}
+
+fn collect_swings(parsed_measure: &ParsedMeasure, acc: &mut Vec<Option<Swing>>) {
+    match parsed_measure {
+        ParsedMeasure::Single(Single::Event(e)) => acc.push(e.modifiers.swing),
+        ParsedMeasure::Single(Single::Alternate(a)) => {
+            a.0.iter().for_each(|pg| collect_swings(&pg.to_parsed_measure(), acc))
+        }
+        ParsedMeasure::Group(xs) => xs.iter().for_each(|x| collect_swings(x, acc)),
+    }
+}
+
+fn normalize_parsed(parsed: &Parsed) -> Parsed {
+    match parsed {
+        // A measure made of a single group is the group itself, unless the group carries a swing or
+        // a humanize
+        Parsed::ParsedMeasure(ParsedMeasure::Group(xs)) => {
+            let xs: Vec<ParsedMeasure> = xs.iter().map(normalize_element).collect();
+            Parsed::ParsedMeasure(match xs.as_slice() {
+                [inner @ ParsedMeasure::Group(_)] if own_swing(inner).is_none() && own_humanize(inner).is_none() => {
+                    inner.clone()
+                }
+                _ => ParsedMeasure::Group(xs),
+            })
+        }
+        Parsed::ParsedMeasure(pm) => Parsed::ParsedMeasure(normalize_element(pm)),
+        Parsed::Polymetric(p) => Parsed::Polymetric(Polymetric {
+            elements: p.elements.iter().map(normalize_element).collect(),
+            length: p.length,
+        }),
+        Parsed::Arithmetic(a) => Parsed::Arithmetic(Arithmetic {
+            left: Box::new(normalize_parsed(&a.left)),
+            operator: a.operator,
+            right: Box::new(normalize_parsed(&a.right)),
+        }),
+    }
+}
+
+// A single swung or humanized event keeps its brackets, they are the ones of the swing or humanize
+fn normalize_element(parsed_measure: &ParsedMeasure) -> ParsedMeasure {
+    match parsed_measure {
+        ParsedMeasure::Group(xs) => {
+            let xs: Vec<ParsedMeasure> = xs.iter().map(normalize_element).collect();
+            match xs.as_slice() {
+                [inner @ ParsedMeasure::Group(_)] => inner.clone(),
+                [inner] if common_swing(inner).is_none() && own_humanize(inner).is_none() => inner.clone(),
+                _ => ParsedMeasure::Group(xs),
+            }
+        }
+        ParsedMeasure::Single(Single::Alternate(a)) => match a.0.as_slice() {
+            [only] => normalize_element(&only.to_parsed_measure()),
+            options => ParsedMeasure::Single(Single::Alternate(Alternate(
+                options.iter().map(normalize_primitive).collect(),
+            ))),
+        },
+        event => event.clone(),
+    }
+}
+
+fn normalize_primitive(primitive_group: &PrimitiveGroup) -> PrimitiveGroup {
+    match primitive_group {
+        PrimitiveGroup::Group(xs) => {
+            let xs: Vec<PrimitiveGroup> = xs.iter().map(normalize_primitive).collect();
+            match xs.as_slice() {
+                [inner] => inner.clone(),
+                _ => PrimitiveGroup::Group(xs),
+            }
+        }
+        event => event.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{arguments, normalize, print, spacing};
+    use crate::pattern::limits::Limits;
+    use crate::pattern::parser::{parse, parse_complete};
+
+    // Every construct of the language, printing the parsed sequence gives the same sequence back
+    const INPUTS: &[&str] = &[
+        "a b c",
+        "",
+        "a ~ [b c] [[d e] f]",
+        "a?50 b?0 0?0 ~ -3 c?100",
+        "hh:r3 hh:r3:v0.5 hh:r2:p0.25 sn>10 sn<5 sn@+0.02 sn^-0.5 a_ _ _ b",
+        "<a,b> <[a b],c?20,~> <0..3,7>",
+        "bd(3,8) bd(<3,5>,8,<0,2>) [bd sn](3,8,1)",
+        "a*2 [a b]*3 a!3 [a b]!2",
+        "0..4 7..0 0..12:3",
+        "rev[a b c] rot(1)[a b c] rot(-1)[a [b c]] palin[a b c]",
+        "arp(up, 0 4 7) arp(updown, 0 <3,4> 7)",
+        "every(4, rev, [a b c d]) whenmod(8, 6, fast 2, a b c d) sn@1:4",
+        "swing(16, 0.6)[a b [c d] e] f swing(8, 0.7)[g swing(16, 0.6)[h i]]",
+        "swing(16, 0.6)[a] [swing(16, 0.6)[b] c]",
+        "humanize(10, 20)[a b [c d]] e humanize(5, 0, 7)[<f,g> humanize(1, 2)[h]]",
+        "swing(8, 0.6)[humanize(1, 2)[a b]] humanize(1, 2)[swing(8, 0.6)[c d]] humanize(1, 2)[[a] [b]]",
+        "~:r2 a?50:r2:v0.5>3_ _ [b_ _] _",
+        "{a b c}%4 | {a <b,c> [d e]}%2",
+        "\"0 2 4\" + \"<0,12>\" | (0 3 7) + 5 - 1 * 2 | \"0 1\" * (2 3) | \"{0 1 2}%2\" + 1",
+        "\"(0 1) + 2\" - 1",
+        "(3/4) a b c | a b @2 @140bpm | a @1/3",
+        "a b | c d / 4",
+        "@scale=dorian root=c3 swing=16:0.6 bpm=120 0 2 4 | 1",
+        "@scale=ionian 0 2 4",
+        "scale(\"minor\", \"0 2 4 <5,7>\")",
+    ];
+
+    #[test]
+    fn round_trip() {
+        for input in INPUTS {
+            let parsed =
+                parse_complete(input, &Limits::default()).unwrap_or_else(|_| panic!("{} does not parse", input));
+            let printed = print(&parsed);
+            assert_eq!(
+                Ok(parsed),
+                parse_complete(&printed, &Limits::default()),
+                "{} printed as {}",
+                input,
+                printed
+            );
+        }
+    }
+
+    #[test]
+    fn formatted_round_trip() {
+        let limits = Limits::default();
+        for input in INPUTS {
+            let formatted = arguments(&spacing(input));
+            assert_eq!(
+                parse_complete(input, &limits),
+                parse_complete(&formatted, &limits),
+                "{} formatted as {}",
+                input,
+                formatted
+            );
+            assert_eq!(formatted, arguments(&spacing(&formatted)));
+        }
+    }
+
+    #[test]
+    fn normalized_round_trip() {
+        for input in INPUTS {
+            let printed = print(&normalize(parse(input, &Limits::default()).unwrap()));
+            let normalized = normalize(parse_complete(&printed, &Limits::default()).unwrap());
+            assert_eq!(printed, print(&normalized), "{}", input);
+        }
+    }
+
+    #[test]
+    fn expanded() {
+        let printed = |input: &str| print(&normalize(parse(input, &Limits::default()).unwrap()));
+        assert_eq!("bd ~ ~ bd ~ ~ bd ~", printed("bd(3,8)"));
+        assert_eq!("[a b] [a b]", printed("[a b]!2"));
+        assert_eq!("a b", printed("[[a b]]"));
+        assert_eq!("a [b c] d e", printed("[a] [[b c]] <[[d]]> <e>"));
+        assert_eq!("swing(16, 0.6)[a] b", printed("[swing(16, 0.6)[a]] b"));
+        assert_eq!("humanize(1, 2)[a] b", printed("[humanize(1,2)[a]] b"));
+        assert_eq!("\"0 2\" + 1", printed("\"[0 2]\" + \"[1]\""));
+        assert_eq!("@scale=minor root=62 0 1", printed("@scale=aeolian root=62 0 1"));
+        assert_eq!("@scale=minor 0 1", printed("scale(\"minor\", \"0 1\")"));
+        assert_eq!("0 @120bpm | 1", printed("@bpm=120 0 | 1"));
+    }
+
+    #[test]
+    fn spaces() {
+        assert_eq!(
+            "[a b] <a,b> swing(16,0.6)[a]",
+            spacing("  [ a   b ]\n<a , b>\tswing( 16 , 0.6 )[a] ")
+        );
+        assert_eq!("a b | c / 2", spacing("a  b |  c / 2"));
+    }
+}
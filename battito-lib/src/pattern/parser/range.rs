@@ -0,0 +1,25 @@
+use super::expansion::range::Range;
+use super::{map_limited, ParserResult};
+use crate::pattern::error::Error;
+use crate::pattern::limits::Limits;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, digit1};
+use nom::combinator::{cut, opt, recognize};
+use nom::sequence::{pair, preceded, terminated, tuple};
+
+// 0..7, 7..0, 0..12:3
+pub(crate) fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Range> {
+    let (input, start) = terminated(parser_integer, tag(".."))(input)?;
+    // Once ".." is found this must be a range, an invalid one is an error
+    cut(map_limited(
+        tuple((parser_integer, opt(preceded(char(':'), digit1)))),
+        move |(end, step): (&str, Option<&str>)| -> Result<Range, Error> {
+            let step: Option<u32> = step.map(|s| s.parse()).transpose()?;
+            Range::create(start.parse()?, end.parse()?, step, limits)
+        },
+    ))(input)
+}
+
+fn parser_integer(input: &str) -> ParserResult<'_, &str> {
+    recognize(pair(opt(char('-')), digit1))(input)
+}
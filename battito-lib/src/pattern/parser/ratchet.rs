@@ -0,0 +1,27 @@
+use crate::pattern::error::Error;
+use crate::pattern::parser::ParserResult;
+use crate::pattern::ratchet::{Decay, Ratchet};
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, digit1};
+use nom::combinator::{cut, map, map_res, opt};
+use nom::number::complete::double;
+use nom::sequence::{pair, preceded};
+
+// hh:r3, hh:r3:v0.5, hh:r3:p0.5
+pub(crate) fn parser_ratchet(input: &str) -> ParserResult<'_, Ratchet> {
+    preceded(
+        tag(":r"),
+        cut(map_res(
+            pair(digit1, opt(preceded(char(':'), parser_decay))),
+            |(count, decay): (&str, _)| -> Result<Ratchet, Error> { Ratchet::create(count.parse()?, decay) },
+        )),
+    )(input)
+}
+
+fn parser_decay(input: &str) -> ParserResult<'_, Decay> {
+    alt((
+        map(preceded(char('v'), double), Decay::Velocity),
+        map(preceded(char('p'), double), Decay::Probability),
+    ))(input)
+}
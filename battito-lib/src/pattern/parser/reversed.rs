@@ -0,0 +1,15 @@
+use super::expansion::reversed::Reversed;
+use super::{parser_group, ParserResult};
+use crate::pattern::limits::Limits;
+use nom::bytes::complete::tag;
+use nom::character::complete::char;
+use nom::combinator::map;
+use nom::sequence::delimited;
+
+// rev[a b c]
+pub(crate) fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Reversed> {
+    map(
+        delimited(tag("rev["), |i| parser_group(i, limits), char(']')),
+        |value| Reversed { value },
+    )(input)
+}
@@ -0,0 +1,25 @@
+use super::expansion::rotated::Rotated;
+use super::{parser_group, ParserResult};
+use crate::pattern::error::Error;
+use crate::pattern::limits::Limits;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, digit1};
+use nom::combinator::{map_res, opt, recognize};
+use nom::sequence::{delimited, pair, tuple};
+
+// rot(1)[a b c d]
+pub(crate) fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Rotated> {
+    map_res(
+        tuple((
+            delimited(tag("rot("), recognize(pair(opt(char('-')), digit1)), tag(")[")),
+            |i| parser_group(i, limits),
+            char(']'),
+        )),
+        |(amount, value, _): (&str, _, _)| -> Result<Rotated, Error> {
+            Ok(Rotated {
+                value,
+                amount: amount.parse()?,
+            })
+        },
+    )(input)
+}
@@ -0,0 +1,43 @@
+use super::{parser_sequence, ParsedSequence, ParserResult};
+use crate::pattern::error::Error;
+use crate::pattern::limits::Limits;
+use crate::pattern::scale::{parse_root, Scale};
+use nom::bytes::complete::{tag, take_while1};
+use nom::character::complete::{char, space0};
+use nom::combinator::{cut, map_res, opt};
+use nom::sequence::{delimited, preceded, terminated, tuple};
+
+// scale("minor", "0 2 4 <5,7>"), or with a root: scale("minor", "c3", "0 2 4 <5,7>")
+pub(crate) fn parser_scale<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedSequence> {
+    preceded(tag("scale(\""), cut(|i| parser_scale_arguments(i, limits)))(input)
+}
+
+fn parser_separator(input: &str) -> ParserResult<'_, (char, char, &str)> {
+    tuple((char('"'), char(','), space0))(input)
+}
+
+// The root is told apart from a sequence by the argument following it
+fn parser_root(input: &str) -> ParserResult<'_, &str> {
+    delimited(
+        char('"'),
+        take_while1(|c: char| c.is_alphanumeric() || c == '#' || c == '-'),
+        parser_separator,
+    )(input)
+}
+
+fn parser_scale_arguments<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, ParsedSequence> {
+    map_res(
+        tuple((
+            terminated(take_while1(|c: char| c.is_alphanumeric() || c == '_'), parser_separator),
+            opt(parser_root),
+            delimited(char('"'), |i| parser_sequence(i, limits), tag("\")")),
+        )),
+        |(name, root, sequence): (&str, Option<&str>, ParsedSequence)| -> Result<ParsedSequence, Error> {
+            let root = root.map(parse_root).transpose()?;
+            Ok(ParsedSequence {
+                scale: Some(Scale::new(name, root)?),
+                ..sequence
+            })
+        },
+    )(input)
+}
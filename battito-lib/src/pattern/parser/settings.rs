@@ -0,0 +1,86 @@
+use crate::pattern::error::{Error, ParsingError};
+use crate::pattern::limits::Limits;
+use crate::pattern::rational::Rational;
+use crate::pattern::sequence::MeasureSettings;
+use crate::pattern::tempo::Tempo;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, digit1};
+use nom::combinator::{cut, map, map_res, opt, peek};
+use nom::multi::many0;
+use nom::number::complete::double;
+use nom::sequence::{pair, preceded, terminated, tuple};
+
+use super::parsed_measure::Parsed;
+use super::{parser_measure, ParserResult};
+
+enum Annotation {
+    Length(Rational),
+    Bpm(f64),
+}
+
+// "3/4" or "2", in cycles
+fn parser_fraction(input: &str) -> ParserResult<'_, Rational> {
+    map_res(
+        pair(digit1, opt(preceded(char('/'), digit1))),
+        |(n, d): (&str, Option<&str>)| -> Result<Rational, Error> {
+            let numerator: i64 = n.parse()?;
+            let denominator: i64 = d.map(|d| d.parse()).transpose()?.unwrap_or(1);
+            if numerator == 0 || denominator == 0 {
+                Err(Error::DSLParsingError(ParsingError::LengthError))
+            } else {
+                Ok(Rational::new(numerator, denominator))
+            }
+        },
+    )(input)
+}
+
+// Same range as the tempo given from outside, see `Tempo::new`
+pub(crate) fn parse_bpm(bpm: f64) -> Result<f64, Error> {
+    Tempo::new(bpm, Tempo::default().cycles_per_bar).map(|tempo| tempo.bpm)
+}
+
+// "(3/4) a b c", the fraction is required to tell it apart from an arithmetic group
+fn parser_length_prefix(input: &str) -> ParserResult<'_, Rational> {
+    preceded(
+        pair(char('('), peek(pair(digit1, char('/')))),
+        cut(terminated(parser_fraction, tag(") "))),
+    )(input)
+}
+
+// "a b c @3/4", "a b c @140bpm"
+fn parser_annotation(input: &str) -> ParserResult<'_, Annotation> {
+    preceded(
+        tag(" @"),
+        alt((
+            map_res(terminated(double, tag("bpm")), |bpm| {
+                parse_bpm(bpm).map(Annotation::Bpm)
+            }),
+            cut(map(parser_fraction, Annotation::Length)),
+        )),
+    )(input)
+}
+
+pub(crate) fn parser_measure_with_settings<'a>(
+    input: &'a str,
+    limits: &Limits,
+) -> ParserResult<'a, (Parsed, MeasureSettings)> {
+    map(
+        tuple((
+            opt(parser_length_prefix),
+            |i| parser_measure(i, limits),
+            many0(parser_annotation),
+        )),
+        |(prefix, measure, annotations)| {
+            let mut settings = MeasureSettings {
+                length: prefix.unwrap_or_else(|| Rational::integer(1)),
+                ..MeasureSettings::default()
+            };
+            annotations.into_iter().for_each(|a| match a {
+                Annotation::Length(length) => settings.length = length,
+                Annotation::Bpm(bpm) => settings.bpm = Some(bpm),
+            });
+            (measure, settings)
+        },
+    )(input)
+}
@@ -0,0 +1,28 @@
+use super::expansion::swung::Swung;
+use super::{parser_group, ParserResult};
+use crate::pattern::limits::Limits;
+use crate::pattern::swing::Swing;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, digit1, space0};
+use nom::combinator::{cut, map, map_res};
+use nom::number::complete::double;
+use nom::sequence::{delimited, preceded, separated_pair, terminated, tuple};
+
+// swing(16, 0.6)[a b c d]
+pub(crate) fn parser<'a>(input: &'a str, limits: &Limits) -> ParserResult<'a, Swung> {
+    map(
+        tuple((
+            preceded(tag("swing("), cut(terminated(parser_swing, tag(")[")))),
+            |i| parser_group(i, limits),
+            char(']'),
+        )),
+        |(swing, value, _)| Swung { value, swing },
+    )(input)
+}
+
+fn parser_swing(input: &str) -> ParserResult<'_, Swing> {
+    map_res(
+        separated_pair(digit1, delimited(space0, char(','), space0), double),
+        |(grid, amount): (&str, f64)| Swing::create(grid.parse()?, amount),
+    )(input)
+}
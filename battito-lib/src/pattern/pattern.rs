@@ -1,6 +1,11 @@
-use crate::pattern::measure::Event;
-use serde::Serialize;
+use crate::pattern::measure::{Event, Modifiers, DEFAULT_VELOCITY};
+use serde::{Deserialize, Serialize};
 
+use super::binary;
+use super::error::Error;
+use super::limits::Limits;
+use super::rational::Rational;
+use super::tempo::Time;
 use super::OutputFormat;
 
 #[derive(Debug, PartialEq, Serialize)]
@@ -10,33 +15,119 @@ pub struct Payload {
 }
 
 // Event associated to a particular time index
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct TimedEvent {
     pub index: u32,
+    pub duration: u32, // in ticks
+    // Events coming from the same ratcheted step share the same id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ratchet: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<Time>,
     pub event: Event,
 }
 
 impl TimedEvent {
-    pub fn new(index: u32, value: &str, probability: u8) -> Self {
+    pub fn new(index: u32, value: &str, probability: u8, duration: u32) -> Self {
         TimedEvent {
             index,
+            duration,
+            ratchet: None,
+            time: None,
             event: Event {
                 value: value.into(),
                 probability,
+                velocity: DEFAULT_VELOCITY,
+                modifiers: Modifiers::default(),
             },
         }
     }
 
-    pub fn max_format(&self) -> String {
-        format!("{} {} {}", self.index, self.event.value, self.event.probability)
+    pub fn with_ratchet(self, id: u32) -> Self {
+        TimedEvent {
+            ratchet: Some(id),
+            ..self
+        }
+    }
+
+    pub fn with_velocity(mut self, velocity: u8) -> Self {
+        self.event.velocity = velocity;
+        self
+    }
+
+    pub fn with_time(self, onset_ms: f64, duration_ms: f64) -> Self {
+        TimedEvent {
+            time: Some(Time { onset_ms, duration_ms }),
+            ..self
+        }
+    }
+
+    // "index value probability", followed by onset and duration in milliseconds when there is a
+    // tempo, then by the ratchet id when the pattern has ratchets, -1 for the events without one.
+    // Every line of a pattern has the same columns
+    pub fn max_format(&self, ratchet_column: bool) -> String {
+        let mut format = format!("{} {} {}", self.index, self.event.value, self.event.probability);
+        if let Some(time) = self.time {
+            format.push_str(&format!(" {} {}", time.onset_ms, time.duration_ms));
+        }
+        match self.ratchet {
+            Some(id) => format.push_str(&format!(" {}", id)),
+            None if ratchet_column => format.push_str(" -1"),
+            None => (),
+        }
+        format
+    }
+
+    // Row of the CSV output, the columns are `CSV_HEADER`. Ratchet and times are empty when the
+    // event has none
+    pub fn csv_format(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        format!(
+            "{},{},{},{},{},{},{},{}",
+            self.index,
+            csv_field(&self.event.value),
+            self.event.probability,
+            self.duration,
+            self.event.velocity,
+            optional(self.ratchet.map(|id| id.to_string())),
+            optional(self.time.map(|time| time.onset_ms.to_string())),
+            optional(self.time.map(|time| time.duration_ms.to_string())),
+        )
     }
 }
 
-#[derive(Debug, PartialEq, Serialize)]
+// Only the events are written, the length of the pattern, its subdivision and its measures are left
+// out of the CSV output. JSON and MessagePack carry them
+pub const CSV_HEADER: &str = "index,value,probability,duration,velocity,ratchet,onset_ms,duration_ms";
+
+// Quoted when it holds a separator, a quote or a line break
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+// Occurrence of an event of a looping pattern, as returned by Pattern::query
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Occurrence {
+    // Absolute position and duration, in cycles
+    pub onset: Rational,
+    pub duration: Rational,
+    // Number of the loop the event belongs to, starting from 0
+    pub repetition: i64,
+    pub timed_event: TimedEvent,
+}
+
+// Serialized with a schema version, see `schema`
+#[derive(Debug, PartialEq)]
 pub struct Pattern {
+    // Sorted by index
     pub steps: Vec<TimedEvent>,
-    pub length: u32,
+    pub length: Rational, // in cycles
     pub subdivision: u32,
+    // Index of the first tick of every measure
+    pub measures: Vec<u32>,
 }
 
 impl Pattern {
@@ -44,7 +135,8 @@ impl Pattern {
         match output_format {
             OutputFormat::Json => serde_json::to_string_pretty(self).unwrap(),
             OutputFormat::Max => {
-                let strings: Vec<String> = self.steps.iter().map(|e| e.max_format()).collect();
+                let ratchet_column = self.steps.iter().any(|e| e.ratchet.is_some());
+                let strings: Vec<String> = self.steps.iter().map(|e| e.max_format(ratchet_column)).collect();
                 strings.join(", ")
                 // json!({
                 //     "steps": strings.join(", "),
@@ -53,31 +145,107 @@ impl Pattern {
                 // })
                 // .to_string()
             }
+            OutputFormat::Csv => std::iter::once(CSV_HEADER.to_string())
+                .chain(self.steps.iter().map(|e| e.csv_format()))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            // Binary formats are written in hexadecimal, see `encode` for their bytes
+            OutputFormat::MessagePack | OutputFormat::Binary => self
+                .encode(output_format)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
         }
     }
 
-    pub fn fill(&self) -> Vec<Event> {
-        let buffer_length = (self.length * self.subdivision) as usize;
-        let mut filled_steps: Vec<Event> = vec![Event::empty(); buffer_length];
+    // The pattern in the given format, text formats as UTF-8
+    pub fn encode(&self, output_format: OutputFormat) -> Vec<u8> {
+        match output_format {
+            OutputFormat::MessagePack => rmp_serde::to_vec_named(self).unwrap(),
+            OutputFormat::Binary => binary::write(self),
+            _ => self.format_steps(output_format).into_bytes(),
+        }
+    }
 
-        self.steps.iter().for_each(|te| {
-            filled_steps[(te.index - 1) as usize] = te.event.clone();
-        });
+    // Measure boundaries for Max, e.g. "1 1441 3361"
+    pub fn format_measures(&self) -> String {
+        let strings: Vec<String> = self.measures.iter().map(|m| m.to_string()).collect();
+        strings.join(" ")
+    }
 
-        filled_steps
+    // Events with an onset in [start, end), in cycles from the start of the first loop. The
+    // pattern loops forever in both directions, so the span can cross the loop point. Only the
+    // steps of each repetition falling in the span are read, and the result is bounded by
+    // `limits.max_events`
+    pub fn query(&self, start: Rational, end: Rational, limits: &Limits) -> Result<Vec<Occurrence>, Error> {
+        if self.length <= Rational::zero() || end <= start || self.steps.is_empty() {
+            return Ok(vec![]);
+        }
+        let subdivision = Rational::from(self.subdivision);
+        let first = start.checked_div(self.length)?.floor();
+        let last = end.checked_div(self.length)?.ceil();
+        let mut occurrences = Vec::new();
+        for repetition in first..last {
+            let offset = Rational::integer(repetition).checked_mul(self.length)?;
+            // Ticks of the span in this repetition, steps being sorted by index
+            let from = start.checked_sub(offset)?.checked_mul(subdivision)?.ceil();
+            let to = end.checked_sub(offset)?.checked_mul(subdivision)?.ceil();
+            let tick = |te: &TimedEvent| te.index as i64 - 1;
+            let steps = &self.steps[self.steps.partition_point(|te| tick(te) < from)..];
+            let steps = &steps[..steps.partition_point(|te| tick(te) < to)];
+            limits.check_events(occurrences.len().saturating_add(steps.len()))?;
+            for te in steps {
+                occurrences.push(Occurrence {
+                    onset: offset.checked_add(Rational::from(te.index - 1).checked_div(subdivision)?)?,
+                    duration: Rational::from(te.duration).checked_div(subdivision)?,
+                    repetition,
+                    timed_event: te.clone(),
+                });
+            }
+        }
+        Ok(occurrences)
+    }
+
+    // Length of the pattern in ticks, cut at u32::MAX for lengths that do not fit
+    pub fn ticks(&self) -> u32 {
+        self.length
+            .ticks(self.subdivision)
+            .map_or(u32::MAX, |ticks| ticks.clamp(0, u32::MAX as i64) as u32)
+    }
+
+    // Event sounding at every tick, empty when nothing starts there. Ticks are produced one at a
+    // time, when more steps start at the same tick the last one wins
+    pub fn events(&self) -> impl Iterator<Item = Event> + '_ {
+        let mut steps = self.steps.iter().peekable();
+        (1..=self.ticks()).map(move |tick| {
+            let mut event = None;
+            while let Some(te) = steps.next_if(|te| te.index <= tick) {
+                if te.index == tick {
+                    event = Some(te);
+                }
+            }
+            event.map_or_else(Event::empty, |te| te.event.clone())
+        })
     }
 
     pub fn empty(subdivision: u32) -> Pattern {
         Pattern {
-            steps: vec![TimedEvent {
-                index: 1,
-                event: Event {
-                    value: "0".into(),
-                    probability: 0,
-                },
-            }],
-            length: 1,
+            steps: vec![TimedEvent::new(1, "0", 0, subdivision)],
+            length: Rational::integer(1),
             subdivision,
+            measures: vec![1],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::csv_field;
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!("bd", csv_field("bd"));
+        assert_eq!("\"a,b\"", csv_field("a,b"));
+        assert_eq!("\"say \"\"hi\"\"\"", csv_field("say \"hi\""));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::pattern::error::{Error, ParsingError};
+use crate::pattern::pattern::TimedEvent;
+
+// hh:r3 plays hh three times inside its step, hh:r3:v0.5 halves the velocity of every repetition
+// and hh:r3:p0.5 halves its probability
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Ratchet {
+    pub count: u32,
+    pub decay: Option<Decay>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Decay {
+    Velocity(f64),
+    Probability(f64),
+}
+
+impl Ratchet {
+    pub fn create(count: u32, decay: Option<Decay>) -> Result<Ratchet, Error> {
+        let valid_decay = match decay {
+            Some(Decay::Velocity(d)) | Some(Decay::Probability(d)) => d > 0.0 && d <= 1.0,
+            None => true,
+        };
+        if count == 0 || !valid_decay {
+            Err(Error::DSLParsingError(ParsingError::RatchetError))
+        } else {
+            Ok(Ratchet { count, decay })
+        }
+    }
+
+    // Split the event in `count` repetitions sharing the ratchet group `id`, the last repetition
+    // takes the ticks left by the integer division of the duration
+    pub fn split(&self, timed_event: &TimedEvent, id: u32) -> Vec<TimedEvent> {
+        let count = self.count.min(timed_event.duration.max(1));
+        let step = timed_event.duration / count;
+        (0..count)
+            .map(|k| {
+                let mut event = timed_event.event.clone();
+                match self.decay {
+                    Some(Decay::Velocity(d)) => {
+                        event.velocity = (event.velocity as f64 * d.powi(k as i32)).round() as u8
+                    }
+                    Some(Decay::Probability(d)) => {
+                        event.probability = (event.probability as f64 * d.powi(k as i32)).round() as u8
+                    }
+                    None => (),
+                }
+                TimedEvent {
+                    index: timed_event.index + k * step,
+                    duration: if k == count - 1 {
+                        timed_event.duration - k * step
+                    } else {
+                        step
+                    },
+                    ratchet: Some(id),
+                    time: timed_event.time,
+                    event,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decay, Ratchet};
+    use crate::pattern::pattern::TimedEvent;
+
+    #[test]
+    fn split() {
+        let te = TimedEvent::new(481, "hh", 100, 480);
+        let ratchet = Ratchet::create(3, Some(Decay::Probability(0.5))).unwrap();
+        let expected = vec![
+            TimedEvent::new(481, "hh", 100, 160).with_ratchet(2),
+            TimedEvent::new(641, "hh", 50, 160).with_ratchet(2),
+            TimedEvent::new(801, "hh", 25, 160).with_ratchet(2),
+        ];
+        assert_eq!(expected, ratchet.split(&te, 2));
+
+        let ratchet = Ratchet::create(4, Some(Decay::Velocity(0.5))).unwrap();
+        let velocities: Vec<u8> = ratchet.split(&te, 0).iter().map(|te| te.event.velocity).collect();
+        assert_eq!(vec![100, 50, 25, 13], velocities);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Ratchet::create(0, None).is_err());
+        assert!(Ratchet::create(2, Some(Decay::Velocity(1.5))).is_err());
+        assert!(Ratchet::create(2, Some(Decay::Probability(0.0))).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::pattern::error::{Error, LimitError};
+use gcd::Gcd;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+// Exact fraction of cycles, always stored reduced and with a positive denominator
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Rational {
+    numerator: i64,
+    denominator: i64,
+}
+
+impl Rational {
+    pub fn new(numerator: i64, denominator: i64) -> Rational {
+        assert!(denominator != 0, "Rational with zero denominator");
+        Rational::reduced(numerator.into(), denominator.into()).expect("Rational out of range")
+    }
+
+    // Reduced from a wider fraction, failing when it does not fit in an i64 fraction
+    fn reduced(numerator: i128, denominator: i128) -> Result<Rational, Error> {
+        if denominator == 0 {
+            return Err(Error::UnexpectedError);
+        }
+        let sign = if denominator < 0 { -1 } else { 1 };
+        let divisor = (numerator.unsigned_abs().gcd(denominator.unsigned_abs()) as i128).max(1);
+        let fit = |n: i128| i64::try_from(sign * n / divisor).map_err(|_| Error::LimitError(LimitError::Overflow));
+        Ok(Rational {
+            numerator: fit(numerator)?,
+            denominator: fit(denominator)?,
+        })
+    }
+
+    pub fn integer(n: i64) -> Rational {
+        Rational::new(n, 1)
+    }
+
+    pub fn zero() -> Rational {
+        Rational::integer(0)
+    }
+
+    pub fn numerator(&self) -> i64 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> i64 {
+        self.denominator
+    }
+
+    pub fn floor(&self) -> i64 {
+        self.numerator.div_euclid(self.denominator)
+    }
+
+    pub fn ceil(&self) -> i64 {
+        match self.numerator.rem_euclid(self.denominator) {
+            0 => self.floor(),
+            _ => self.floor() + 1,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    // Nearest tick for a subdivision of the cycle, halves are rounded up
+    pub fn ticks(&self, subdivision: u32) -> Result<i64, Error> {
+        let twice = 2 * self.numerator as i128 * subdivision as i128 + self.denominator as i128;
+        i64::try_from(twice.div_euclid(2 * self.denominator as i128))
+            .map_err(|_| Error::LimitError(LimitError::Overflow))
+    }
+
+    // The operations are done on i128 and fail with an overflow when the reduced result does not
+    // fit, so that lengths and onsets taken from the input cannot wrap around
+    pub fn checked_add(self, other: Rational) -> Result<Rational, Error> {
+        Rational::reduced(
+            self.numerator as i128 * other.denominator as i128 + other.numerator as i128 * self.denominator as i128,
+            self.denominator as i128 * other.denominator as i128,
+        )
+    }
+
+    pub fn checked_sub(self, other: Rational) -> Result<Rational, Error> {
+        Rational::reduced(
+            self.numerator as i128 * other.denominator as i128 - other.numerator as i128 * self.denominator as i128,
+            self.denominator as i128 * other.denominator as i128,
+        )
+    }
+
+    pub fn checked_mul(self, other: Rational) -> Result<Rational, Error> {
+        Rational::reduced(
+            self.numerator as i128 * other.numerator as i128,
+            self.denominator as i128 * other.denominator as i128,
+        )
+    }
+
+    // Fails on a division by zero too
+    pub fn checked_div(self, other: Rational) -> Result<Rational, Error> {
+        Rational::reduced(
+            self.numerator as i128 * other.denominator as i128,
+            self.denominator as i128 * other.numerator as i128,
+        )
+    }
+}
+
+impl From<u32> for Rational {
+    fn from(n: u32) -> Self {
+        Rational::integer(n as i64)
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.numerator as i128 * other.denominator as i128).cmp(&(other.numerator as i128 * self.denominator as i128))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// "3", "7/4"
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.denominator {
+            1 => write!(f, "{}", self.numerator),
+            d => write!(f, "{}/{}", self.numerator, d),
+        }
+    }
+}
+
+impl Serialize for Rational {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+// Inverse of Display, the denominator must not be zero
+impl FromStr for Rational {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid fraction \"{}\"", s);
+        let (numerator, denominator) = match s.split_once('/') {
+            Some((n, d)) => (n, d.parse::<i64>().map_err(|_| invalid())?),
+            None => (s, 1),
+        };
+        let numerator = numerator.parse::<i64>().map_err(|_| invalid())?;
+        // Neither a zero denominator nor i64::MIN over a negative one, whose sign cannot be moved
+        Rational::reduced(numerator.into(), denominator.into()).map_err(|_| invalid())
+    }
+}
+
+struct RationalVisitor;
+
+impl<'de> Visitor<'de> for RationalVisitor {
+    type Value = Rational;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a fraction such as \"7/4\" or an integer")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Rational::integer(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        i64::try_from(v).map(Rational::integer).map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rational {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(RationalVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rational;
+    use crate::pattern::error::{Error, LimitError};
+
+    #[test]
+    fn arithmetic() {
+        let three_quarters = Rational::new(3, 4);
+        assert_eq!(Rational::new(6, 8), three_quarters);
+        assert_eq!(
+            Ok(Rational::new(7, 4)),
+            three_quarters.checked_add(Rational::integer(1))
+        );
+        assert_eq!(
+            Ok(Rational::new(-1, 4)),
+            three_quarters.checked_sub(Rational::integer(1))
+        );
+        assert_eq!(Ok(Rational::new(3, 8)), three_quarters.checked_mul(Rational::new(1, 2)));
+        assert_eq!(Ok(Rational::new(3, 2)), three_quarters.checked_div(Rational::new(1, 2)));
+        assert_eq!(Rational::new(1, -2), Rational::new(-1, 2));
+        assert!(Rational::new(2, 3) < three_quarters);
+    }
+
+    #[test]
+    fn overflow() {
+        let overflow = Err(Error::LimitError(LimitError::Overflow));
+        let large = Rational::integer(i64::MAX);
+        assert_eq!(overflow, large.checked_mul(Rational::integer(2)));
+        assert_eq!(overflow, large.checked_add(Rational::integer(1)));
+        assert_eq!(
+            overflow,
+            Rational::new(1, 3037000500).checked_add(Rational::new(1, 3037000501))
+        );
+        // Reduced before it is checked
+        assert_eq!(Ok(Rational::integer(1)), large.checked_div(large));
+        assert_eq!(Err(Error::LimitError(LimitError::Overflow)), large.ticks(4));
+        assert!(Rational::integer(1).checked_div(Rational::zero()).is_err());
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!(Ok(Rational::new(7, 4)), "14/8".parse());
+        assert_eq!(Ok(Rational::integer(-3)), "-3".parse());
+        assert!("1/0".parse::<Rational>().is_err());
+        assert!("a/4".parse::<Rational>().is_err());
+        assert!("-9223372036854775808/-1".parse::<Rational>().is_err());
+        assert_eq!(Ok(Rational::integer(i64::MIN / 2)), "-9223372036854775808/2".parse());
+        assert_eq!(Ok(Rational::new(1, -2)), "-2/4".parse());
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(1, Rational::new(7, 4).floor());
+        assert_eq!(2, Rational::new(7, 4).ceil());
+        assert_eq!(-2, Rational::new(-7, 4).floor());
+        assert_eq!(Ok(1440), Rational::new(3, 4).ticks(1920));
+        assert_eq!(Ok(274), Rational::new(1, 7).ticks(1920));
+        assert_eq!("7/4", Rational::new(7, 4).to_string());
+        assert_eq!("2", Rational::new(4, 2).to_string());
+    }
+}
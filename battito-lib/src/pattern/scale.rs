@@ -0,0 +1,162 @@
+use crate::pattern::error::{Error, ParsingError, ScaleError};
+use crate::pattern::measure::{Event, Measure};
+
+// MIDI note used as degree 0 when no root is given (C4)
+pub const DEFAULT_ROOT: i32 = 60;
+
+// Highest MIDI note, the lowest being 0
+pub const MAX_NOTE: i32 = 127;
+
+const SCALES: &[(&str, &[i32])] = &[
+    ("major", &[0, 2, 4, 5, 7, 9, 11]),
+    ("ionian", &[0, 2, 4, 5, 7, 9, 11]),
+    ("minor", &[0, 2, 3, 5, 7, 8, 10]),
+    ("aeolian", &[0, 2, 3, 5, 7, 8, 10]),
+    ("dorian", &[0, 2, 3, 5, 7, 9, 10]),
+    ("phrygian", &[0, 1, 3, 5, 7, 8, 10]),
+    ("lydian", &[0, 2, 4, 6, 7, 9, 11]),
+    ("mixolydian", &[0, 2, 4, 5, 7, 9, 10]),
+    ("locrian", &[0, 1, 3, 5, 6, 8, 10]),
+    ("harmonic_minor", &[0, 2, 3, 5, 7, 8, 11]),
+    ("melodic_minor", &[0, 2, 3, 5, 7, 9, 11]),
+    ("major_pentatonic", &[0, 2, 4, 7, 9]),
+    ("minor_pentatonic", &[0, 3, 5, 7, 10]),
+    ("blues", &[0, 3, 5, 6, 7, 10]),
+    ("whole_tone", &[0, 2, 4, 6, 8, 10]),
+    ("chromatic", &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+];
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Scale {
+    pub steps: Vec<i32>,
+    pub root: i32,
+}
+
+impl Scale {
+    pub fn new(name: &str, root: Option<i32>) -> Result<Scale, Error> {
+        let steps = SCALES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, steps)| steps.to_vec())
+            .ok_or(Error::DSLParsingError(ParsingError::ScaleError(
+                ScaleError::UnknownScale,
+            )))?;
+        Ok(Scale {
+            steps,
+            root: root.unwrap_or(DEFAULT_ROOT),
+        })
+    }
+
+    // Name of the scale, the first one when several names have the same steps
+    pub fn name(&self) -> Option<&'static str> {
+        SCALES
+            .iter()
+            .find(|(_, steps)| *steps == self.steps.as_slice())
+            .map(|(name, _)| *name)
+    }
+
+    // Degrees outside the scale wrap to the neighbouring octaves: in a 7 notes scale 7 is the root
+    // one octave up and -1 is the last degree one octave down. Notes outside the MIDI range are an
+    // error
+    pub fn note(&self, degree: i32) -> Result<i32, Error> {
+        let len = self.steps.len() as i32;
+        let octave = degree.div_euclid(len) as i64;
+        let step = self.steps[degree.rem_euclid(len) as usize] as i64;
+        let note = self.root as i64 + 12 * octave + step;
+        match note {
+            n if (0..=MAX_NOTE as i64).contains(&n) => Ok(n as i32),
+            _ => Err(Error::DSLParsingError(ParsingError::ScaleError(
+                ScaleError::NoteOutOfRange,
+            ))),
+        }
+    }
+
+    // Map every integer value of the measure to a note, leaving rests and non numeric values untouched
+    pub fn apply(&self, measure: &Measure) -> Result<Measure, Error> {
+        measure.try_map_events(&|event| match event.value.parse::<i32>() {
+            Ok(degree) if event.probability != 0 => Ok(Event {
+                value: self.note(degree)?.to_string(),
+                ..event.clone()
+            }),
+            _ => Ok(event.clone()),
+        })
+    }
+}
+
+// Parse a root note either as a MIDI number ("48") or as a note name with octave ("c3", "f#2", "eb4"),
+// within the MIDI range
+pub fn parse_root(input: &str) -> Result<i32, Error> {
+    let invalid = || Error::DSLParsingError(ParsingError::ScaleError(ScaleError::InvalidRoot));
+    let in_range = |n: i32| match n {
+        n if (0..=MAX_NOTE).contains(&n) => Ok(n),
+        _ => Err(invalid()),
+    };
+    if let Ok(n) = input.parse::<i32>() {
+        return in_range(n);
+    }
+    let mut chars = input.chars();
+    let pitch_class = match chars.next().map(|c| c.to_ascii_lowercase()) {
+        Some('c') => 0,
+        Some('d') => 2,
+        Some('e') => 4,
+        Some('f') => 5,
+        Some('g') => 7,
+        Some('a') => 9,
+        Some('b') => 11,
+        _ => return Err(invalid()),
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = match rest.chars().next() {
+        Some('#') | Some('s') => (1, &rest[1..]),
+        Some('b') => (-1, &rest[1..]),
+        _ => (0, rest),
+    };
+    let octave: i32 = octave.parse().map_err(|_| invalid())?;
+    in_range(
+        octave
+            .saturating_add(1)
+            .saturating_mul(12)
+            .saturating_add(pitch_class + accidental),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_root, Scale};
+
+    #[test]
+    fn degrees() {
+        let minor = Scale::new("minor", Some(60)).unwrap();
+        assert_eq!(Ok(60), minor.note(0));
+        assert_eq!(Ok(63), minor.note(2));
+        assert_eq!(Ok(72), minor.note(7));
+        assert_eq!(Ok(75), minor.note(9));
+        assert_eq!(Ok(58), minor.note(-1));
+        assert_eq!(Ok(48), minor.note(-7));
+        assert_eq!(Ok(46), minor.note(-8));
+        assert_eq!(Ok(127), Scale::new("major", Some(0)).unwrap().note(74));
+        assert!(minor.note(40).is_err());
+        assert!(minor.note(-36).is_err());
+        assert!(minor.note(i32::MAX).is_err());
+        assert!(minor.note(i32::MIN).is_err());
+    }
+
+    #[test]
+    fn roots() {
+        assert_eq!(Ok(48), parse_root("c3"));
+        assert_eq!(Ok(60), parse_root("C4"));
+        assert_eq!(Ok(54), parse_root("f#3"));
+        assert_eq!(Ok(63), parse_root("eb4"));
+        assert_eq!(Ok(0), parse_root("c-1"));
+        assert_eq!(Ok(36), parse_root("36"));
+        assert!(parse_root("h3").is_err());
+        assert!(parse_root("c").is_err());
+        assert!(parse_root("128").is_err());
+        assert!(parse_root("c2147483647").is_err());
+    }
+
+    #[test]
+    fn unknown() {
+        assert!(Scale::new("klingon", None).is_err());
+    }
+}
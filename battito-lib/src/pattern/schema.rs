@@ -0,0 +1,200 @@
+use crate::pattern::pattern::{Pattern, TimedEvent};
+use crate::pattern::rational::Rational;
+use serde::de::{self, Deserializer};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// Version of the JSON layout of a pattern, written in every document. It changes when a field is
+// renamed, removed or changes meaning, receivers should refuse versions they do not know
+pub const SCHEMA_VERSION: u32 = 1;
+
+impl Serialize for Pattern {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Pattern", 5)?;
+        state.serialize_field("schema_version", &SCHEMA_VERSION)?;
+        state.serialize_field("steps", &self.steps)?;
+        state.serialize_field("length", &self.length)?;
+        state.serialize_field("subdivision", &self.subdivision)?;
+        state.serialize_field("measures", &self.measures)?;
+        state.end()
+    }
+}
+
+// A pattern as written by a given version of the schema
+#[derive(Deserialize)]
+struct Document {
+    schema_version: u32,
+    steps: Vec<TimedEvent>,
+    length: Rational,
+    subdivision: u32,
+    measures: Vec<u32>,
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let document = Document::deserialize(deserializer)?;
+        if document.schema_version != SCHEMA_VERSION {
+            return Err(de::Error::custom(format!(
+                "unsupported schema version {}, expected {}",
+                document.schema_version, SCHEMA_VERSION
+            )));
+        }
+        Ok(Pattern {
+            steps: document.steps,
+            length: document.length,
+            subdivision: document.subdivision,
+            measures: document.measures,
+        })
+    }
+}
+
+// JSON Schema (draft 07) of the current version, the one in schema/pattern.schema.json
+pub fn json_schema() -> Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Battito pattern",
+        "description": "Events of a pattern on a grid of ticks, as written by OutputFormat::Json",
+        "type": "object",
+        "required": ["schema_version", "steps", "length", "subdivision", "measures"],
+        "properties": {
+            "schema_version": {
+                "description": "Version of this layout, changed when a field is renamed, removed or changes meaning",
+                "const": SCHEMA_VERSION
+            },
+            "steps": {
+                "description": "Events in order of onset",
+                "type": "array",
+                "items": { "$ref": "#/definitions/step" }
+            },
+            "length": {
+                "description": "Length of the pattern in cycles",
+                "$ref": "#/definitions/fraction"
+            },
+            "subdivision": {
+                "description": "Ticks per cycle",
+                "type": "integer",
+                "minimum": 1
+            },
+            "measures": {
+                "description": "Index of the first tick of every measure",
+                "type": "array",
+                "items": { "type": "integer", "minimum": 1 }
+            }
+        },
+        "definitions": {
+            "fraction": {
+                "description": "Exact fraction such as \"3\" or \"7/4\"",
+                "type": "string",
+                "pattern": "^-?[0-9]+(/[0-9]+)?$"
+            },
+            "step": {
+                "type": "object",
+                "required": ["index", "duration", "event"],
+                "properties": {
+                    "index": {
+                        "description": "Tick of the onset, starting from 1",
+                        "type": "integer",
+                        "minimum": 1
+                    },
+                    "duration": {
+                        "description": "Duration in ticks",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "ratchet": {
+                        "description": "Shared by the events of the same ratcheted step, absent otherwise",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "time": {
+                        "description": "Onset and duration in milliseconds, present when a tempo is given",
+                        "$ref": "#/definitions/time"
+                    },
+                    "event": { "$ref": "#/definitions/event" }
+                }
+            },
+            "time": {
+                "type": "object",
+                "required": ["onset_ms", "duration_ms"],
+                "properties": {
+                    "onset_ms": { "type": "number" },
+                    "duration_ms": { "type": "number", "minimum": 0 }
+                }
+            },
+            "event": {
+                "type": "object",
+                "required": ["value", "probability"],
+                "properties": {
+                    "value": { "type": "string" },
+                    "probability": { "type": "integer", "minimum": 0, "maximum": 100 },
+                    "velocity": {
+                        "description": "Absent when it is the default",
+                        "type": "integer",
+                        "minimum": 0,
+                        "maximum": 127,
+                        "default": 100
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::json_schema;
+    use crate::pattern::pattern::Pattern;
+    use crate::pattern::tempo::Tempo;
+    use crate::pattern::{transform_with_options, Options, OutputFormat};
+    use jsonschema::JSONSchema;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn schema_document() {
+        let document: Value = serde_json::from_str(include_str!("../../schema/pattern.schema.json")).unwrap();
+        assert_eq!(json_schema(), document);
+    }
+
+    #[test]
+    fn valid_output() {
+        let schema = json_schema();
+        let schema = JSONSchema::compile(&schema).unwrap();
+        for (input, tempo) in &[
+            ("a b?50 c:r3:v0.5 humanize(0, 20)[e g] | f @3/4", None),
+            ("a_ _ b>10 | ~", Some(Tempo::default())),
+            ("", None),
+        ] {
+            let options = Options {
+                tempo: *tempo,
+                ..Options::default()
+            };
+            let json = transform_with_options(input, &options)
+                .unwrap()
+                .format_steps(OutputFormat::Json);
+            let document: Value = serde_json::from_str(&json).unwrap();
+            let errors: Vec<String> = match schema.validate(&document) {
+                Ok(()) => vec![],
+                Err(errors) => errors.map(|e| e.to_string()).collect(),
+            };
+            assert!(errors.is_empty(), "{}: {:?}", input, errors);
+        }
+        let invalid = json!({"schema_version": 1, "steps": [], "length": "1/0/2", "subdivision": 0, "measures": []});
+        assert!(!schema.is_valid(&invalid));
+    }
+
+    #[test]
+    fn unknown_version() {
+        let json = r#"{"schema_version": 2, "steps": [], "length": "1", "subdivision": 4, "measures": [1]}"#;
+        let error = serde_json::from_str::<Pattern>(json).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("unsupported schema version 2, expected 1"));
+    }
+}
@@ -1,47 +1,311 @@
-use crate::pattern::measure::Measure;
+use crate::pattern::error::{Error, LimitError};
+use crate::pattern::humanize::{Humanize, Humanizer};
+use crate::pattern::limits::Limits;
+use crate::pattern::measure::{Measure, Modifiers, Tie};
+use crate::pattern::nudge::Boundary;
 use crate::pattern::pattern::{Pattern, TimedEvent};
+use crate::pattern::rational::Rational;
+use crate::pattern::scale::Scale;
+use crate::pattern::swing::Swing;
+use crate::pattern::tempo::{Tempo, TempoMap};
 use crate::pattern::DEFAULT_SUBDIVISION;
+use std::convert::TryFrom;
 
+use super::parser::parsed_measure::Parsed;
 use super::parser::ParsedSequence;
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct MeasureSettings {
+    // In cycles
+    pub length: Rational,
+    // Tempo from this measure on
+    pub bpm: Option<f64>,
+}
+
+impl Default for MeasureSettings {
+    fn default() -> Self {
+        MeasureSettings {
+            length: Rational::integer(1),
+            bpm: None,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Sequence {
-    pub measures: Vec<Measure>,
+    // The measures are only expanded when they are needed, see `measures`. Every part comes with
+    // its number of measures
+    parts: Vec<(Parsed, MeasureSettings, usize)>,
+    scale: Option<Scale>,
     pub subdivision: u32,
-    pub length: u32,
+    // Total length in cycles, the measures are stretched to fill it
+    pub length: Rational,
+    pub swing: Option<Swing>,
+    pub boundary: Boundary,
+    pub humanize: Option<Humanize>,
+    // Onsets and durations are also given in milliseconds when there is a tempo
+    pub tempo: Option<Tempo>,
+    limits: Limits,
 }
 
 impl Sequence {
-    pub fn from_parsed_sequence(parsed_sequence: &ParsedSequence, subdivision: Option<u32>) -> Sequence {
-        Sequence::new(
-            &parsed_sequence.measures.iter().flat_map(|m| m.to_measures()).collect(),
-            parsed_sequence.length,
-            subdivision,
-        )
-    }
-    fn new(measures: &Vec<Measure>, length: Option<u32>, subdivision: Option<u32>) -> Sequence {
-        Sequence {
-            measures: measures.clone(),
+    pub fn from_parsed_sequence(
+        parsed_sequence: &ParsedSequence,
+        subdivision: Option<u32>,
+        limits: &Limits,
+    ) -> Result<Sequence, Error> {
+        let subdivision = subdivision.map(|s| limits.check_subdivision(s)).transpose()?;
+        let mut measures: usize = 0;
+        let parts = parsed_sequence
+            .measures
+            .iter()
+            .zip(parsed_sequence.settings.iter())
+            .map(|(parsed, settings)| {
+                let count = parsed.measure_count()?;
+                measures = limits.check_measures(measures.saturating_add(count))?;
+                Ok((parsed.clone(), settings.clone(), count))
+            })
+            .collect::<Result<Vec<(Parsed, MeasureSettings, usize)>, Error>>()?;
+        // The length in cycles is bounded like the number of measures
+        let length = parsed_sequence
+            .length
+            .map(|l| limits.check_measures(l as usize).map(|l| l as u32))
+            .transpose()?;
+        Ok(Sequence {
+            swing: parsed_sequence.swing,
+            scale: parsed_sequence.scale.clone(),
+            limits: *limits,
+            ..Sequence::new(parts, length, subdivision)?
+        })
+    }
+    fn new(
+        parts: Vec<(Parsed, MeasureSettings, usize)>,
+        length: Option<u32>,
+        subdivision: Option<u32>,
+    ) -> Result<Sequence, Error> {
+        let natural = parts.iter().try_fold(Rational::zero(), |acc, (_, s, count)| {
+            acc.checked_add(s.length.checked_mul(Rational::from(*count as u32))?)
+        })?;
+        Ok(Sequence {
+            parts,
+            scale: None,
+            tempo: None,
+            swing: None,
+            boundary: Boundary::default(),
+            humanize: None,
+            limits: Limits::default(),
             subdivision: subdivision.unwrap_or(DEFAULT_SUBDIVISION),
-            length: match length {
-                Some(l) if l > measures.len() as u32 => l,
-                _ => measures.len() as u32,
+            length: match length.map(Rational::from) {
+                Some(l) if l > natural => l,
+                _ => natural,
             },
-        }
+        })
     }
 
-    pub fn to_pattern(&self) -> Pattern {
-        let mut steps: Vec<TimedEvent> = Vec::new();
-        let mut i = 1;
-        self.measures.iter().for_each(|m| {
-            steps.extend(m.timed_events(i, self.length as f32 / self.measures.len() as f32, self.subdivision));
-            i = i + self.subdivision;
+    // Measures of the sequence, built one at a time
+    pub fn measures(&self) -> impl Iterator<Item = Result<Measure, Error>> + '_ {
+        self.parts
+            .iter()
+            .flat_map(|(parsed, _, count)| (0..*count).map(move |i| parsed.measure(i)))
+            .map(move |measure| {
+                measure.and_then(|m| match &self.scale {
+                    Some(scale) => scale.apply(&m),
+                    None => Ok(m),
+                })
+            })
+    }
+
+    fn settings(&self) -> impl Iterator<Item = &MeasureSettings> + '_ {
+        self.parts
+            .iter()
+            .flat_map(|(_, settings, count)| std::iter::repeat_n(settings, *count))
+    }
+
+    // Events of every measure at their nominal position, before ties, swing, nudges and ratchets.
+    // See `boundaries`
+    pub fn timed_events<'a>(&'a self, boundaries: &'a [u32]) -> impl Iterator<Item = Result<TimedEvent, Error>> + 'a {
+        self.measures()
+            .zip(boundaries.iter().zip(boundaries.iter().skip(1)))
+            .flat_map(|(measure, (&start, &end))| {
+                // The nesting of the groups has to fit in the arithmetic of the positions
+                let events = measure.and_then(|m| m.subdivision().map(|_| m.timed_events(start + 1, end - start)));
+                let events: Vec<Result<TimedEvent, Error>> = match events {
+                    Ok(events) => events.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                events
+            })
+    }
+
+    // The events go through the steps below one at a time, only the steps of the pattern are kept
+    pub fn to_pattern(&self) -> Result<Pattern, Error> {
+        let limits = self.limits;
+        let total =
+            u32::try_from(self.length.ticks(self.subdivision)?).map_err(|_| Error::LimitError(LimitError::Overflow))?;
+        let boundaries = self.boundaries()?;
+        // A sequence shorter than a tick has no room for its events
+        if total == 0 {
+            return Ok(Pattern {
+                steps: vec![],
+                length: self.length,
+                subdivision: self.subdivision,
+                measures: vec![1],
+            });
+        }
+        let events = self
+            .timed_events(&boundaries)
+            .enumerate()
+            .map(|(i, te)| limits.check_events(i + 1).and(te));
+
+        // Swing moves the events on the timeline, the swing of an event overrides the global one.
+        // Humanize and nudges are applied on top of the swung position
+        let mut humanizer = Humanizer::new(self.humanize, self.subdivision, total, self.boundary);
+        let placed = Ties::new(events).map(move |te| {
+            te.and_then(|mut te| {
+                if let Some(swing) = te.event.modifiers.swing.or(self.swing) {
+                    swing.apply(&mut te, self.subdivision as f64);
+                }
+                humanizer.apply(&mut te)?;
+                if let Some(nudge) = te.event.modifiers.nudge {
+                    nudge.apply(&mut te, self.subdivision, total, self.boundary)?;
+                }
+                Ok(te)
+            })
+        });
+
+        // Ratchets split a step once its final position is known, each step gets its own group id.
+        // Repetitions past the end of the sequence follow the boundary: they continue from its start
+        // with Wrap, and are dropped with Clamp as there is no room left for them
+        let mut ratchets = 0;
+        let split = placed.flat_map(move |te| {
+            let split: Vec<Result<TimedEvent, Error>> = match te {
+                Ok(mut te) => {
+                    let ratchet = te.event.modifiers.ratchet;
+                    te.event.modifiers = Modifiers::default();
+                    let split = match ratchet {
+                        Some(ratchet) => match limits.check_events(ratchet.count as usize) {
+                            Ok(_) => {
+                                ratchets += 1;
+                                ratchet.split(&te, ratchets - 1)
+                            }
+                            Err(e) => return vec![Err(e)],
+                        },
+                        None => vec![te],
+                    };
+                    split
+                        .into_iter()
+                        .filter_map(|mut te| match self.boundary {
+                            Boundary::Clamp if te.index > total => None,
+                            Boundary::Clamp => Some(Ok(te)),
+                            Boundary::Wrap => {
+                                te.index = (te.index - 1) % total + 1;
+                                Some(Ok(te))
+                            }
+                        })
+                        .collect()
+                }
+                Err(e) => vec![Err(e)],
+            };
+            split
         });
+        let mut steps = split
+            .enumerate()
+            .map(|(i, te)| limits.check_events(i + 1).and(te))
+            .collect::<Result<Vec<TimedEvent>, Error>>()?;
+        // Only nudges, humanize and wrapped ratchets move events out of order
+        if !steps.is_sorted_by_key(|te| te.index) {
+            steps.sort_by_key(|te| te.index);
+        }
 
-        Pattern {
+        // A tempo annotation in the sequence is enough to get the times, at the default tempo
+        let tempo = match self.tempo {
+            Some(tempo) => Some(tempo),
+            None if self.settings().any(|s| s.bpm.is_some()) => Some(Tempo::default()),
+            None => None,
+        };
+        if let Some(tempo) = tempo {
+            let changes = boundaries.iter().copied().zip(self.settings().map(|s| s.bpm));
+            let tempo_map = TempoMap::new(tempo, changes, self.subdivision);
+            steps.iter_mut().for_each(|te| te.time = Some(tempo_map.time(te)));
+        }
+
+        let mut measures: Vec<u32> = boundaries.iter().map(|b| b + 1).collect();
+        measures.pop();
+        Ok(Pattern {
             steps,
             length: self.length,
             subdivision: self.subdivision,
+            measures,
+        })
+    }
+
+    // Tick where every measure starts, followed by the end of the sequence. Positions are rounded
+    // from the exact ones, so that rounding errors do not add up along the sequence
+    fn boundaries(&self) -> Result<Vec<u32>, Error> {
+        let tick = |position: Rational| {
+            u32::try_from(position.ticks(self.subdivision)?).map_err(|_| Error::LimitError(LimitError::Overflow))
+        };
+        let natural = self
+            .settings()
+            .try_fold(Rational::zero(), |acc, s| acc.checked_add(s.length))?;
+        // Without measures the sequence still spans its length
+        if natural == Rational::zero() {
+            return Ok(vec![0, tick(self.length)?]);
+        }
+        let stretch = self.length.checked_div(natural)?;
+        let mut start = Rational::zero();
+        std::iter::once(Ok(0))
+            .chain(self.settings().map(|s| {
+                start = start.checked_add(s.length)?;
+                tick(start.checked_mul(stretch)?)
+            }))
+            .collect()
+    }
+}
+
+// Extends every tied event with the "_" placeholders that directly follow it, a placeholder keeps
+// the tie open for the next one. Placeholders not following a tied event are rests. Every event is
+// held until the next one, in case it is tied
+struct Ties<I> {
+    events: I,
+    held: Option<TimedEvent>,
+    open: bool,
+}
+
+impl<I> Ties<I> {
+    fn new(events: I) -> Ties<I> {
+        Ties {
+            events,
+            held: None,
+            open: false,
+        }
+    }
+}
+
+impl<I> Iterator for Ties<I>
+where
+    I: Iterator<Item = Result<TimedEvent, Error>>,
+{
+    type Item = Result<TimedEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for te in &mut self.events {
+            let te = match te {
+                Ok(te) => te,
+                Err(e) => return Some(Err(e)),
+            };
+            if te.event.is_continuation() {
+                match self.held.as_mut() {
+                    Some(held) if self.open && held.index + held.duration == te.index => held.duration += te.duration,
+                    _ => self.open = false,
+                }
+            } else {
+                self.open = te.event.modifiers.tie == Some(Tie::Start);
+                if let Some(held) = self.held.replace(te) {
+                    return Some(Ok(held));
+                }
+            }
         }
+        self.held.take().map(Ok)
     }
 }
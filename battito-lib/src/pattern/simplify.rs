@@ -0,0 +1,584 @@
+use std::convert::TryFrom;
+
+use crate::pattern::error::Error;
+use crate::pattern::measure::DEFAULT_VELOCITY;
+use crate::pattern::parser::expansion::euclidean::Euclidean;
+use crate::pattern::pattern::Pattern;
+use crate::pattern::rational::Rational;
+use crate::pattern::utils::lcm_vec;
+use crate::pattern::{expanded, format, transform_with_options, Options};
+use gcd::Gcd;
+
+// The search grows quickly with the number of slots of a measure
+const MAX_SLOTS: usize = 256;
+
+// A step of a grid
+#[derive(Debug, PartialEq, Clone)]
+pub enum Slot {
+    // An event starts, with its value as written in an expression
+    Onset(String),
+    // The event started before is still playing
+    Hold,
+    Rest,
+    // Nothing starts, whether the event started before is still playing does not matter
+    Free,
+}
+
+// Candidates are built as trees, so that ties can be checked before they are written
+#[derive(Debug, PartialEq, Clone)]
+enum Expr {
+    // A value, tied to the placeholder following it
+    Value(String, bool),
+    Rest,
+    Hold,
+    Group(Vec<Expr>),
+    Fast(Box<Expr>, usize),
+    Replicated(Box<Expr>, usize),
+    // value(pulses,steps,rotation), pulses and rotations having one option per measure
+    Euclid(String, Vec<u32>, u32, Vec<u32>),
+    Alternate(Vec<Expr>),
+}
+
+// Shortest expression giving the same pattern as the input: the input itself, its canonical or
+// expanded forms, or an expression found on the grid of its events, see `from_pattern`
+pub fn simplify(input: &str, options: &Options) -> Result<String, Error> {
+    let pattern = transform_with_options(input, options)?;
+    // The grid is read on the events before they are moved or given times
+    let plain = Options {
+        subdivision: options.subdivision,
+        limits: options.limits,
+        complete: options.complete,
+        ..Options::default()
+    };
+    let mut candidates = vec![input.trim().to_string()];
+    candidates.extend(format(input).ok());
+    candidates.extend(expanded(input, &options.limits).ok());
+    candidates.extend(candidates_of(&transform_with_options(input, &plain)?));
+    Ok(shortest_equivalent(candidates, &pattern, options).unwrap_or_else(|| input.trim().to_string()))
+}
+
+// Shortest expression giving the pattern, using groups, repetitions, euclidean rhythms and
+// alternates. Only patterns whose events fall on a grid and keep their default velocity can be
+// written
+pub fn from_pattern(pattern: &Pattern) -> Option<String> {
+    let options = Options {
+        subdivision: Some(pattern.subdivision),
+        ..Options::default()
+    };
+    shortest_equivalent(candidates_of(pattern), pattern, &options)
+}
+
+// Shortest expression for measures given as grids of equally long steps. Measures repeating with
+// a shorter period are written once, since the pattern loops
+pub fn measures(measures: &[Vec<Slot>]) -> String {
+    let period = period(measures);
+    let measures: Vec<(Vec<Slot>, Rational)> = measures[..period]
+        .iter()
+        .map(|m| (m.clone(), Rational::integer(1)))
+        .collect();
+    expressions(&measures)
+        .into_iter()
+        .min_by_key(|e| length(e))
+        .unwrap_or_default()
+}
+
+// Every slot of the measures written as a step, an event for the onsets and a rest otherwise.
+// Only the onsets are kept, the events last a single step
+pub fn literal(measures: &[Vec<Slot>]) -> String {
+    let written: Vec<String> = measures
+        .iter()
+        .map(|slots| {
+            let steps: Vec<&str> = slots
+                .iter()
+                .map(|slot| match slot {
+                    Slot::Onset(value) => value.as_str(),
+                    _ => "~",
+                })
+                .collect();
+            steps.join(" ")
+        })
+        .collect();
+    written.join(" | ")
+}
+
+// Shortest expression of a single measure
+pub fn measure(slots: &[Slot]) -> String {
+    measures(&[slots.to_vec()])
+}
+
+// Every candidate is checked by transforming it, the first of the shortest equivalent ones is kept
+fn shortest_equivalent(mut candidates: Vec<String>, pattern: &Pattern, options: &Options) -> Option<String> {
+    candidates.sort_by_key(|c| length(c));
+    candidates
+        .into_iter()
+        .find(|c| transform_with_options(c, options).as_ref() == Ok(pattern))
+}
+
+fn candidates_of(pattern: &Pattern) -> Vec<String> {
+    grid(pattern).map(|g| expressions(&g)).unwrap_or_default()
+}
+
+// Slots of every measure, with the length of the measure in cycles. A slot is the largest duration
+// dividing every onset, end and measure boundary
+fn grid(pattern: &Pattern) -> Option<Vec<(Vec<Slot>, Rational)>> {
+    let total = u32::try_from(pattern.length.ticks(pattern.subdivision).ok()?).ok()?;
+    let starts: Vec<u32> = pattern.measures.iter().map(|m| m - 1).collect();
+    let ends: Vec<u32> = starts.iter().skip(1).copied().chain(std::iter::once(total)).collect();
+    let steps = &pattern.steps;
+    let plain = steps
+        .iter()
+        .all(|te| te.ratchet.is_none() && te.time.is_none() && te.event.velocity == DEFAULT_VELOCITY);
+    let apart = steps.windows(2).all(|w| w[0].index + w[0].duration <= w[1].index);
+    let within = steps.last().is_none_or(|te| te.index - 1 + te.duration <= total);
+    if starts.is_empty() || !plain || !apart || !within {
+        return None;
+    }
+    let size = starts
+        .iter()
+        .chain(ends.iter())
+        .copied()
+        .chain(
+            steps
+                .iter()
+                .flat_map(|te| vec![te.index - 1, te.index - 1 + te.duration]),
+        )
+        .fold(0, |acc: u32, t| acc.gcd(t));
+    if size == 0
+        || starts
+            .iter()
+            .zip(ends.iter())
+            .any(|(s, e)| (e - s) / size > MAX_SLOTS as u32)
+    {
+        return None;
+    }
+
+    let mut slots = vec![Slot::Rest; (total / size) as usize];
+    for te in steps {
+        let start = ((te.index - 1) / size) as usize;
+        let end = ((te.index - 1 + te.duration) / size) as usize;
+        slots[start] = match te.event.probability {
+            100 => Slot::Onset(te.event.value.clone()),
+            p => Slot::Onset(format!("{}?{}", te.event.value, p)),
+        };
+        slots[start + 1..end].iter_mut().for_each(|s| *s = Slot::Hold);
+    }
+    starts
+        .iter()
+        .zip(ends.iter())
+        .map(|(s, e)| {
+            let measure = slots[(s / size) as usize..(e / size) as usize].to_vec();
+            let length = Rational::new((e - s) as i64, pattern.subdivision as i64);
+            match measure.is_empty() {
+                true => None,
+                false => Some((measure, length)),
+            }
+        })
+        .collect()
+}
+
+// Expressions of the measures written one after the other, and merged in a single measure with
+// alternates when they have the same length. Every measure is written, so that the expressions
+// have as many measures as the grid
+fn expressions(measures: &[(Vec<Slot>, Rational)]) -> Vec<String> {
+    let annotation = |length: &Rational| match *length == Rational::integer(1) {
+        true => String::new(),
+        false => format!(" @{}", length),
+    };
+    let mut expressions = Vec::new();
+
+    let tops: Option<Vec<Vec<Expr>>> = measures.iter().map(|(slots, _)| top(&[slots])).collect();
+    if let Some(tops) = tops.and_then(tie_measures) {
+        let written: Vec<String> = tops
+            .iter()
+            .zip(measures.iter())
+            .map(|(top, (_, length))| format!("{}{}", write_all(top), annotation(length)))
+            .collect();
+        expressions.push(written.join(" | "));
+    }
+
+    let cols: Vec<&[Slot]> = measures.iter().map(|(slots, _)| slots.as_slice()).collect();
+    let same = measures
+        .iter()
+        .all(|(slots, length)| slots.len() == cols[0].len() && *length == measures[0].1);
+    let held = cols.iter().any(|c| c.first() == Some(&Slot::Hold));
+    if measures.len() > 1 && same && !held {
+        if let Some(merged) = merged(&cols) {
+            expressions.push(format!("{}{}", write_all(&merged), annotation(&measures[0].1)));
+        }
+    }
+    expressions
+}
+
+// A measure starting with a placeholder continues the last event of the previous measure
+fn tie_measures(mut tops: Vec<Vec<Expr>>) -> Option<Vec<Vec<Expr>>> {
+    if tops.first()?.first().is_some_and(starts_with_hold) {
+        return None;
+    }
+    for i in 1..tops.len() {
+        if tops[i].first().is_some_and(starts_with_hold) {
+            let last = tops[i - 1].pop()?;
+            tops[i - 1].push(tied(&last)?);
+        }
+    }
+    Some(tops)
+}
+
+// The alternates of the merged measure have to cycle through all the measures
+fn merged(cols: &[&[Slot]]) -> Option<Vec<Expr>> {
+    let mut children = top(cols)?;
+    let mut counts: Vec<u32> = Vec::new();
+    children.iter().for_each(|c| alternates(c, &mut counts));
+    let count = cols.len() as u32;
+    if lcm_vec(&counts).ok()? != count {
+        let longest = *counts.iter().max()?;
+        if !children.iter_mut().any(|c| pad(c, longest as usize, count as usize)) {
+            return None;
+        }
+    }
+    Some(children)
+}
+
+fn alternates(expr: &Expr, acc: &mut Vec<u32>) {
+    match expr {
+        Expr::Alternate(options) => acc.push(options.len() as u32),
+        Expr::Euclid(_, pulses, _, rotations) => acc.extend(
+            [pulses.len(), rotations.len()]
+                .iter()
+                .filter(|len| **len > 1)
+                .map(|len| *len as u32),
+        ),
+        Expr::Group(xs) => xs.iter().for_each(|x| alternates(x, acc)),
+        Expr::Fast(x, _) | Expr::Replicated(x, _) => alternates(x, acc),
+        _ => (),
+    }
+}
+
+// Repeat the options of the first alternate with `options` options until there are `count`
+fn pad(expr: &mut Expr, options: usize, count: usize) -> bool {
+    match expr {
+        Expr::Alternate(xs) if xs.len() == options => {
+            *xs = xs.iter().cycle().take(count).cloned().collect();
+            true
+        }
+        Expr::Euclid(_, xs, _, _) | Expr::Euclid(_, _, _, xs) if xs.len() == options => {
+            *xs = xs.iter().cycle().take(count).copied().collect();
+            true
+        }
+        Expr::Group(xs) => xs.iter_mut().any(|x| pad(x, options, count)),
+        Expr::Fast(x, _) | Expr::Replicated(x, _) => pad(x, options, count),
+        _ => false,
+    }
+}
+
+// Content of a measure: a sequence, or a single element such as an alternate
+fn top(cols: &[&[Slot]]) -> Option<Vec<Expr>> {
+    let candidates = vec![element(cols, true).map(|e| vec![e]), sequence(cols, true)];
+    shortest(candidates.into_iter().flatten().collect())
+}
+
+// Content of a group spanning the slots: the slots are split in equal parts, every part is an
+// element. `cols` are the slots of every measure, they only differ in alternates
+fn sequence(cols: &[&[Slot]], compact: bool) -> Option<Vec<Expr>> {
+    let n = cols[0].len();
+    if n <= 1 {
+        return element(cols, compact).map(|e| vec![e]);
+    }
+    let candidates = divisors(n).filter_map(|d| {
+        let children = (0..d)
+            .map(|i| {
+                let part: Vec<&[Slot]> = cols.iter().map(|c| &c[i * n / d..(i + 1) * n / d]).collect();
+                element(&part, compact)
+            })
+            .collect::<Option<Vec<Expr>>>()?;
+        let children = tie(children)?;
+        Some(match compact {
+            true => replicate(children),
+            false => children,
+        })
+    });
+    shortest(candidates.collect())
+}
+
+// A single element lasting as long as the slots. Without `compact` only events, rests and groups
+// are used, as in the options of an alternate
+fn element(cols: &[&[Slot]], compact: bool) -> Option<Expr> {
+    if cols.iter().all(|c| *c == cols[0]) {
+        return single(cols[0], compact);
+    }
+    let mut candidates = Vec::new();
+    if cols[0].len() > 1 {
+        candidates.extend(sequence(cols, compact).map(Expr::Group));
+    }
+    if compact {
+        candidates.extend(alternate(cols));
+        candidates.extend(euclid_alternate(cols));
+    }
+    shortest_expr(candidates)
+}
+
+fn single(slots: &[Slot], compact: bool) -> Option<Expr> {
+    let (first, rest) = slots.split_first()?;
+    let holds = |s: &[Slot]| s.iter().all(|s| matches!(s, Slot::Hold | Slot::Free));
+    match first {
+        Slot::Onset(value) if holds(rest) => return Some(Expr::Value(value.clone(), false)),
+        _ if slots.iter().all(|s| matches!(s, Slot::Rest | Slot::Free)) => return Some(Expr::Rest),
+        _ if holds(slots) => return Some(Expr::Hold),
+        _ => (),
+    }
+    let mut candidates: Vec<Expr> = sequence(&[slots], compact).map(Expr::Group).into_iter().collect();
+    if compact {
+        candidates.extend(repeated(slots));
+        candidates.extend(euclid(slots));
+    }
+    shortest_expr(candidates)
+}
+
+// [a b a b] -> [a b]*2
+fn repeated(slots: &[Slot]) -> Vec<Expr> {
+    let n = slots.len();
+    divisors(n)
+        .filter_map(|d| {
+            let parts: Vec<&[Slot]> = slots.chunks(n / d).collect();
+            if parts[0][0] == Slot::Hold || parts.iter().any(|p| *p != parts[0]) {
+                return None;
+            }
+            single(parts[0], true).map(|e| Expr::Fast(Box::new(e), d))
+        })
+        .collect()
+}
+
+// [a ~ ~ a ~ ~ a ~] -> a(3,8), the steps being made of one or more slots
+fn euclid(slots: &[Slot]) -> Vec<Expr> {
+    divisors(slots.len())
+        .filter_map(|d| {
+            let (value, pulses, rotation) = euclid_steps(slots, d)?;
+            Some(Expr::Euclid(value, vec![pulses], d as u32, vec![rotation]))
+        })
+        .collect()
+}
+
+// [a ~ ~ a ~ ~ a ~] | [a ~ a ~ a ~ a ~] -> a(<3,4>,8)
+fn euclid_alternate(cols: &[&[Slot]]) -> Vec<Expr> {
+    divisors(cols[0].len())
+        .filter_map(|d| {
+            let rhythms = cols
+                .iter()
+                .map(|c| euclid_steps(c, d))
+                .collect::<Option<Vec<(String, u32, u32)>>>()?;
+            let value = &rhythms[0].0;
+            if rhythms.iter().any(|(v, _, _)| v != value) {
+                return None;
+            }
+            let pulses: Vec<u32> = rhythms.iter().map(|(_, p, _)| *p).collect();
+            let rotations: Vec<u32> = rhythms.iter().map(|(_, _, r)| *r).collect();
+            let pulses = pulses[..period(&pulses)].to_vec();
+            let rotations = rotations[..period(&rotations)].to_vec();
+            Some(Expr::Euclid(value.clone(), pulses, d as u32, rotations))
+        })
+        .collect()
+}
+
+fn divisors(n: usize) -> impl Iterator<Item = usize> {
+    (2..=n).filter(move |d| n.is_multiple_of(*d))
+}
+
+// Value, pulses and rotation of the rhythm when the slots are split in `steps` steps
+fn euclid_steps(slots: &[Slot], steps: usize) -> Option<(String, u32, u32)> {
+    let cells = slots
+        .chunks(slots.len() / steps)
+        .map(|c| match c.split_first() {
+            Some((Slot::Onset(v), rest)) if rest.iter().all(|s| matches!(s, Slot::Hold | Slot::Free)) => Some(Some(v)),
+            _ if c.iter().all(|s| matches!(s, Slot::Rest | Slot::Free)) => Some(None),
+            _ => None,
+        })
+        .collect::<Option<Vec<Option<&String>>>>()?;
+    let values: Vec<&String> = cells.iter().flatten().copied().collect();
+    let value = *values.first()?;
+    if values.len() == steps || values.iter().any(|v| *v != value) {
+        return None;
+    }
+    let hits: Vec<bool> = cells.iter().map(|c| c.is_some()).collect();
+    let pulses = values.len() as u32;
+    let steps = steps as u32;
+    let rotation = (0..steps).find(|r| Euclidean::hits(pulses, steps, *r).ok().as_ref() == Some(&hits))?;
+    Some((value.clone(), pulses, rotation))
+}
+
+// <a,b>: one option per measure, written once per period of the options
+fn alternate(cols: &[&[Slot]]) -> Option<Expr> {
+    let options = cols.iter().map(|c| single(c, false)).collect::<Option<Vec<Expr>>>()?;
+    if options.iter().any(starts_with_hold) {
+        return None;
+    }
+    let period = period(&options);
+    Some(Expr::Alternate(options[..period].to_vec()))
+}
+
+fn period<T: PartialEq>(items: &[T]) -> usize {
+    (1..=items.len())
+        .find(|p| items.len().is_multiple_of(*p) && (*p..items.len()).all(|i| items[i] == items[i - p]))
+        .unwrap_or(items.len())
+}
+
+// A child starting with a placeholder continues the last event of the previous child, which has
+// to be tied
+fn tie(mut children: Vec<Expr>) -> Option<Vec<Expr>> {
+    for i in 1..children.len() {
+        if starts_with_hold(&children[i]) {
+            children[i - 1] = tied(&children[i - 1])?;
+        }
+    }
+    Some(children)
+}
+
+fn tied(expr: &Expr) -> Option<Expr> {
+    match expr {
+        Expr::Value(value, _) => Some(Expr::Value(value.clone(), true)),
+        Expr::Hold => Some(Expr::Hold),
+        Expr::Group(xs) => {
+            let mut xs = xs.clone();
+            let last = xs.pop()?;
+            xs.push(tied(&last)?);
+            Some(Expr::Group(xs))
+        }
+        _ => None,
+    }
+}
+
+fn starts_with_hold(expr: &Expr) -> bool {
+    match expr {
+        Expr::Hold => true,
+        Expr::Group(xs) => xs.first().is_some_and(starts_with_hold),
+        Expr::Fast(x, _) | Expr::Replicated(x, _) => starts_with_hold(x),
+        Expr::Alternate(xs) => xs.iter().any(starts_with_hold),
+        _ => false,
+    }
+}
+
+// a a a b -> a!3 b
+fn replicate(children: Vec<Expr>) -> Vec<Expr> {
+    let mut runs: Vec<(Expr, usize)> = Vec::new();
+    for child in children {
+        match runs.last_mut() {
+            Some((last, count)) if *last == child && !starts_with_hold(&child) => *count += 1,
+            _ => runs.push((child, 1)),
+        }
+    }
+    runs.into_iter()
+        .flat_map(|(e, count)| {
+            let replicated = Expr::Replicated(Box::new(e.clone()), count);
+            let repeated = vec![e; count];
+            match count > 1 && write(&replicated).len() < write_all(&repeated).len() {
+                true => vec![replicated],
+                false => repeated,
+            }
+        })
+        .collect()
+}
+
+fn write(expr: &Expr) -> String {
+    match expr {
+        Expr::Value(value, false) => value.clone(),
+        Expr::Value(value, true) => format!("{}_", value),
+        Expr::Rest => "~".to_string(),
+        Expr::Hold => "_".to_string(),
+        Expr::Group(xs) => format!("[{}]", write_all(xs)),
+        Expr::Fast(x, d) => format!("{}*{}", atom(x), d),
+        Expr::Replicated(x, k) => format!("{}!{}", atom(x), k),
+        Expr::Euclid(value, pulses, steps, rotations) if rotations == &[0] => {
+            format!("{}({},{})", value, options(pulses), steps)
+        }
+        Expr::Euclid(value, pulses, steps, rotations) => {
+            format!("{}({},{},{})", value, options(pulses), steps, options(rotations))
+        }
+        Expr::Alternate(xs) => {
+            let written: Vec<String> = xs.iter().map(write).collect();
+            format!("<{}>", written.join(","))
+        }
+    }
+}
+
+fn options(xs: &[u32]) -> String {
+    match xs {
+        [x] => x.to_string(),
+        _ => {
+            let written: Vec<String> = xs.iter().map(|x| x.to_string()).collect();
+            format!("<{}>", written.join(","))
+        }
+    }
+}
+
+// Repetitions only apply to a single event, function or bracketed group
+fn atom(expr: &Expr) -> String {
+    match expr {
+        Expr::Fast(..) | Expr::Replicated(..) => format!("[{}]", write(expr)),
+        _ => write(expr),
+    }
+}
+
+fn write_all(xs: &[Expr]) -> String {
+    let written: Vec<String> = xs.iter().map(write).collect();
+    written.join(" ")
+}
+
+// The first of the shortest candidates, with as few brackets as possible
+fn shortest(candidates: Vec<Vec<Expr>>) -> Option<Vec<Expr>> {
+    candidates.into_iter().min_by_key(|c| length(&write_all(c)))
+}
+
+fn shortest_expr(candidates: Vec<Expr>) -> Option<Expr> {
+    candidates.into_iter().min_by_key(|c| length(&write(c)))
+}
+
+fn length(expression: &str) -> (usize, usize) {
+    (expression.len(), expression.matches('[').count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{literal, measure, measures, Slot};
+
+    fn slots(grid: &str) -> Vec<Slot> {
+        grid.split(' ')
+            .map(|s| match s {
+                "." => Slot::Free,
+                "_" => Slot::Hold,
+                "~" => Slot::Rest,
+                v => Slot::Onset(v.to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compact() {
+        assert_eq!("bd", measure(&slots("bd . . .")));
+        assert_eq!("bd sn", measure(&slots("bd . sn .")));
+        assert_eq!("bd(3,8)", measure(&slots("bd . . bd . . bd .")));
+        assert_eq!("hh*8", measure(&slots("hh hh hh hh hh hh hh hh")));
+        assert_eq!("[bd sn]*4", measure(&slots("bd sn bd sn bd sn bd sn")));
+        assert_eq!("bd!3 sn", measure(&slots("bd bd bd sn")));
+        assert_eq!("~ sn", measure(&slots(". . sn .")));
+        assert_eq!(
+            "bd | sn",
+            measures(&[slots("bd ."), slots("sn ."), slots("bd ."), slots("sn .")])
+        );
+        assert_eq!("~", measures(&[slots(". ."), slots(". .")]));
+        assert_eq!("bd ~ ~ ~ | ~ ~ sn ~", literal(&[slots("bd . _ ~"), slots(". . sn .")]));
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!("a", measure(&slots("a _ _ _")));
+        assert_eq!("a ~", measure(&slots("a _ ~ ~")));
+        assert_eq!("a_ _ _ b", measure(&slots("a _ _ b")));
+        assert_eq!("a(2,6)", measure(&slots("a ~ ~ a ~ ~")));
+        assert_eq!("a(3,8)", measure(&slots("a _ ~ ~ ~ ~ a _ ~ ~ ~ ~ a _ ~ ~")));
+        assert_eq!("[a b]*4", measure(&slots("a _ b _ a _ b _ a _ b _ a _ b _")));
+        assert_eq!("a_ | _ b", measures(&[slots("a _"), slots("_ b")]));
+        assert_eq!("bd <sn,cp>", measures(&[slots("bd sn"), slots("bd cp")]));
+        assert_eq!(
+            "bd(<3,5>,8)",
+            measures(&[slots("bd ~ ~ bd ~ ~ bd ~"), slots("bd ~ bd bd ~ bd bd ~")])
+        );
+    }
+}
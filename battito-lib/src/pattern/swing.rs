@@ -0,0 +1,67 @@
+use crate::pattern::error::{Error, ParsingError};
+use crate::pattern::pattern::TimedEvent;
+
+// swing(16, 0.6): the cycle is divided in `grid` cells, and every other cell is delayed so that
+// each pair of cells is split `amount` : 1 - `amount` instead of evenly
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Swing {
+    pub grid: u32,
+    pub amount: f64,
+}
+
+impl Swing {
+    pub fn create(grid: u32, amount: f64) -> Result<Swing, Error> {
+        if grid < 2 || amount <= 0.0 || amount >= 1.0 {
+            Err(Error::DSLParsingError(ParsingError::SwingError))
+        } else {
+            Ok(Swing { grid, amount })
+        }
+    }
+
+    // Move a position in ticks from the start of the pattern, cycle_length being the ticks of a cycle
+    fn warp(&self, position: f64, cycle_length: f64) -> f64 {
+        let pair = 2.0 * cycle_length / self.grid as f64;
+        let start = (position / pair).floor() * pair;
+        let offset = position - start;
+        let warped = if offset < pair / 2.0 {
+            offset * 2.0 * self.amount
+        } else {
+            pair * self.amount + (offset - pair / 2.0) * 2.0 * (1.0 - self.amount)
+        };
+        start + warped
+    }
+
+    // Both the onset and the end of the event are moved, so that consecutive events still tile
+    pub fn apply(&self, timed_event: &mut TimedEvent, cycle_length: f64) {
+        let onset = (timed_event.index - 1) as f64;
+        let end = onset + timed_event.duration as f64;
+        let new_onset = self.warp(onset, cycle_length).round() as u32;
+        let new_end = self.warp(end, cycle_length).round() as u32;
+        timed_event.index = new_onset + 1;
+        timed_event.duration = new_end - new_onset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Swing;
+
+    #[test]
+    fn warp() {
+        let swing = Swing::create(16, 0.6).unwrap();
+        assert_eq!(0.0, swing.warp(0.0, 1920.0));
+        assert_eq!(144.0, swing.warp(120.0, 1920.0));
+        assert_eq!(240.0, swing.warp(240.0, 1920.0));
+        assert_eq!(384.0, swing.warp(360.0, 1920.0));
+        assert_eq!(72.0, swing.warp(60.0, 1920.0));
+        let straight = Swing::create(8, 0.5).unwrap();
+        assert_eq!(300.0, straight.warp(300.0, 1920.0));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Swing::create(16, 1.0).is_err());
+        assert!(Swing::create(16, 0.0).is_err());
+        assert!(Swing::create(1, 0.6).is_err());
+    }
+}
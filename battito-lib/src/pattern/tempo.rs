@@ -0,0 +1,128 @@
+use crate::pattern::error::{Error, ParsingError};
+use crate::pattern::pattern::TimedEvent;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_BPM: f64 = 120.0;
+const BEATS_PER_BAR: f64 = 4.0;
+
+// Tempo used to convert ticks into wall time: a bar lasts four beats, and is made of
+// `cycles_per_bar` cycles
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Tempo {
+    pub bpm: f64,
+    pub cycles_per_bar: f64,
+}
+
+impl Default for Tempo {
+    fn default() -> Self {
+        Tempo {
+            bpm: DEFAULT_BPM,
+            cycles_per_bar: 1.0,
+        }
+    }
+}
+
+impl Tempo {
+    // Both must be positive and finite
+    pub fn new(bpm: f64, cycles_per_bar: f64) -> Result<Tempo, Error> {
+        let valid = |x: f64| x > 0.0 && x.is_finite();
+        if valid(bpm) && valid(cycles_per_bar) {
+            Ok(Tempo { bpm, cycles_per_bar })
+        } else {
+            Err(Error::DSLParsingError(ParsingError::TempoError))
+        }
+    }
+
+    fn cycle_ms(&self, bpm: f64) -> f64 {
+        60_000.0 / bpm * self.beats_per_cycle()
+    }
+
+    pub fn beats_per_cycle(&self) -> f64 {
+        BEATS_PER_BAR / self.cycles_per_bar
+    }
+}
+
+// Onset and duration of an event in milliseconds
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Time {
+    pub onset_ms: f64,
+    pub duration_ms: f64,
+}
+
+// Piecewise linear conversion from ticks to milliseconds, with one piece per tempo change
+pub struct TempoMap {
+    // First tick of the piece, its time and the duration of a cycle
+    pieces: Vec<(u32, f64, f64)>,
+    subdivision: u32,
+}
+
+impl TempoMap {
+    // changes holds the first tick of every measure and its tempo change
+    pub fn new<I>(tempo: Tempo, changes: I, subdivision: u32) -> TempoMap
+    where
+        I: IntoIterator<Item = (u32, Option<f64>)>,
+    {
+        let mut pieces: Vec<(u32, f64, f64)> = vec![(0, 0.0, tempo.cycle_ms(tempo.bpm))];
+        changes.into_iter().for_each(|(start, change)| {
+            let &(previous, previous_ms, cycle_ms) = pieces.last().unwrap();
+            match change.map(|bpm| tempo.cycle_ms(bpm)) {
+                Some(changed) if changed != cycle_ms => {
+                    let ms = previous_ms + (start - previous) as f64 * cycle_ms / subdivision as f64;
+                    pieces.push((start, ms, changed));
+                }
+                _ => (),
+            }
+        });
+        TempoMap { pieces, subdivision }
+    }
+
+    pub fn ms(&self, tick: u32) -> f64 {
+        let (start, ms, cycle_ms) = self
+            .pieces
+            .iter()
+            .rev()
+            .find(|(start, _, _)| *start <= tick)
+            .unwrap_or(&self.pieces[0]);
+        ms + (tick as f64 - *start as f64) * cycle_ms / self.subdivision as f64
+    }
+
+    pub fn time(&self, timed_event: &TimedEvent) -> Time {
+        let onset = timed_event.index - 1;
+        let onset_ms = self.ms(onset);
+        Time {
+            onset_ms,
+            duration_ms: self.ms(onset + timed_event.duration) - onset_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Tempo, TempoMap};
+
+    #[test]
+    fn tempo_changes() {
+        let map = TempoMap::new(
+            Tempo::new(120.0, 1.0).unwrap(),
+            vec![(0, None), (1920, Some(60.0)), (3840, None)],
+            1920,
+        );
+        assert_eq!(0.0, map.ms(0));
+        assert_eq!(1000.0, map.ms(960));
+        assert_eq!(2000.0, map.ms(1920));
+        assert_eq!(4000.0, map.ms(2880));
+        assert_eq!(6000.0, map.ms(3840));
+        assert_eq!(8000.0, map.ms(4800));
+        let half_bar = TempoMap::new(Tempo::new(120.0, 2.0).unwrap(), vec![(0, None)], 1920);
+        assert_eq!(1000.0, half_bar.ms(1920));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Tempo::new(0.0, 1.0).is_err());
+        assert!(Tempo::new(-120.0, 1.0).is_err());
+        assert!(Tempo::new(f64::NAN, 1.0).is_err());
+        assert!(Tempo::new(120.0, 0.0).is_err());
+        assert!(Tempo::new(120.0, f64::INFINITY).is_err());
+    }
+}
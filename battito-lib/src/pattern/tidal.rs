@@ -0,0 +1,625 @@
+use crate::pattern::error::{Error, TidalError};
+use crate::pattern::limits::Limits;
+use crate::pattern::measure::Tie;
+use crate::pattern::parser::expansion::euclidean::Euclidean;
+use crate::pattern::parser::parsed_measure::{Parsed, ParsedMeasure, Single};
+use crate::pattern::parser::primitives::ParsedEvent;
+use crate::pattern::parser::{parse_complete, printer, ParsedSequence};
+use crate::pattern::rational::Rational;
+use crate::pattern::utils::lcm_vec;
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{alphanumeric1, char, digit1, multispace0, multispace1},
+    combinator::{map, map_res, opt, recognize},
+    multi::{many0, separated_list1},
+    sequence::{delimited, pair, preceded, tuple},
+    IResult,
+};
+
+// Conversion between expressions and the mini-notation of TidalCycles and Strudel, for the
+// constructs they share: groups, "*", "!", "<>", euclidean rhythms, "?" and "{}%"
+
+// Mini-notation of the expression. Expansions are applied by the parser, so they are written
+// expanded, as in `printer::print`
+pub fn export(input: &str) -> Result<String, Error> {
+    let limits = Limits::default();
+    limits.check_depth(input)?;
+    mini_notation(&printer::normalize(parse_complete(&printer::spacing(input), &limits)?))
+}
+
+// Expression playing like the mini-notation
+pub fn import(input: &str) -> Result<String, Error> {
+    let limits = Limits::default();
+    limits.check_depth(input)?;
+    check_supported(input)?;
+    let steps = match sequence(input) {
+        Ok(("", steps)) => steps,
+        _ => return Err(Error::TidalError(TidalError::InvalidSyntax)),
+    };
+    let expression = sequence_source(&steps, Mode::Compact, &limits)?;
+    parse_complete(&expression, &limits).map(|_| expression)
+}
+
+fn unsupported(construct: &str) -> Error {
+    Error::TidalError(TidalError::Unsupported(construct.to_string()))
+}
+
+// Export
+
+// Whether the last event is tied, and if its placeholders can follow in the same sequence. Tidal
+// only lengthens a step with the "_" right after it
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Tied {
+    No,
+    Here,
+    Elsewhere,
+}
+
+impl Tied {
+    fn elsewhere(self) -> Tied {
+        match self {
+            Tied::No => Tied::No,
+            _ => Tied::Elsewhere,
+        }
+    }
+}
+
+// Measures are played one per cycle, as the options of an alternation. Every measure has to be a
+// single cycle: Tidal alternates between the measures before expanding their own alternates
+fn mini_notation(sequence: &ParsedSequence) -> Result<String, Error> {
+    if sequence.scale.is_some() {
+        return Err(unsupported("scale"));
+    }
+    if sequence.swing.is_some() {
+        return Err(unsupported("swing"));
+    }
+    if sequence.length.is_some() {
+        return Err(unsupported("sequence length"));
+    }
+    if sequence.settings.iter().any(|s| s.length != Rational::integer(1)) {
+        return Err(unsupported("measure length"));
+    }
+    if sequence.settings.iter().any(|s| s.bpm.is_some()) {
+        return Err(unsupported("tempo"));
+    }
+    let mut tied = Tied::No;
+    let measures = sequence
+        .measures
+        .iter()
+        .map(|parsed| {
+            tied = tied.elsewhere();
+            parsed_notation(parsed, &mut tied)
+        })
+        .collect::<Result<Vec<String>, Error>>()?;
+    match measures.as_slice() {
+        [measure] => Ok(measure.clone()),
+        measures => {
+            for parsed in &sequence.measures {
+                if parsed.measure_count()? > 1 {
+                    return Err(unsupported("alternates in a sequence of measures"));
+                }
+            }
+            let written: Vec<String> = measures.iter().map(|m| format!("[{}]", m)).collect();
+            Ok(format!("<{}>", written.join(" ")))
+        }
+    }
+}
+
+fn parsed_notation(parsed: &Parsed, tied: &mut Tied) -> Result<String, Error> {
+    match parsed {
+        Parsed::ParsedMeasure(ParsedMeasure::Group(xs)) => elements(xs, tied),
+        Parsed::ParsedMeasure(pm) => element(pm, tied),
+        Parsed::Polymetric(p) => Ok(format!("{{{}}}%{}", elements(&p.elements, tied)?, p.length)),
+        Parsed::Arithmetic(_) => Err(unsupported("arithmetic")),
+    }
+}
+
+fn elements(xs: &[ParsedMeasure], tied: &mut Tied) -> Result<String, Error> {
+    let written = xs
+        .iter()
+        .map(|x| element(x, tied))
+        .collect::<Result<Vec<String>, Error>>()?;
+    Ok(written.join(" "))
+}
+
+fn element(parsed_measure: &ParsedMeasure, tied: &mut Tied) -> Result<String, Error> {
+    match parsed_measure {
+        ParsedMeasure::Group(xs) => {
+            *tied = tied.elsewhere();
+            let written = elements(xs, tied)?;
+            *tied = tied.elsewhere();
+            Ok(format!("[{}]", written))
+        }
+        ParsedMeasure::Single(Single::Event(e)) => match (e.modifiers.tie, *tied) {
+            (Some(Tie::Continue), Tied::Here) => Ok("_".to_string()),
+            (Some(Tie::Continue), Tied::Elsewhere) => Err(unsupported("tie across groups")),
+            // A placeholder not following a tied event is a rest
+            (Some(Tie::Continue), Tied::No) => Ok("~".to_string()),
+            (tie, _) => {
+                *tied = match tie {
+                    Some(Tie::Start) => Tied::Here,
+                    _ => Tied::No,
+                };
+                event(e)
+            }
+        },
+        // A tie left open by an option can only be continued inside it
+        ParsedMeasure::Single(Single::Alternate(a)) => {
+            let incoming = tied.elsewhere();
+            let mut open = false;
+            let written =
+                a.0.iter()
+                    .map(|option| {
+                        let mut option_tied = incoming;
+                        let written = element(&option.to_parsed_measure(), &mut option_tied);
+                        open |= option_tied != Tied::No;
+                        written
+                    })
+                    .collect::<Result<Vec<String>, Error>>()?;
+            *tied = if open { Tied::Elsewhere } else { Tied::No };
+            Ok(format!("<{}>", written.join(" ")))
+        }
+    }
+}
+
+// "?" is the chance of dropping the event in Tidal, the chance of playing it here
+fn event(event: &ParsedEvent) -> Result<String, Error> {
+    let modifiers = &event.modifiers;
+    if modifiers.swing.is_some() {
+        return Err(unsupported("swing"));
+    }
+    if modifiers.nudge.is_some() {
+        return Err(unsupported("nudge"));
+    }
+    if modifiers.ratchet.is_some() {
+        return Err(unsupported("ratchet"));
+    }
+    if modifiers.humanize.is_some() {
+        return Err(unsupported("humanize"));
+    }
+    Ok(match (event.value.as_str(), event.probability) {
+        ("0", 0) => "~".to_string(),
+        (value, 100) => value.to_string(),
+        (value, probability) => format!("{}?{}", value, f64::from(100 - probability) / 100.0),
+    })
+}
+
+// Import
+
+#[derive(Debug, PartialEq, Clone)]
+enum Term {
+    // Value, with the chance of playing it
+    Word(String, f64),
+    Rest,
+    Group(Vec<Step>),
+    Alternation(Vec<Step>),
+    Polymeter(Vec<Step>, Option<f64>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Modifier {
+    Fast(f64),
+    // "a!3", or "a!" for one more
+    Replicate(Option<f64>),
+    Weight(f64),
+    // Chance of dropping the events, half of them without an amount
+    Degrade(Option<f64>),
+    Euclid(Vec<Vec<f64>>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Step {
+    Term(Term, Vec<Modifier>),
+    // "_" lengthens the previous step, a lone "!" repeats it
+    Elongate,
+    Repeat,
+}
+
+// Steps of a sequence with their count and weight, once "_", "!" and "@" are applied
+struct Item {
+    term: Term,
+    modifiers: Vec<Modifier>,
+    count: u32,
+    weight: u32,
+}
+
+// Alternates can only hold events and groups, the steps inside them are written expanded
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    Compact,
+    Primitive,
+}
+
+// Constructs told apart by a single character, found before parsing so that the error names them
+fn check_supported(input: &str) -> Result<(), Error> {
+    if input.contains("..") {
+        return Err(unsupported("range"));
+    }
+    let mut parentheses = 0;
+    for c in input.chars() {
+        match c {
+            '(' => parentheses += 1,
+            ')' => parentheses -= 1,
+            ',' if parentheses == 0 => return Err(unsupported("stack")),
+            '|' => return Err(unsupported("random choice")),
+            '/' => return Err(unsupported("slow")),
+            ':' => return Err(unsupported("sample number")),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+// Steps separated by spaces, " . " splits them in groups
+fn sequence(input: &str) -> IResult<&str, Vec<Step>> {
+    let (input, items) = many0(preceded(multispace0, alt((map(char('.'), |_| None), map(step, Some)))))(input)?;
+    let (input, _) = multispace0(input)?;
+    let sections: Vec<Vec<Step>> = items
+        .split(|item| item.is_none())
+        .map(|section| section.iter().flatten().cloned().collect())
+        .collect();
+    let steps = match sections.len() {
+        1 => sections.into_iter().flatten().collect(),
+        _ => sections
+            .into_iter()
+            .map(|section| Step::Term(Term::Group(section), vec![]))
+            .collect(),
+    };
+    Ok((input, steps))
+}
+
+fn step(input: &str) -> IResult<&str, Step> {
+    alt((
+        map(char('_'), |_| Step::Elongate),
+        map(char('!'), |_| Step::Repeat),
+        map(pair(term, many0(modifier)), |(term, modifiers)| {
+            Step::Term(term, modifiers)
+        }),
+    ))(input)
+}
+
+fn term(input: &str) -> IResult<&str, Term> {
+    alt((
+        map(delimited(char('['), sequence, char(']')), Term::Group),
+        map(delimited(char('<'), sequence, char('>')), Term::Alternation),
+        map(
+            pair(
+                delimited(char('{'), sequence, char('}')),
+                opt(preceded(char('%'), number)),
+            ),
+            |(steps, length)| Term::Polymeter(steps, length),
+        ),
+        map(char('~'), |_| Term::Rest),
+        map(word, |w| Term::Word(w.to_string(), 1.0)),
+    ))(input)
+}
+
+fn word(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((
+        opt(char('-')),
+        alphanumeric1,
+        many0(alt((alphanumeric1, tag("."), tag("#")))),
+    )))(input)
+}
+
+fn number(input: &str) -> IResult<&str, f64> {
+    map_res(recognize(pair(digit1, opt(pair(char('.'), digit1)))), str::parse)(input)
+}
+
+fn modifier(input: &str) -> IResult<&str, Modifier> {
+    let comma = tuple((multispace0, char(','), multispace0));
+    let argument = alt((
+        map(number, |n| vec![n]),
+        delimited(
+            pair(char('<'), multispace0),
+            separated_list1(multispace1, number),
+            pair(multispace0, char('>')),
+        ),
+    ));
+    alt((
+        map(preceded(char('*'), number), Modifier::Fast),
+        map(preceded(char('!'), opt(number)), Modifier::Replicate),
+        map(preceded(char('@'), number), Modifier::Weight),
+        map(preceded(char('?'), opt(number)), Modifier::Degrade),
+        map(
+            delimited(
+                pair(char('('), multispace0),
+                separated_list1(comma, argument),
+                pair(multispace0, char(')')),
+            ),
+            Modifier::Euclid,
+        ),
+    ))(input)
+}
+
+// Whole number of a count, a length or an euclidean argument
+fn whole(n: f64, construct: &str) -> Result<u32, Error> {
+    match n.fract() == 0.0 && n >= 1.0 && n <= f64::from(u32::MAX) {
+        true => Ok(n as u32),
+        false => Err(unsupported(&format!("{} {}", construct, n))),
+    }
+}
+
+fn items(steps: &[Step]) -> Result<Vec<Item>, Error> {
+    let mut items: Vec<Item> = Vec::new();
+    for step in steps {
+        match step {
+            Step::Term(term, modifiers) => {
+                let mut item = Item {
+                    term: term.clone(),
+                    modifiers: vec![],
+                    count: 1,
+                    weight: 1,
+                };
+                for modifier in modifiers {
+                    match modifier {
+                        Modifier::Replicate(Some(n)) => item.count = whole(*n, "replication")?,
+                        Modifier::Replicate(None) => item.count += 1,
+                        Modifier::Weight(w) => item.weight = whole(*w, "weight")?,
+                        Modifier::Degrade(amount) => degrade(&mut item.term, 1.0 - amount.unwrap_or(0.5)),
+                        modifier => item.modifiers.push(modifier.clone()),
+                    }
+                }
+                items.push(item);
+            }
+            Step::Elongate | Step::Repeat => {
+                let last = items.last_mut().ok_or(Error::TidalError(TidalError::InvalidSyntax))?;
+                match step {
+                    Step::Elongate => last.weight += 1,
+                    _ => last.count += 1,
+                }
+            }
+        }
+    }
+    Ok(items)
+}
+
+// The chance of playing every event is multiplied by `keep`
+fn degrade(term: &mut Term, keep: f64) {
+    match term {
+        Term::Word(_, chance) => *chance *= keep,
+        Term::Rest => (),
+        Term::Group(steps) | Term::Alternation(steps) | Term::Polymeter(steps, _) => {
+            for step in steps.iter_mut() {
+                if let Step::Term(term, _) = step {
+                    degrade(term, keep);
+                }
+            }
+        }
+    }
+}
+
+// Weights are written as ties, so only single events and rests can have one. The options of an
+// alternation are cycles, they cannot have one
+fn sequence_source(steps: &[Step], mode: Mode, limits: &Limits) -> Result<String, Error> {
+    let written = items(steps)?
+        .iter()
+        .map(|item| {
+            let source = match (item.weight, &item.term, mode) {
+                (1, _, _) => item_source(item, mode, limits)?,
+                (w, Term::Rest, _) => repeated("~", w, limits)?.join(" "),
+                (w, Term::Word(..), _) if item.modifiers.is_empty() => {
+                    limits.check_events(w as usize)?;
+                    format!("{}_{}", item_source(item, mode, limits)?, " _".repeat(w as usize - 1))
+                }
+                _ => return Err(unsupported("weight of a group")),
+            };
+            Ok(match (item.count, mode) {
+                (1, _) => source,
+                (count, Mode::Compact) if item.weight == 1 => format!("{}!{}", atom(&source), count),
+                (count, _) => repeated(&source, count, limits)?.join(" "),
+            })
+        })
+        .collect::<Result<Vec<String>, Error>>()?;
+    Ok(written.join(" "))
+}
+
+// Copies of a source written out, checked against the limits before they are made
+fn repeated(source: &str, count: u32, limits: &Limits) -> Result<Vec<String>, Error> {
+    let steps = source.split(' ').count().saturating_mul(count as usize);
+    limits.check_events(steps)?;
+    Ok(vec![source.to_string(); count as usize])
+}
+
+fn item_source(item: &Item, mode: Mode, limits: &Limits) -> Result<String, Error> {
+    let mut source = term_source(&item.term, mode, limits)?;
+    for (i, modifier) in item.modifiers.iter().enumerate() {
+        source = match modifier {
+            Modifier::Fast(n) => {
+                let n = whole(*n, "speed")?;
+                match mode {
+                    Mode::Compact => format!("{}*{}", atom(&source), n),
+                    Mode::Primitive => format!("[{}]", repeated(&source, n, limits)?.join(" ")),
+                }
+            }
+            Modifier::Euclid(arguments) => {
+                // Steps written out are made of the subject written expanded
+                let primitive = || {
+                    let item = Item {
+                        term: item.term.clone(),
+                        modifiers: item.modifiers[..i].to_vec(),
+                        count: 1,
+                        weight: 1,
+                    };
+                    item_source(&item, Mode::Primitive, limits)
+                };
+                euclid(&source, primitive, arguments, mode, limits)?
+            }
+            _ => source,
+        };
+    }
+    Ok(source)
+}
+
+fn term_source(term: &Term, mode: Mode, limits: &Limits) -> Result<String, Error> {
+    match term {
+        Term::Word(value, chance) => {
+            let valid = value.chars().all(|c| c.is_ascii_alphanumeric())
+                || value
+                    .strip_prefix('-')
+                    .is_some_and(|v| v.chars().all(|c| c.is_ascii_digit()));
+            if !valid {
+                return Err(unsupported(&format!("value {}", value)));
+            }
+            Ok(match (chance * 100.0).round() as u8 {
+                100 => value.clone(),
+                probability => format!("{}?{}", value, probability),
+            })
+        }
+        Term::Rest => Ok("~".to_string()),
+        Term::Group(steps) => Ok(format!("[{}]", sequence_source(steps, mode, limits)?)),
+        Term::Alternation(_) if mode == Mode::Primitive => Err(unsupported("nested alternation")),
+        Term::Alternation(steps) => {
+            let options = items(steps)?
+                .iter()
+                .map(|item| match item.weight {
+                    1 => repeated(&item_source(item, Mode::Primitive, limits)?, item.count, limits),
+                    _ => Err(unsupported("weight in an alternation")),
+                })
+                .collect::<Result<Vec<Vec<String>>, Error>>()?;
+            Ok(format!("<{}>", options.concat().join(",")))
+        }
+        Term::Polymeter(..) if mode == Mode::Primitive => Err(unsupported("polymeter in an alternation")),
+        Term::Polymeter(steps, Some(length)) => Ok(format!(
+            "{{{}}}%{}",
+            sequence_source(steps, mode, limits)?,
+            whole(*length, "polymeter length")?
+        )),
+        // Without a number of steps, the steps of the only layer fill the cycle
+        Term::Polymeter(steps, None) => Ok(format!("[{}]", sequence_source(steps, mode, limits)?)),
+    }
+}
+
+// Repetitions apply to a single event or a bracketed group
+fn atom(source: &str) -> String {
+    let bracketed = (source.starts_with('[') && source.ends_with(']'))
+        || (source.starts_with('<') && source.ends_with('>') && !source.contains(' '));
+    let value = source.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    match bracketed || value {
+        true => source.to_string(),
+        false => format!("[{}]", source),
+    }
+}
+
+// Tidal rotates to the left and does not place the pulses as battito does, so the rhythm is written
+// with battito's arguments when they give the same steps, and written out otherwise
+fn euclid(
+    source: &str,
+    primitive: impl Fn() -> Result<String, Error>,
+    arguments: &[Vec<f64>],
+    mode: Mode,
+    limits: &Limits,
+) -> Result<String, Error> {
+    let arguments = arguments
+        .iter()
+        .map(|options| {
+            options
+                .iter()
+                .map(|n| match *n == 0.0 {
+                    true => Ok(0),
+                    false => whole(*n, "euclidean argument"),
+                })
+                .collect::<Result<Vec<u32>, Error>>()
+        })
+        .collect::<Result<Vec<Vec<u32>>, Error>>()?;
+    let (pulses, steps, rotations) = match arguments.as_slice() {
+        [p, s] => (p, s, vec![0]),
+        [p, s, r] => (p, s, r.clone()),
+        _ => return Err(Error::TidalError(TidalError::InvalidSyntax)),
+    };
+    let lengths: Vec<u32> = vec![pulses.len() as u32, steps.len() as u32, rotations.len() as u32];
+    let rhythms = (0..limits.check_measures(lcm_vec(&lengths)? as usize)?)
+        .map(|i| {
+            let (p, s, r) = (
+                pulses[i % pulses.len()],
+                steps[i % steps.len()],
+                rotations[i % rotations.len()],
+            );
+            if s == 0 || p > s {
+                return Err(unsupported(&format!("euclidean rhythm ({},{},{})", p, s, r)));
+            }
+            limits.check_events(s as usize)?;
+            let mut hits = bjorklund(p, s);
+            hits.rotate_left((r % s) as usize);
+            Ok((p, s, hits))
+        })
+        .collect::<Result<Vec<(u32, u32, Vec<bool>)>, Error>>()?;
+
+    if let ([(p, s, hits)], Mode::Compact) = (rhythms.as_slice(), mode) {
+        let rotation = (0..*s).find(|r| Euclidean::hits(*p, *s, *r).ok().as_ref() == Some(hits));
+        let plain = source.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            || (source.starts_with('[') && source.ends_with(']'));
+        match rotation {
+            Some(0) if plain && *p > 0 => return Ok(format!("{}({},{})", source, p, s)),
+            Some(r) if plain && *p > 0 => return Ok(format!("{}({},{},{})", source, p, s, r)),
+            _ => (),
+        }
+    }
+    let subject = primitive()?;
+    let steps = rhythms.iter().map(|(_, s, _)| *s as usize).max().unwrap_or(0);
+    limits.check_events(subject.split(' ').count().saturating_mul(steps))?;
+    let written: Vec<String> = rhythms
+        .iter()
+        .map(|(_, _, hits)| {
+            let steps: Vec<&str> = hits.iter().map(|h| if *h { subject.as_str() } else { "~" }).collect();
+            format!("[{}]", steps.join(" "))
+        })
+        .collect();
+    match written.as_slice() {
+        [rhythm] => Ok(rhythm.clone()),
+        _ if mode == Mode::Primitive => Err(unsupported("nested alternation")),
+        rhythms => Ok(format!("<{}>", rhythms.join(","))),
+    }
+}
+
+// Bjorklund's algorithm as Tidal implements it: the pulses and the rests are grouped and the
+// remainders appended to the groups until at most one group is left over
+fn bjorklund(pulses: u32, steps: u32) -> Vec<bool> {
+    let mut counts = (pulses as usize, (steps - pulses) as usize);
+    let mut xs: Vec<Vec<bool>> = vec![vec![true]; counts.0];
+    let mut ys: Vec<Vec<bool>> = vec![vec![false]; counts.1];
+    let append = |xs: Vec<Vec<bool>>, ys: Vec<Vec<bool>>| -> Vec<Vec<bool>> {
+        xs.into_iter()
+            .zip(ys)
+            .map(|(mut x, y)| {
+                x.extend(y);
+                x
+            })
+            .collect()
+    };
+    while counts.0.min(counts.1) > 1 {
+        let (i, j) = counts;
+        if i > j {
+            let rest = xs.split_off(j);
+            xs = append(xs, ys);
+            ys = rest;
+            counts = (j, i - j);
+        } else {
+            let rest = ys.split_off(i);
+            xs = append(xs, ys);
+            ys = rest;
+            counts = (i, j - i);
+        }
+    }
+    xs.into_iter().chain(ys).flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bjorklund;
+
+    // Rhythms of Toussaint's paper, as Tidal plays them
+    #[test]
+    fn bjorklund_rhythms() {
+        let rhythm = |p, s| -> String { bjorklund(p, s).iter().map(|h| if *h { 'x' } else { '.' }).collect() };
+        assert_eq!("x..x..x.", rhythm(3, 8));
+        assert_eq!("x.xx.xx", rhythm(5, 7));
+        assert_eq!("x..x.x..x.x.", rhythm(5, 12));
+        assert_eq!("x.xx.x.xx.x.", rhythm(7, 12));
+        assert_eq!("x..x.x.x..x.x.x.", rhythm(7, 16));
+        assert_eq!("x.xx.x.x.xx.x.x.", rhythm(9, 16));
+        assert_eq!("....", rhythm(0, 4));
+        assert_eq!("xxxx", rhythm(4, 4));
+    }
+}
@@ -1,12 +1,38 @@
+use crate::pattern::error::{Error, LimitError};
 use gcd::Gcd;
 
-pub fn lcm_vec<'a, I>(v: I) -> u32
+pub fn lcm_vec<'a, I>(v: I) -> Result<u32, Error>
 where
     I: IntoIterator<Item = &'a u32>,
 {
-    v.into_iter().fold(1, |acc, i| lcm(acc, *i))
+    v.into_iter().try_fold(1, |acc, i| lcm(acc, *i))
 }
 
-pub fn lcm(a: u32, b: u32) -> u32 {
-    (a * b) / a.gcd(b)
+pub fn lcm(a: u32, b: u32) -> Result<u32, Error> {
+    match a.gcd(b) {
+        0 => Ok(0),
+        gcd => (a / gcd).checked_mul(b).ok_or(Error::LimitError(LimitError::Overflow)),
+    }
+}
+
+// Small deterministic xorshift generator, so that random choices are reproducible for a given seed
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // xorshift gets stuck on 0, and close seeds should not give close sequences
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u32
+    }
+
+    // Uniform value in [0, n)
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u32() as u128 * n as u128) >> 32) as u64
+    }
 }
@@ -1,7 +1,21 @@
 #[cfg(test)]
 mod tests {
+    use battito_lib::pattern::equivalence::{self, Difference};
+    use battito_lib::pattern::error::{
+        ArithmeticError, Error, LimitError, MidiError, ParsingError, ScaleError, TidalError,
+    };
+    use battito_lib::pattern::grid::{self, Charset, GridOptions};
+    use battito_lib::pattern::humanize::Humanize;
+    use battito_lib::pattern::limits::Limits;
+    use battito_lib::pattern::midi::{self, ImportOptions, Layer, MidiFormat, MidiOptions, Track, Velocity};
+    use battito_lib::pattern::nudge::Boundary;
     use battito_lib::pattern::pattern::{Pattern, TimedEvent};
-    use battito_lib::pattern::transform;
+    use battito_lib::pattern::rational::Rational;
+    use battito_lib::pattern::simplify;
+    use battito_lib::pattern::tempo::Tempo;
+    use battito_lib::pattern::tidal;
+    use battito_lib::pattern::{format, transform, transform_with_options, Options, OutputFormat};
+    use std::num::NonZeroU32;
 
     fn test(first: &str, second: &str) {
         let one = transform(first, None);
@@ -14,12 +28,13 @@ mod tests {
         let out = transform("1 2 3", None);
         let expected = Ok(Pattern {
             steps: vec![
-                TimedEvent::new(1, "1", 100),
-                TimedEvent::new(641, "2", 100),
-                TimedEvent::new(1281, "3", 100),
+                TimedEvent::new(1, "1", 100, 640),
+                TimedEvent::new(641, "2", 100, 640),
+                TimedEvent::new(1281, "3", 100, 640),
             ],
-            length: 1,
+            length: Rational::integer(1),
             subdivision: 1920,
+            measures: vec![1],
         });
         assert_eq!(expected, out);
     }
@@ -29,16 +44,17 @@ mod tests {
         let out = transform("1 2 3 | 4 5 6 7", None);
         let expected = Ok(Pattern {
             steps: vec![
-                TimedEvent::new(1, "1", 100),
-                TimedEvent::new(641, "2", 100),
-                TimedEvent::new(1281, "3", 100),
-                TimedEvent::new(1921, "4", 100),
-                TimedEvent::new(2401, "5", 100),
-                TimedEvent::new(2881, "6", 100),
-                TimedEvent::new(3361, "7", 100),
+                TimedEvent::new(1, "1", 100, 640),
+                TimedEvent::new(641, "2", 100, 640),
+                TimedEvent::new(1281, "3", 100, 640),
+                TimedEvent::new(1921, "4", 100, 480),
+                TimedEvent::new(2401, "5", 100, 480),
+                TimedEvent::new(2881, "6", 100, 480),
+                TimedEvent::new(3361, "7", 100, 480),
             ],
-            length: 2,
+            length: Rational::integer(2),
             subdivision: 1920,
+            measures: vec![1, 1921],
         });
         assert_eq!(expected, out);
     }
@@ -153,28 +169,30 @@ mod tests {
         let out = transform("1 2?25 3 4", None);
         let expected = Ok(Pattern {
             steps: vec![
-                TimedEvent::new(1, "1", 100),
-                TimedEvent::new(481, "2", 25),
-                TimedEvent::new(961, "3", 100),
-                TimedEvent::new(1441, "4", 100),
+                TimedEvent::new(1, "1", 100, 480),
+                TimedEvent::new(481, "2", 25, 480),
+                TimedEvent::new(961, "3", 100, 480),
+                TimedEvent::new(1441, "4", 100, 480),
             ],
-            length: 1,
+            length: Rational::integer(1),
             subdivision: 1920,
+            measures: vec![1],
         });
         assert_eq!(expected, out);
 
         let out = transform("1 [3?22 4] 3 | 5 6", None);
         let expected = Ok(Pattern {
             steps: vec![
-                TimedEvent::new(1, "1", 100),
-                TimedEvent::new(641, "3", 22),
-                TimedEvent::new(961, "4", 100),
-                TimedEvent::new(1281, "3", 100),
-                TimedEvent::new(1921, "5", 100),
-                TimedEvent::new(2881, "6", 100),
+                TimedEvent::new(1, "1", 100, 640),
+                TimedEvent::new(641, "3", 22, 320),
+                TimedEvent::new(961, "4", 100, 320),
+                TimedEvent::new(1281, "3", 100, 640),
+                TimedEvent::new(1921, "5", 100, 960),
+                TimedEvent::new(2881, "6", 100, 960),
             ],
-            length: 2,
+            length: Rational::integer(2),
             subdivision: 1920,
+            measures: vec![1, 1921],
         });
         assert_eq!(expected, out);
 
@@ -196,4 +214,1070 @@ mod tests {
         // test("1 [2 4]?22 3 | 5 6", "1 [2?22 4?22] 3 | 5 6");
         // test("1 <2,4>?22 3 | 5 6", "1 2?22 3 | 1 4?22 3 | 5 6");
     }
+
+    #[test]
+    fn scale() {
+        test("scale(\"minor\", \"0 2 4 <5,7>\")", "60 63 67 <68,72>");
+        test("scale(\"major\", \"0 -1 -7 [9 b]\")", "60 59 48 [76 b]");
+        test("scale(\"minor_pentatonic\", \"0 ~ 5?30 2*2\")", "60 ~ 72?30 [65 65]");
+        test("@scale=dorian root=c3 0 2 4 | 6 7", "48 51 55 | 58 60");
+        test("@scale=major root=48 0(3,8)", "48(3,8)");
+        test("@scale=minor 0 1 2", "60 62 63");
+        test("scale(\"minor\", \"c3\", \"0 2 4\")", "48 51 55");
+        test("scale(\"major\", \"62\", \"0 -1\")", "62 61");
+        test("scale(\"major\", \"7\")", "72");
+        assert!(transform("scale(\"klingon\", \"0 1 2\")", None).is_err());
+        assert!(transform("scale(\"major\", \"h3\", \"0 1 2\")", None).is_err());
+        assert_eq!(
+            Err(Error::DSLParsingError(ParsingError::ScaleError(
+                ScaleError::NoteOutOfRange
+            ))),
+            transform("@scale=major 40", None)
+        );
+        assert!(transform("@scale=major 2147483647", None).is_err());
+        assert!(transform("@scale=klingon 0 1 2", None).is_err());
+        assert!(transform("@root=c3 0 1 2", None).is_err());
+    }
+
+    #[test]
+    fn arpeggio() {
+        test("arp(up, 7 0 4)", "[0 4 7]");
+        test("arp(up, [7 0 4]) 5", "[0 4 7] 5");
+        test("arp(down, 0 [4 7] ~ 12)", "[12 [7 4] ~ 0]");
+        test("arp(up, b 12 a 3)", "[3 12 a b]");
+        test("arp(updown, 0 4 7)", "<[0 4 7],[4 0 4],[7 4 0],[4 7 4]>");
+        test("arp(updown, 0 4 7 12)", "<[0 4 7 12],[7 4 0 4],[7 12 7 4]>");
+        test("arp(up, 7 <0,12> 4) 5", "<[0 4 7],[4 7 12]> 5");
+        test("arp(down, 0 4 7)*2 | 1", "[[7 4 0] [7 4 0]] | 1");
+        test("arp(up, 7 0 4)!2", "[0 4 7] [0 4 7]");
+        test("arp(up, 4 1(3,8))", "[1 [1 ~ ~ 1 ~ ~ 4 ~]]");
+        test("arp(up, 0_ _ 4)", "[0_ _ 4]");
+        test("arp(down, 0_ _ 4)", "[4_ _ 0]");
+        test("arp(down, 0 4_ _)", "[4 0_ _]");
+        test("scale(\"minor\", \"arp(down, 0 2 4)\")", "[67 63 60]");
+    }
+
+    #[test]
+    fn arithmetic() {
+        test("\"0 2 4\" + \"<0,12>\"", "0 2 4 | 12 14 16");
+        test("(0 3 7)+5", "5 8 12");
+        test("\"0 1\"*2", "0 2");
+        // A bare value followed by *N is a repetition, scaling needs quotes or parentheses
+        test("3*2", "[3 3]");
+        test("(3)*2", "6");
+        test("\"3\"*2", "6");
+        test("\"0 1 2 3\" + \"10 20\"", "10 11 22 23");
+        test("\"0 ~ 2\" - 1", "-1 ~ 1");
+        test("\"0 [1 2]\" + \"<0,12> 100\"", "0 [101 102] | 12 [101 102]");
+        test("\"0 1\" + \"5 ~\"", "5 1");
+        test("\"0 1\" + 2 * 3", "6 9");
+        test("\"0?30 1*2\" + 1", "1?30 [2 2]");
+        test("\"{0 1 2}%4\" + 12", "12 13 14 12 | 13 14 12 13 | 14 12 13 14");
+        test("(0 1) + 2 | 5 6", "2 3 | 5 6");
+        test("@scale=minor \"0 2\" + \"<0,7>\"", "60 63 | 72 75");
+        assert_eq!(
+            Err(Error::ArithmeticError(ArithmeticError::NonNumericValue(
+                "x".to_string()
+            ))),
+            transform("\"0 1\" + x", None)
+        );
+        assert_eq!(
+            Err(Error::ArithmeticError(ArithmeticError::NonNumericValue(
+                "a".to_string()
+            ))),
+            transform("\"a b\" + 1", None)
+        );
+    }
+
+    #[test]
+    fn range() {
+        test("0..7", "0 1 2 3 4 5 6 7");
+        test("7..0", "7 6 5 4 3 2 1 0");
+        test("0..12:3", "0 3 6 9 12");
+        test("-2..2 b", "-2 -1 0 1 2 b");
+        test("[0..3] 7", "[0 1 2 3] 7");
+        test("<0..2,7> 5", "<0,1,2,7> 5");
+        test("<[0..3],7>", "<[0 1 2 3],7>");
+        test("{0..4}%4", "0 1 2 3 | 4 0 1 2 | 3 4 0 1 | 2 3 4 0 | 1 2 3 4");
+        test("[0..2]*2", "[[0 1 2] [0 1 2]]");
+        test("[0..2]!2", "0 1 2 0 1 2");
+        test("@scale=major 0..7", "60 62 64 65 67 69 71 72");
+        assert!(transform("0..4:0", None).is_err());
+    }
+
+    #[test]
+    fn reversed() {
+        test("rev[a b c]", "[c b a]");
+        test("rev[a [b c]] d", "[[c b] a] d");
+        test("rev[a <b,[c d]>]", "[<b,[d c]> a]");
+        test("rev[a*2 b]", "[b [a a]]");
+        test("rev[b(3,8,1)]", "[b(3,8,0)]");
+        test("rev[0..3] | 4", "[3 2 1 0] | 4");
+    }
+
+    #[test]
+    fn rotated() {
+        test("rot(1)[a b c d]", "[b c d a]");
+        test("rot(-1)[a b c d]", "[d a b c]");
+        test("rot(5)[a b c d]", "[b c d a]");
+        test("rot(0)[a [b c]]", "[a [b c]]");
+        test("rot(1)[a <b,c> d]", "[<b,c> d a]");
+    }
+
+    #[test]
+    fn palindrome() {
+        test("palin[a b c]", "<[a b c],[c b a]>");
+        test("palin[a [b c]] d", "<[a [b c]],[[c b] a]> d");
+        test("palin[a <b,c>]", "<[a b],[c a]>");
+        test("palin[a <b,c,d>]", "<[a b],[c a],[a d],[b a],[a c],[d a]>");
+    }
+
+    #[test]
+    fn transformations_composition() {
+        test("rev[rot(1)[a b c d]]", "[a d c b]");
+        test("rot(1)[rev[a b] c]", "[c [b a]]");
+        test("palin[rev[a b]]", "<[b a],[a b]>");
+        test("rev[a b]*2", "[[b a] [b a]]");
+        test("rev[a b]!2 c", "[b a] [b a] c");
+        test("rot(1)[a b c]!2", "[b c a] [b c a]");
+        test("palin[a b]*2", "[<[a b],[b a]> <[a b],[b a]>]");
+        test("{rev[a b] c}%3", "[b a] c [b a] | c [b a] c");
+    }
+
+    #[test]
+    fn conditional() {
+        test("every(4, rev, [a b c d])", "<[d c b a],[a b c d],[a b c d],[a b c d]>");
+        test("every(2, fast 2, a b) c", "<[[a b] [a b]],[a b]> c");
+        test("every(2, rot 1, a b c)", "<[b c a],[a b c]>");
+        test("whenmod(4, 2, rev, a b)", "<[a b],[a b],[b a],[b a]>");
+        test(
+            "whenmod(8, 6, fast 2, a b)",
+            "<[a b],[a b],[a b],[a b],[a b],[a b],[[a b] [a b]],[[a b] [a b]]>",
+        );
+        test("every(2, rev, a <b,c,d>)", "<[b a],[a c],[d a],[a b],[c a],[a d]>");
+        test("every(3, palin, a b)", "<[a b],[a b],[a b],[b a],[a b],[a b]>");
+        test("every(2, rev, a b)!2", "<[b a],[a b]> <[b a],[a b]>");
+        // Only a group written alone is the value, groups followed by more elements are elements
+        test("every(2, rev, [a b])", "every(2, rev, a b)");
+        test("every(2, rev, [a b]*2)", "every(2, rev, a b a b)");
+        test("every(2, rev, [a b] c)", "<[c [b a]],[[a b] c]>");
+        test("every(2, rev, [a] [b]!2 c)", "<[c b b a],[a b b c]>");
+        // A group followed by more elements is not parsed again, which would take exponential time
+        // on nested conditionals
+        let nested = (0..30).fold("a".to_string(), |v, _| format!("every(2, rev, [{}] b)", v));
+        assert!(transform(&nested, None).is_ok());
+        assert!(transform("every(0, rev, a b)", None).is_err());
+    }
+
+    #[test]
+    fn trig_condition() {
+        test("sn@1:4", "<sn,~,~,~>");
+        test("sn@3:4 hh", "<~,~,sn,~> hh");
+        test("bd sn?50@2:2", "bd <~,sn?50>");
+        test("[bd sn@1:2]*2", "[[bd <sn,~>] [bd <sn,~>]]");
+        test("sn@1:2 | hh", "sn | ~ | hh");
+        assert!(transform("sn@0:4", None).is_err());
+        assert!(transform("sn@5:4", None).is_err());
+    }
+
+    #[test]
+    fn swing() {
+        let out = transform("@swing=8:0.6 a b c d", None);
+        let expected = Ok(Pattern {
+            steps: vec![
+                TimedEvent::new(1, "a", 100, 480),
+                TimedEvent::new(481, "b", 100, 480),
+                TimedEvent::new(961, "c", 100, 480),
+                TimedEvent::new(1441, "d", 100, 480),
+            ],
+            length: Rational::integer(1),
+            subdivision: 1920,
+            measures: vec![1],
+        });
+        assert_eq!(out, expected);
+
+        let out = transform("@swing=8:0.6 a b c d e f g h", None);
+        let expected = Ok(Pattern {
+            steps: vec![
+                TimedEvent::new(1, "a", 100, 288),
+                TimedEvent::new(289, "b", 100, 192),
+                TimedEvent::new(481, "c", 100, 288),
+                TimedEvent::new(769, "d", 100, 192),
+                TimedEvent::new(961, "e", 100, 288),
+                TimedEvent::new(1249, "f", 100, 192),
+                TimedEvent::new(1441, "g", 100, 288),
+                TimedEvent::new(1729, "h", 100, 192),
+            ],
+            length: Rational::integer(1),
+            subdivision: 1920,
+            measures: vec![1],
+        });
+        assert_eq!(out, expected);
+
+        test("swing(8, 0.6)[a b c d e f g h]", "@swing=8:0.6 a b c d e f g h");
+        test("swing(8, 0.5)[a b c d e f g h]", "a b c d e f g h");
+        test(
+            "@swing=8:0.6 [a b c d] swing(8, 0.5)[e f g h]",
+            "swing(8, 0.6)[a b c d] [e f g h]",
+        );
+        test(
+            "swing(8, 0.6)[[a b c d] swing(8, 0.5)[e f g h]]",
+            "swing(8, 0.6)[a b c d] [e f g h]",
+        );
+        assert!(transform("swing(8, 1.5)[a b]", None).is_err());
+        assert!(transform("swing(0, 0.6)[a b]", None).is_err());
+        assert!(transform("@swing=8 a b", None).is_err());
+    }
+
+    #[test]
+    fn nudge() {
+        let out = transform("a b>10 c<5 d@+0.02", None);
+        let expected = Ok(Pattern {
+            steps: vec![
+                TimedEvent::new(1, "a", 100, 480),
+                TimedEvent::new(491, "b", 100, 480),
+                TimedEvent::new(956, "c", 100, 480),
+                TimedEvent::new(1479, "d", 100, 442),
+            ],
+            length: Rational::integer(1),
+            subdivision: 1920,
+            measures: vec![1],
+        });
+        assert_eq!(out, expected);
+
+        test("a?50>10 b", "a?50@+0.0052083 b");
+        test("sn@+0.5 ~", "~ sn");
+        test("~ sn@-0.5", "sn ~");
+        // ^ is the same nudge
+        test("sn^+0.5 ~", "sn@+0.5 ~");
+        test("sn@1:2 ~", "<sn,~> ~");
+        test("sn@+0.5@1:2 ~", "~ <sn,~>");
+        let overflow = Err(Error::LimitError(LimitError::Overflow));
+        assert_eq!(overflow, transform("a b>9223372036854775807", None));
+        assert_eq!(overflow, transform("a b^+1e300", None));
+        assert_eq!(overflow, transform("a b@-1e300", None));
+        // The largest nudges end up at the boundary
+        let clamped = transform("a b>4294967295", None).unwrap();
+        assert_eq!((1920, 1), (clamped.steps[1].index, clamped.steps[1].duration));
+        test("a<10 b", "a b");
+
+        let options = Options {
+            boundary: Boundary::Wrap,
+            ..Options::default()
+        };
+        let out = transform_with_options("a<10 b | c d", &options);
+        let expected = Ok(Pattern {
+            steps: vec![
+                TimedEvent::new(961, "b", 100, 960),
+                TimedEvent::new(1921, "c", 100, 960),
+                TimedEvent::new(2881, "d", 100, 960),
+                TimedEvent::new(3831, "a", 100, 10),
+            ],
+            length: Rational::integer(2),
+            subdivision: 1920,
+            measures: vec![1, 1921],
+        });
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn ratchet() {
+        let out = transform("bd hh:r3:p0.5 | sn:r2:v0.5", None);
+        let expected = Ok(Pattern {
+            steps: vec![
+                TimedEvent::new(1, "bd", 100, 960),
+                TimedEvent::new(961, "hh", 100, 320).with_ratchet(0),
+                TimedEvent::new(1281, "hh", 50, 320).with_ratchet(0),
+                TimedEvent::new(1601, "hh", 25, 320).with_ratchet(0),
+                TimedEvent::new(1921, "sn", 100, 960).with_ratchet(1),
+                TimedEvent::new(2881, "sn", 100, 960).with_ratchet(1).with_velocity(50),
+            ],
+            length: Rational::integer(2),
+            subdivision: 1920,
+            measures: vec![1, 1921],
+        });
+        assert_eq!(out, expected);
+        assert_eq!(
+            "1 bd 100 -1, 961 hh 100 0, 1281 hh 50 0, 1601 hh 25 0, 1921 sn 100 1, 2881 sn 100 1",
+            out.unwrap().format_steps(OutputFormat::Max)
+        );
+
+        test("hh:r2", "hh:r2:v1");
+        test("[bd hh:r2]*2", "[bd hh:r2] [bd hh:r2]");
+        test("hh?50:r2>10", "hh?50:r2^+0.0052083");
+        assert!(transform("hh:r0", None).is_err());
+        assert!(transform("hh:r2:v2", None).is_err());
+
+        // Swing makes e end after the 5/8 of a cycle, its last repetition falls past the end
+        let indexes = |boundary| -> Vec<(u32, String)> {
+            let options = Options {
+                boundary,
+                ..Options::default()
+            };
+            let pattern = transform_with_options("@swing=8:0.75 a b c d e:r4 @5/8", &options).unwrap();
+            pattern.steps.into_iter().map(|te| (te.index, te.event.value)).collect()
+        };
+        let steps =
+            |steps: &[(u32, &str)]| -> Vec<(u32, String)> { steps.iter().map(|(i, v)| (*i, v.to_string())).collect() };
+        let played = [
+            (1, "a"),
+            (361, "b"),
+            (481, "c"),
+            (841, "d"),
+            (961, "e"),
+            (1051, "e"),
+            (1141, "e"),
+        ];
+        assert_eq!(steps(&played), indexes(Boundary::Clamp));
+        let wrapped = [
+            (1, "a"),
+            (31, "e"),
+            (361, "b"),
+            (481, "c"),
+            (841, "d"),
+            (961, "e"),
+            (1051, "e"),
+            (1141, "e"),
+        ];
+        assert_eq!(steps(&wrapped), indexes(Boundary::Wrap));
+    }
+
+    #[test]
+    fn humanize() {
+        let input = "a b c d | e f g h";
+        let humanized = |seed| {
+            let options = Options {
+                humanize: Some(Humanize::new(20, 10, seed)),
+                ..Options::default()
+            };
+            transform_with_options(input, &options).unwrap()
+        };
+        let straight = transform(input, None).unwrap();
+        assert_eq!(humanized(7), humanized(7));
+        assert_ne!(humanized(7), humanized(8));
+        assert_eq!(straight.steps.len(), humanized(7).steps.len());
+        humanized(7).steps.iter().for_each(|te| {
+            let nominal = straight.steps.iter().find(|s| s.event.value == te.event.value).unwrap();
+            assert!((te.index as i64 - nominal.index as i64).abs() <= 20);
+            assert!((90..=110).contains(&te.event.velocity));
+        });
+
+        let none = Options {
+            humanize: Some(Humanize::new(0, 0, 7)),
+            ..Options::default()
+        };
+        assert_eq!(
+            Ok(transform(input, None).unwrap()),
+            transform_with_options(input, &none)
+        );
+
+        // The humanize of the DSL takes precedence over the one of the options
+        let wrapped = |seed| transform(&format!("humanize(20, 10, {})[a b c d] | e f g h", seed), None).unwrap();
+        assert_eq!(wrapped(7), wrapped(7));
+        assert_ne!(wrapped(7), wrapped(8));
+        assert_eq!(&straight.steps[4..], &wrapped(7).steps[4..]);
+        let options = Options {
+            humanize: Some(Humanize::new(20, 10, 7)),
+            ..Options::default()
+        };
+        assert_eq!(
+            Ok(straight),
+            transform_with_options("humanize(0, 0)[a b c d] | humanize(0, 0)[e f g h]", &options)
+        );
+        assert!(format("humanize(10)[a b]").is_err());
+        assert!(format("humanize(10, 256)[a b]").is_err());
+        test("humanize(3,8)", "humanize ~ ~ humanize ~ ~ humanize ~");
+    }
+
+    #[test]
+    fn tie() {
+        let out = transform("a b c_ | _ d", None);
+        let expected = Ok(Pattern {
+            steps: vec![
+                TimedEvent::new(1, "a", 100, 640),
+                TimedEvent::new(641, "b", 100, 640),
+                TimedEvent::new(1281, "c", 100, 1600),
+                TimedEvent::new(2881, "d", 100, 960),
+            ],
+            length: Rational::integer(2),
+            subdivision: 1920,
+            measures: vec![1, 1921],
+        });
+        assert_eq!(out, expected);
+
+        test("a_ _ b", "a_ [_ _] b");
+        let expected = |measures| Pattern {
+            steps: vec![TimedEvent::new(1, "a", 100, 5760)],
+            length: Rational::integer(3),
+            subdivision: 1920,
+            measures,
+        };
+        assert_eq!(transform("a_ | _ | _", None), Ok(expected(vec![1, 1921, 3841])));
+        // The same event as "a / 3", which is a single measure
+        assert_eq!(transform("a / 3", None), Ok(expected(vec![1])));
+        test("[a_ _]*2", "a a");
+        test("a_ ~ _", "a ~ ~");
+        test("_ a", "~ a");
+        test("a _", "a ~");
+        test("a_ b", "a b");
+        test("\"0_ _ 1\" + 2", "2_ _ 3");
+        test("@scale=major 1_ _ 2", "62_ _ 64");
+    }
+
+    #[test]
+    fn measure_length() {
+        let out = transform("a b c | (3/4) d e f | g h @1/2", None);
+        let expected = Ok(Pattern {
+            steps: vec![
+                TimedEvent::new(1, "a", 100, 640),
+                TimedEvent::new(641, "b", 100, 640),
+                TimedEvent::new(1281, "c", 100, 640),
+                TimedEvent::new(1921, "d", 100, 480),
+                TimedEvent::new(2401, "e", 100, 480),
+                TimedEvent::new(2881, "f", 100, 480),
+                TimedEvent::new(3361, "g", 100, 480),
+                TimedEvent::new(3841, "h", 100, 480),
+            ],
+            length: Rational::new(9, 4),
+            subdivision: 1920,
+            measures: vec![1, 1921, 3361],
+        });
+        assert_eq!(out, expected);
+        assert_eq!("1 1921 3361", out.unwrap().format_measures());
+
+        test("a b @2", "a b / 2");
+        test("a | b @1 | c", "a | b | c");
+        test("<a,b> @1/2 | c", "(1/2) a | (1/2) b | c");
+        test("(3/2) a b / 3", "a b @3");
+        assert!(transform("a b @0", None).is_err());
+        assert!(transform("(0/4) a b", None).is_err());
+        assert!(transform("a b @3/0", None).is_err());
+    }
+
+    #[test]
+    fn tempo() {
+        let options = Options {
+            tempo: Some(Tempo::new(120.0, 1.0).unwrap()),
+            ..Options::default()
+        };
+        let out = transform_with_options("a b | c @60bpm | (1/2) d", &options);
+        let expected = Ok(Pattern {
+            steps: vec![
+                TimedEvent::new(1, "a", 100, 960).with_time(0.0, 1000.0),
+                TimedEvent::new(961, "b", 100, 960).with_time(1000.0, 1000.0),
+                TimedEvent::new(1921, "c", 100, 1920).with_time(2000.0, 4000.0),
+                TimedEvent::new(3841, "d", 100, 960).with_time(6000.0, 2000.0),
+            ],
+            length: Rational::new(5, 2),
+            subdivision: 1920,
+            measures: vec![1, 1921, 3841],
+        });
+        assert_eq!(out, expected);
+        assert_eq!(
+            "1 a 100 0 1000, 961 b 100 1000 1000, 1921 c 100 2000 4000, 3841 d 100 6000 2000",
+            out.unwrap().format_steps(OutputFormat::Max)
+        );
+
+        let half_bar = Options {
+            tempo: Some(Tempo::new(120.0, 2.0).unwrap()),
+            ..Options::default()
+        };
+        let out = transform_with_options("a b", &half_bar).unwrap();
+        assert_eq!(Some(500.0), out.steps[1].time.map(|t| t.onset_ms));
+        assert_eq!(Some(500.0), out.steps[1].time.map(|t| t.duration_ms));
+
+        test("@bpm=90 a b | c", "a b @90bpm | c");
+        test("a b @3/4 @90bpm", "(3/4) a b @90bpm");
+        assert!(transform("a b", None).unwrap().steps[0].time.is_none());
+        assert!(transform("a b @0bpm", None).is_err());
+        assert!(transform("@bpm=0 a b", None).is_err());
+    }
+
+    #[test]
+    fn query() {
+        let pattern = transform("a b c d | e f", None).unwrap();
+        let limits = Limits::default();
+        let onsets = |start, end| -> Vec<(Rational, i64, String)> {
+            pattern
+                .query(start, end, &limits)
+                .unwrap()
+                .into_iter()
+                .map(|o| (o.onset, o.repetition, o.timed_event.event.value))
+                .collect()
+        };
+        let r = Rational::new;
+        assert_eq!(
+            vec![(r(1, 2), 0, "c".to_string()), (r(3, 4), 0, "d".to_string())],
+            onsets(r(1, 2), r(1, 1))
+        );
+        assert_eq!(
+            vec![
+                (r(3, 2), 0, "f".to_string()),
+                (r(2, 1), 1, "a".to_string()),
+                (r(9, 4), 1, "b".to_string())
+            ],
+            onsets(r(3, 2), r(5, 2))
+        );
+        assert_eq!(24, pattern.query(r(-2, 1), r(6, 1), &limits).unwrap().len());
+        assert_eq!(vec![(r(-1, 2), -1, "f".to_string())], onsets(r(-1, 2), r(0, 1)));
+        assert_eq!(r(1, 4), pattern.query(r(0, 1), r(1, 4), &limits).unwrap()[0].duration);
+        assert!(pattern.query(r(1, 1), r(1, 1), &limits).unwrap().is_empty());
+        assert!(pattern.query(r(5, 8), r(3, 4), &limits).unwrap().is_empty());
+        // Spans far from the first loop only read their own repetitions
+        assert_eq!(
+            vec![(r(2_000_001, 2), 500_000, "c".to_string())],
+            onsets(r(2_000_001, 2), r(4_000_003, 4))
+        );
+        let events = Limits {
+            max_events: 10,
+            ..Limits::default()
+        };
+        assert_eq!(10, pattern.query(r(0, 1), r(3, 1), &events).unwrap().len());
+        assert_eq!(
+            Err(Error::LimitError(LimitError::TooManyEvents)),
+            pattern.query(r(0, 1), r(1_000_000_000, 1), &events)
+        );
+    }
+
+    #[test]
+    fn lazy_expansion() {
+        test("<1,2,3> <4,5>", "1 4 | 2 5 | 3 4 | 1 5 | 2 4 | 3 5");
+        test("{<1,2> 3 4}%2", "1 3 | 4 2 | 3 4");
+
+        let pattern = transform("<1,2,3,4,5,6,7> <1,2,3,4,5,6,7,8,9,10,11>", Some(4)).unwrap();
+        assert_eq!(77, pattern.measures.len());
+        assert_eq!(154, pattern.steps.len());
+        let last: Vec<String> = pattern.steps[152..].iter().map(|te| te.event.value.clone()).collect();
+        assert_eq!(vec!["7", "11"], last);
+
+        let events: Vec<_> = pattern.events().collect();
+        assert_eq!(pattern.ticks() as usize, events.len());
+        assert_eq!(308, events.len());
+        assert_eq!("2", events[4].value);
+        assert_eq!(0, events[5].probability);
+    }
+
+    #[test]
+    fn limits() {
+        let with_limits = |input: &str, limits: Limits| {
+            transform_with_options(
+                input,
+                &Options {
+                    limits,
+                    ..Options::default()
+                },
+            )
+        };
+        let error = |e| Err(Error::LimitError(e));
+
+        let events = Limits {
+            max_events: 10,
+            ..Limits::default()
+        };
+        assert!(with_limits("a!10", events).is_ok());
+        assert_eq!(error(LimitError::TooManyEvents), with_limits("a!11", events));
+        assert_eq!(error(LimitError::TooManyEvents), with_limits("[a b]*6", events));
+        assert_eq!(error(LimitError::TooManyEvents), with_limits("a(3,16)", events));
+        assert_eq!(error(LimitError::TooManyEvents), with_limits("0..20", events));
+        assert_eq!(error(LimitError::TooManyEvents), with_limits("a:r16", events));
+        assert_eq!(
+            error(LimitError::TooManyEvents),
+            with_limits("a b c d e f | a b c d e", events)
+        );
+
+        let measures = Limits {
+            max_measures: 50,
+            ..Limits::default()
+        };
+        assert!(with_limits("<1,2,3,4,5,6,7> <1,2,3,4,5,6,7>", measures).is_ok());
+        assert_eq!(
+            error(LimitError::TooManyMeasures),
+            with_limits("<1,2,3,4,5,6,7> <1,2,3,4,5,6,7,8,9,10,11>", measures)
+        );
+        assert_eq!(error(LimitError::TooManyMeasures), with_limits("a / 51", measures));
+
+        let depth = Limits {
+            max_depth: 3,
+            ..Limits::default()
+        };
+        assert!(with_limits("[a [b <c,d>]] e<10", depth).is_ok());
+        assert_eq!(error(LimitError::TooDeep), with_limits("[a [b [c <d,e>]]]", depth));
+        let nested = format!("{}a{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert_eq!(error(LimitError::TooDeep), transform(&nested, None));
+
+        assert_eq!(
+            error(LimitError::SubdivisionTooLarge),
+            transform("a b", Some(10_000_000))
+        );
+
+        // The product of these lengths does not fit in a u32
+        let primes = [23, 29, 31, 37, 41, 43, 47];
+        let alternates: Vec<String> = primes
+            .iter()
+            .map(|p| format!("<{}>", vec!["a"; *p].join(",")))
+            .collect();
+        assert_eq!(error(LimitError::Overflow), transform(&alternates.join(" "), None));
+        assert_eq!(error(LimitError::Overflow), transform("a / 99999999999", None));
+        assert_eq!(error(LimitError::Overflow), transform("a!99999999999", None));
+        assert_eq!(error(LimitError::TooManyEvents), transform("[[a!100]!100]!101", None));
+        // Not a polymetric measure, and no division by zero
+        test("{a b}%0", "");
+        test("[]", "");
+        test("a []", "a ~");
+        assert!(transform("a*0", None).is_err());
+        assert!(transform("[a b]*0", None).is_err());
+        assert!(transform("every(2, fast 0, a)", None).is_err());
+        assert_eq!(error(LimitError::TooManyMeasures), transform("sn@1:4294967295", None));
+        assert_eq!(error(LimitError::Overflow), transform("a @9223372036854775807", None));
+        assert_eq!(
+            error(LimitError::Overflow),
+            transform("a @1/3037000500 | b @1/3037000501", None)
+        );
+        // Nudges are counted like any other event, and their offsets are checked
+        assert!(with_limits("a>10!10", events).is_ok());
+        assert_eq!(error(LimitError::TooManyEvents), with_limits("a>10!11", events));
+        assert_eq!(error(LimitError::TooManyEvents), with_limits("[a@+0.1 b<5]*6", events));
+        assert_eq!(error(LimitError::TooDeep), with_limits("[a [b [c>1 <d,e>]]]", depth));
+        assert_eq!(error(LimitError::Overflow), transform("a b>9223372036854775807", None));
+        assert_eq!(error(LimitError::Overflow), transform("a b<4294967296", None));
+        assert_eq!(error(LimitError::Overflow), transform("[a b@+1e300] c", None));
+        // Limits reached inside a group are not taken for a syntax error
+        assert_eq!(error(LimitError::TooManyEvents), with_limits("[a [b!11]] c", events));
+        // Nested groups are parsed once, not once per alternative
+        let nested = format!("{}a{}", "[".repeat(60), "]".repeat(60));
+        assert!(transform(&nested, None).is_ok());
+    }
+
+    #[test]
+    fn midi() {
+        let single = MidiOptions {
+            format: MidiFormat::Single,
+            ..MidiOptions::default()
+        };
+        let tracks = vec![Track::new("beat", transform("bd sn", Some(96)).unwrap())];
+        let expected: Vec<u8> = vec![
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 24, // type 0, one track, 24 ticks per quarter
+            b'M', b'T', b'r', b'k', 0, 0, 0, 43, // track of 43 bytes
+            0x00, 0xFF, 0x03, 4, b'b', b'e', b'a', b't', // name
+            0x00, 0xFF, 0x58, 4, 4, 2, 24, 8, // 4/4
+            0x00, 0xFF, 0x51, 3, 0x07, 0xA1, 0x20, // 120 bpm
+            0x00, 0x90, 36, 100, 0x30, 0x80, 36, 0x40, // bd for two beats
+            0x00, 0x90, 38, 100, 0x30, 0x80, 38, 0x40, // sn for two beats
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        assert_eq!(Ok(expected), midi::write(&tracks, &single));
+
+        // A tempo track and one track per layer, the loop lasts until the end of the pattern
+        let layers = vec![
+            Track::new("kick", transform("bd ~", Some(96)).unwrap()),
+            Track {
+                channel: 9,
+                ..Track::new("snare", transform("~ sn?50", Some(96)).unwrap())
+            },
+        ];
+        let options = MidiOptions {
+            velocity: Velocity::Probability,
+            ..MidiOptions::default()
+        };
+        let file = midi::write(&layers, &options).unwrap();
+        assert_eq!(&[0, 1, 0, 3, 0, 24], &file[8..14]);
+        let kick = [0x00, 0x90, 36, 127, 0x30, 0x80, 36, 0x40, 0x30, 0xFF, 0x2F, 0x00];
+        assert!(file.windows(kick.len()).any(|w| w == kick));
+        let snare = [0x30, 0x99, 38, 63, 0x30, 0x89, 38, 0x40, 0x00, 0xFF, 0x2F, 0x00];
+        assert!(file.windows(snare.len()).any(|w| w == snare));
+
+        let unmapped = vec![Track::new("", transform("kick", None).unwrap())];
+        assert_eq!(
+            Err(Error::MidiError(MidiError::UnmappedValue("kick".to_string()))),
+            midi::write(&unmapped, &options)
+        );
+
+        // The division fits the subdivisions of every track
+        let mixed = vec![
+            Track::new("", transform("bd", Some(96)).unwrap()),
+            Track::new("", transform("sn", Some(144)).unwrap()),
+        ];
+        let file = midi::write(&mixed, &options).unwrap();
+        assert_eq!(&[0, 72], &file[12..14]);
+        let whole = [0x00, 0x90, 38, 127, 0x82, 0x20, 0x80, 38, 0x40];
+        assert!(file.windows(whole.len()).any(|w| w == whole));
+        // Delta times are at most 0x0FFFFFFF ticks
+        let long = MidiOptions {
+            tempo: Tempo::new(120.0, 0.00001).unwrap(),
+            ..MidiOptions::default()
+        };
+        let beat = vec![Track::new("", transform("bd", None).unwrap())];
+        assert_eq!(Err(Error::MidiError(MidiError::TooLong)), midi::write(&beat, &long));
+    }
+
+    #[test]
+    fn midi_import() {
+        let import = |inputs: &[&str], grid: u32| -> Vec<Layer> {
+            let tracks: Vec<Track> = inputs
+                .iter()
+                .map(|input| Track::new("loop", transform(input, Some(96)).unwrap()))
+                .collect();
+            let file = midi::write(&tracks, &MidiOptions::default()).unwrap();
+            let options = ImportOptions {
+                grid,
+                ..ImportOptions::default()
+            };
+            midi::import(&file, &options).unwrap()
+        };
+        let expressions = |layers: Vec<Layer>| -> Vec<String> { layers.into_iter().map(|l| l.expression).collect() };
+        // Drum hits only keep their onsets, lengths are not imported
+        let onsets = |input: &str| -> Vec<(u32, String)> {
+            let pattern = transform(input, Some(96)).unwrap();
+            pattern.steps.into_iter().map(|te| (te.index, te.event.value)).collect()
+        };
+
+        let compact = [
+            ("bd [~ bd] sn ~", "[bd ~ ~ bd] sn"),
+            ("bd(3,8)", "bd(3,8)"),
+            ("hh hh hh hh hh hh hh hh", "hh*8"),
+            ("bd sn bd sn bd sn bd sn", "[bd sn]*4"),
+            ("bd bd bd sn", "bd!3 sn"),
+            ("bd | sn", "bd | sn"),
+            ("bd(5,8,2) | ~ sn", "bd(5,8,2) | ~ sn"),
+        ];
+        for (input, expected) in &compact {
+            let imported = import(&[input], 8);
+            assert_eq!(vec![expected.to_string()], expressions(imported.clone()));
+            assert_eq!("loop", imported[0].name);
+            assert_eq!(onsets(input), onsets(expected));
+        }
+        assert_eq!(vec!["bd sn"], expressions(import(&["bd ~ sn ~ | bd ~ sn ~"], 16)));
+        // Notes off the grid are moved to the nearest step
+        assert_eq!(
+            vec!["bd sn"],
+            expressions(import(&["[bd ~ ~ ~ ~ ~ ~ ~ ~ sn ~ ~ ~ ~ ~ ~]"], 2))
+        );
+
+        // Notes sounding together are split in layers
+        let layers = import(&["bd(3,8)", "hh*8"], 8);
+        assert_eq!(
+            vec![
+                Layer {
+                    name: "bd".to_string(),
+                    expression: "bd(3,8)".to_string()
+                },
+                Layer {
+                    name: "hh".to_string(),
+                    expression: "hh*8".to_string()
+                }
+            ],
+            layers
+        );
+
+        assert_eq!(
+            Err(Error::MidiError(MidiError::InvalidFile)),
+            midi::import(b"MThd", &ImportOptions::default())
+        );
+        // A system common message inside a track
+        let mut file = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x18MTrk\x00\x00\x00\x08".to_vec();
+        file.extend_from_slice(&[0x00, 0xF2, 0x00, 0x00, 0x00, 0xFF, 0x2F, 0x00]);
+        assert_eq!(
+            Err(Error::MidiError(MidiError::InvalidFile)),
+            midi::import(&file, &ImportOptions::default())
+        );
+    }
+
+    #[test]
+    fn format_source() {
+        assert_eq!(Ok("[[a b]] c".to_string()), format("  [ [a  b] ]\tc \n"));
+        assert_eq!(Ok("[a] [[b]] <c,[d]>".to_string()), format("[a] [[b]] < c , [d] >"));
+        // The expansions are kept as written
+        assert_eq!(Ok("bd(3,8) | ~ sn".to_string()), format("bd( 3, 8 ) | ~ sn"));
+        assert_eq!(Ok("(0 2) + 1 @3/4".to_string()), format("( 0 2 ) + 1 @3/4"));
+        assert_eq!(
+            Ok("every(2, rev, swing(8, 0.7)[a b]) [a b]*2".to_string()),
+            format("every(2,rev,swing( 8 ,0.7)[a b])  [a b]*2")
+        );
+        // Input that is not entirely a sequence is not dropped
+        assert_eq!(Err(Error::DSLParsingError(ParsingError::Generic)), format("a b ] c"));
+        assert_eq!(Err(Error::LimitError(LimitError::TooDeep)), format(&"[".repeat(100)));
+
+        // The formatted source plays like the input, and formatting it again changes nothing
+        for input in &[
+            "bd(<3,5>,8,<0,2>) [sn cp]!2 hh*4",
+            "{a b c}%4 | rev[a b c] | rot(1)[a b c] | palin[a b]",
+            "every(2, rev, swing(8, 0.7)[a b]) c",
+            "swing(8, 0.7)[a swing(16, 0.6)[b c]] d:r3:v0.5>10 e_ _",
+            "@scale=dorian root=62 swing=16:0.6 bpm=120 0 2 <4,5> | 0..3",
+            "\"0 3 7\" + \"<0,12>\" | a b @140bpm / 3",
+        ] {
+            let formatted = format(input).unwrap();
+            test(&formatted, input);
+            assert_eq!(Ok(formatted.clone()), format(&formatted));
+        }
+    }
+
+    #[test]
+    fn complete_input() {
+        let complete = Options {
+            complete: true,
+            ..Options::default()
+        };
+        // Only the pattern the input starts with is read by default
+        assert_eq!(transform("a", None), transform("a ]", None));
+        assert!(transform("[[[", None).is_ok());
+        assert_eq!(
+            Err(Error::DSLParsingError(ParsingError::Generic)),
+            transform_with_options("a ]", &complete)
+        );
+        assert!(transform_with_options("[[[", &complete).is_err());
+        assert_eq!(transform("a b", None), transform_with_options(" a  b\n", &complete));
+        assert_eq!(Ok("a b".to_string()), simplify::simplify("a  b ", &complete));
+        assert!(simplify::simplify("[[[", &complete).is_err());
+    }
+
+    #[test]
+    fn simplify_expression() {
+        let simplify = |input: &str| simplify::simplify(input, &Options::default()).unwrap();
+        for (input, expected) in &[
+            ("bd ~ ~ bd ~ ~ bd ~", "bd(3,8)"),
+            ("hh hh hh hh hh hh hh hh", "hh*8"),
+            ("[a b] [a b] [a b]", "[a b]*3"),
+            ("bd sn | bd cp", "bd <sn,cp>"),
+            ("bd sn | bd cp | bd sn | bd hh", "bd <sn,cp,sn,hh>"),
+            ("[a a a a] b?50 b?50 [c _ _ d]", "a*4 b?50!2 [c ~ ~ d]"),
+            ("bd(3,8) | bd(5,8)", "bd(<3,5>,8)"),
+            ("\"0 2 4\" + \"<0,12>\"", "0 2 4 | 12 14 16"),
+            // Nothing shorter than the input or its canonical form
+            ("hh:r3", "hh:r3"),
+            ("[a] [[b]]", "a b"),
+        ] {
+            let simplified = simplify(input);
+            assert_eq!(*expected, simplified);
+            test(&simplified, input);
+        }
+        assert_eq!(
+            Err(Error::ArithmeticError(ArithmeticError::NonNumericValue(
+                "a".to_string()
+            ))),
+            simplify::simplify("\"a b\" + 1", &Options::default())
+        );
+
+        assert_eq!(
+            Some("bd(3,8)".to_string()),
+            simplify::from_pattern(&transform("bd(3,8)", None).unwrap())
+        );
+        assert_eq!(None, simplify::from_pattern(&transform("bd:r3", None).unwrap()));
+    }
+
+    #[test]
+    fn equivalent_patterns() {
+        let difference = |a: &str, b: &str| equivalence::difference(a, b, &Options::default(), None).unwrap();
+        // Repetitions of the same loop
+        assert_eq!(None, difference("a b", "a b | a b"));
+        assert_eq!(None, difference("a <b,c>", "a b | a c | a b | a c"));
+        assert_eq!(None, difference("a b c @3/4", "[a b c]!2 @3/2"));
+        assert_eq!(None, difference("bd(3,8)", "bd ~ ~ bd ~ ~ bd ~"));
+        // Different subdivisions
+        assert_eq!(
+            Ok(None),
+            equivalence::pattern_difference(
+                &transform("a b", Some(4)).unwrap(),
+                &transform("a b", None).unwrap(),
+                None,
+                &Limits::default()
+            )
+        );
+
+        let Difference { onset, first, second } = difference("a b | a c", "a b").unwrap();
+        assert_eq!(Rational::new(3, 2), onset);
+        assert_eq!("c", first.unwrap().timed_event.event.value);
+        assert_eq!("b", second.unwrap().timed_event.event.value);
+        // An event only one pattern plays
+        let Difference { onset, first, second } = difference("a [b c]", "a [b ~]").unwrap();
+        assert_eq!(Rational::new(3, 4), onset);
+        assert_eq!("c", first.unwrap().timed_event.event.value);
+        assert_eq!(None, second);
+        let Difference { onset, first, second } = difference("~ b", "a b").unwrap();
+        assert_eq!(Rational::zero(), onset);
+        assert_eq!(None, first);
+        assert_eq!("a", second.unwrap().timed_event.event.value);
+        assert_eq!(
+            "at 1/2: b lasting 1/2 / b lasting 1/2, probability 50",
+            difference("a b", "a b?50").unwrap().to_string()
+        );
+
+        // Nudges smaller than the resolution
+        let rounded = |a: &str, b: &str, resolution| {
+            equivalence::difference(a, b, &Options::default(), NonZeroU32::new(resolution)).unwrap()
+        };
+        assert!(difference("a b", "a b>10").is_some());
+        assert_eq!(None, rounded("a b", "a b>10", 16));
+        assert!(rounded("a b", "a b>200", 16).is_some());
+    }
+
+    #[test]
+    fn grid_view() {
+        let render = |input: &str, width| {
+            let options = GridOptions {
+                width,
+                ..GridOptions::default()
+            };
+            grid::render(&transform(input, None).unwrap(), &options)
+        };
+        assert_eq!("bd x...|xx..\nsn ..x.|..x.", render("bd ~ sn ~ | bd bd sn ~", 80));
+        // Lengths and probabilities
+        assert_eq!(
+            "a x--.........|......x-x-x-\nb ......+--...|............\nc .........:--|............",
+            render("a _ b?50 c?20 | ~ a:r3", 80)
+        );
+        // Notes from the highest
+        assert_eq!(
+            "72 ....o-\n67 ...x..\n64 ..x...\n60 x-....",
+            render("60 [64 67] 72?80", 80)
+        );
+        // Zoomed out to fit, onsets win over what they share a column with
+        assert_eq!(
+            "hh xxxx|....|....\nbd ....|xx.x|....\nsn ....|....|xxxx",
+            render("hh*16 | bd(3,8) | sn*4", 20)
+        );
+        assert_eq!("", render("~ ~", 80));
+
+        let options = GridOptions {
+            width: 80,
+            charset: Charset::Unicode,
+        };
+        assert_eq!(
+            "bd █─·█····│█─────··\nsn ····▒─··│······▓─",
+            grid::render(&transform("bd [~ bd] sn?50 ~ | bd_ _ _ sn?80", None).unwrap(), &options)
+        );
+    }
+
+    #[test]
+    fn tidal_notation() {
+        for (expression, notation) in &[
+            ("bd [~ bd] sn ~", "bd [~ bd] sn ~"),
+            ("a b | c d", "<[a b] [c d]>"),
+            ("bd(3,8)", "bd ~ ~ bd ~ ~ bd ~"),
+            ("a?50 b?20 <a,[b c]>", "a?0.5 b?0.8 <a [b c]>"),
+            ("a_ _ b | [a_ b] _", "<[a _ b] [[a b] ~]>"),
+            ("{a b c}%4", "{a b c}%4"),
+        ] {
+            assert_eq!(Ok(notation.to_string()), tidal::export(expression));
+            test(&tidal::import(notation).unwrap(), expression);
+        }
+
+        for (notation, expression) in &[
+            ("bd*2 [~ bd] sn", "bd*2 [~ bd] sn"),
+            ("bd(3,8,2)", "bd(3,8,6)"),
+            // Tidal places the pulses differently
+            ("bd(5,12)", "bd(5,12,10)"),
+            ("bd(<3 5>,8)", "<[bd ~ ~ bd ~ ~ bd ~],[bd ~ bd bd ~ bd bd ~]>"),
+            ("a? b?0.3 [c d]?", "a?50 b?70 [c?50 d?50]"),
+            ("<a!2 b*2 [c d]>", "<a,a,[b b],[c d]>"),
+            ("a@3 b _ . c ! d", "[a_ _ _ b_ _] [c!2 d]"),
+            ("{a b c}", "[a b c]"),
+            ("<[a _ b] c>", "<[a_ _ b],c>"),
+        ] {
+            assert_eq!(Ok(expression.to_string()), tidal::import(notation));
+        }
+        test(&tidal::import("bd(5,12)").unwrap(), "bd ~ ~ bd ~ bd ~ ~ bd ~ bd ~");
+
+        let unsupported = |construct: &str| Err(Error::TidalError(TidalError::Unsupported(construct.to_string())));
+        assert_eq!(unsupported("ratchet"), tidal::export("a:r3"));
+        assert_eq!(unsupported("nudge"), tidal::export("a>10"));
+        assert_eq!(unsupported("humanize"), tidal::export("humanize(1, 2)[a b]"));
+        assert_eq!(unsupported("swing"), tidal::export("swing(8, 0.7)[a b]"));
+        assert_eq!(unsupported("scale"), tidal::export("@scale=minor 0 2"));
+        assert_eq!(unsupported("arithmetic"), tidal::export("\"0 2\" + 1"));
+        assert_eq!(unsupported("measure length"), tidal::export("a b c @3/4"));
+        assert_eq!(unsupported("tie across groups"), tidal::export("[a b_] _"));
+        assert_eq!(
+            unsupported("alternates in a sequence of measures"),
+            tidal::export("a <b,c> | d")
+        );
+        assert_eq!(unsupported("stack"), tidal::import("[a, b]"));
+        assert_eq!(unsupported("random choice"), tidal::import("a | b"));
+        assert_eq!(unsupported("slow"), tidal::import("a/2"));
+        assert_eq!(unsupported("sample number"), tidal::import("bd:3"));
+        assert_eq!(unsupported("value c#"), tidal::import("c# e"));
+        assert_eq!(unsupported("weight of a group"), tidal::import("[a b]@2 c"));
+        assert_eq!(unsupported("nested alternation"), tidal::import("<a <b c>>"));
+        assert_eq!(unsupported("weight in an alternation"), tidal::import("<a@2 b>"));
+        assert_eq!(Err(Error::TidalError(TidalError::InvalidSyntax)), tidal::import("a [b"));
+
+        // Steps written out are checked before they are made
+        let too_many = Err(Error::LimitError(LimitError::TooManyEvents));
+        for notation in &[
+            "<a!100000000>",
+            "<a*100000000>",
+            "a(1,100000000)",
+            "~@100000000",
+            "a@100000000",
+        ] {
+            assert_eq!(too_many, tidal::import(notation), "{}", notation);
+        }
+        assert_eq!(too_many, tidal::import("<[a!1000]!1001 b>"));
+    }
+
+    #[test]
+    fn json_output() {
+        let pattern = transform("a:r2 b?50", Some(4)).unwrap();
+        let json = pattern.format_steps(OutputFormat::Json);
+        assert_eq!(
+            serde_json::json!({
+                "schema_version": 1,
+                "steps": [
+                    {"index": 1, "duration": 1, "ratchet": 0, "event": {"value": "a", "probability": 100}},
+                    {"index": 2, "duration": 1, "ratchet": 0, "event": {"value": "a", "probability": 100}},
+                    {"index": 3, "duration": 2, "event": {"value": "b", "probability": 50}}
+                ],
+                "length": "1",
+                "subdivision": 4,
+                "measures": [1]
+            }),
+            serde_json::from_str::<serde_json::Value>(&json).unwrap()
+        );
+        assert_eq!(pattern, serde_json::from_str(&json).unwrap());
+
+        let pattern = transform("a:r2:v0.5", Some(2)).unwrap();
+        let json = pattern.format_steps(OutputFormat::Json);
+        assert!(json.contains("\"velocity\": 50"));
+        assert_eq!(pattern, serde_json::from_str(&json).unwrap());
+
+        let options = Options {
+            tempo: Some(Tempo::new(120.0, 1.0).unwrap()),
+            ..Default::default()
+        };
+        let pattern = transform_with_options("a b _ c | d e @3/4", &options).unwrap();
+        let json = pattern.format_steps(OutputFormat::Json);
+        assert_eq!(pattern, serde_json::from_str(&json).unwrap());
+        assert!(
+            serde_json::from_str::<Pattern>(&json.replacen("\"schema_version\": 1", "\"schema_version\": 0", 1))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn other_output_formats() {
+        let options = Options {
+            subdivision: Some(4),
+            tempo: Some(Tempo::new(120.0, 1.0).unwrap()),
+            ..Default::default()
+        };
+        let pattern = transform_with_options("a:r2 b?50", &options).unwrap();
+        assert_eq!(
+            "index,value,probability,duration,velocity,ratchet,onset_ms,duration_ms\n\
+             1,a,100,1,100,0,0,500\n\
+             2,a,100,1,100,0,500,500\n\
+             3,b,50,2,100,,1000,1000",
+            pattern.format_steps(OutputFormat::Csv)
+        );
+
+        let bytes = pattern.encode(OutputFormat::MessagePack);
+        assert_eq!(pattern, rmp_serde::from_slice(&bytes).unwrap());
+        assert_eq!(
+            bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+            pattern.format_steps(OutputFormat::MessagePack)
+        );
+
+        let bytes = pattern.encode(OutputFormat::Binary);
+        assert_eq!(b"BATT", &bytes[..4]);
+        assert_eq!(28 + 4 + 3 * 16 + 2 * 5, bytes.len());
+    }
 }
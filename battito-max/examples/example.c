@@ -9,4 +9,8 @@ int main (void) {
     struct pattern pattern = transform(input, 480);
     printf("%d\n", pattern.events[120].value);
     printf("%d\n", pattern.length);
+    struct buffer csv = encode(input, 480, FORMAT_CSV);
+    printf("%.*s\n", (int)csv.length, csv.data);
+    battito_buffer_free(csv);
+    printf("%d\n", check("a!99999999999", 480) == ERROR_OVERFLOW);
 }
\ No newline at end of file
@@ -6,6 +6,38 @@ struct event {
 struct pattern {
   struct event* events;
   uint32_t length;
+  uint32_t ticks;
+  uint32_t* measures;
+  uint32_t measures_length;
 };
 
-struct pattern transform(char* input, uint32_t subdivision);
\ No newline at end of file
+struct buffer {
+  uint8_t* data;
+  uint32_t length;
+};
+
+enum format {
+  FORMAT_JSON = 0,
+  FORMAT_MAX = 1,
+  FORMAT_CSV = 2,
+  FORMAT_MESSAGEPACK = 3,
+  FORMAT_BINARY = 4
+};
+
+enum error {
+  ERROR_NONE = 0,
+  ERROR_INVALID = 1,
+  ERROR_TOO_MANY_MEASURES = 2,
+  ERROR_TOO_MANY_EVENTS = 3,
+  ERROR_TOO_DEEP = 4,
+  ERROR_SUBDIVISION_TOO_LARGE = 5,
+  ERROR_OVERFLOW = 6
+};
+
+struct pattern transform(char* input, uint32_t subdivision);
+
+struct buffer encode(char* input, uint32_t subdivision, uint32_t format);
+
+uint32_t check(char* input, uint32_t subdivision);
+
+void battito_buffer_free(struct buffer buffer);
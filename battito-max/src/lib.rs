@@ -4,6 +4,9 @@ use std::ffi::CStr;
 use std::mem;
 use std::os::raw::c_char;
 
+use battito_lib::pattern::error::{Error, LimitError};
+use battito_lib::pattern::OutputFormat;
+
 #[repr(C)]
 pub struct Event {
     pub value: u32,
@@ -13,18 +16,81 @@ pub struct Event {
 #[repr(C)]
 pub struct Pattern {
     pub events: *const Event,
+    pub length: u32, // in cycles, rounded up
+    pub ticks: u32,  // length of the events buffer
+    pub measures: *const u32,
+    pub measures_length: u32,
+}
+
+#[repr(C)]
+pub struct Buffer {
+    pub data: *const u8,
     pub length: u32,
 }
 
+// Formats of `encode`, as in battito_max.h
+pub const FORMAT_JSON: u32 = 0;
+pub const FORMAT_MAX: u32 = 1;
+pub const FORMAT_CSV: u32 = 2;
+pub const FORMAT_MESSAGEPACK: u32 = 3;
+pub const FORMAT_BINARY: u32 = 4;
+
+fn output_format(format: u32) -> Option<OutputFormat> {
+    match format {
+        FORMAT_JSON => Some(OutputFormat::Json),
+        FORMAT_MAX => Some(OutputFormat::Max),
+        FORMAT_CSV => Some(OutputFormat::Csv),
+        FORMAT_MESSAGEPACK => Some(OutputFormat::MessagePack),
+        FORMAT_BINARY => Some(OutputFormat::Binary),
+        _ => None,
+    }
+}
+
+// Errors of `check`, as in battito_max.h
+pub const ERROR_NONE: u32 = 0;
+pub const ERROR_INVALID: u32 = 1;
+pub const ERROR_TOO_MANY_MEASURES: u32 = 2;
+pub const ERROR_TOO_MANY_EVENTS: u32 = 3;
+pub const ERROR_TOO_DEEP: u32 = 4;
+pub const ERROR_SUBDIVISION_TOO_LARGE: u32 = 5;
+pub const ERROR_OVERFLOW: u32 = 6;
+
+fn error_code(error: Error) -> u32 {
+    match error {
+        Error::LimitError(LimitError::TooManyMeasures) => ERROR_TOO_MANY_MEASURES,
+        Error::LimitError(LimitError::TooManyEvents) => ERROR_TOO_MANY_EVENTS,
+        Error::LimitError(LimitError::TooDeep) => ERROR_TOO_DEEP,
+        Error::LimitError(LimitError::SubdivisionTooLarge) => ERROR_SUBDIVISION_TOO_LARGE,
+        Error::LimitError(LimitError::Overflow) => ERROR_OVERFLOW,
+        _ => ERROR_INVALID,
+    }
+}
+
+// Safety: `ptr` must point to a valid, NUL-terminated C string
+unsafe fn parse(ptr: *const c_char, subdivision: u32) -> Result<battito_lib::pattern::pattern::Pattern, Error> {
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| Error::EventParsingError)
+        .and_then(|input| battito_lib::pattern::transform(input, Some(subdivision)))
+}
+
+// Safety: `ptr` must point to a valid, NUL-terminated C string
+unsafe fn pattern(ptr: *const c_char, subdivision: u32) -> battito_lib::pattern::pattern::Pattern {
+    // Invalid inputs, including the ones exceeding the default limits, give an empty pattern, `check`
+    // tells them apart
+    parse(ptr, subdivision).unwrap_or(battito_lib::pattern::pattern::Pattern::empty(subdivision))
+}
+
+// Kept safe as in the first version of the library, C callers must pass a valid, NUL-terminated
+// C string
 #[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn transform(ptr: *const c_char, subdivision: u32) -> Pattern {
-    let cstr = unsafe { CStr::from_ptr(ptr) };
-    let pattern = battito_lib::pattern::transform(cstr.to_str().unwrap(), Some(subdivision))
-        .unwrap_or(battito_lib::pattern::pattern::Pattern::empty(subdivision));
-    let filled = pattern.fill();
+    let pattern = unsafe { pattern(ptr, subdivision) };
 
-    let v: Vec<Event> = filled
-        .into_iter()
+    // Written straight into the buffer, one tick at a time
+    let v: Vec<Event> = pattern
+        .events()
         .map(|te| Event {
             value: te.value.parse().unwrap_or(0),
             probability: te.probability,
@@ -33,9 +99,63 @@ pub extern "C" fn transform(ptr: *const c_char, subdivision: u32) -> Pattern {
 
     let pointer = v.as_ptr();
     mem::forget(v);
+    let measures = pattern.measures.clone();
+    let measures_pointer = measures.as_ptr();
+    mem::forget(measures);
 
     Pattern {
         events: pointer,
-        length: pattern.length,
+        length: pattern.length.ceil() as u32,
+        ticks: pattern.ticks(),
+        measures: measures_pointer,
+        measures_length: pattern.measures.len() as u32,
+    }
+}
+
+/// One of the `ERROR_` codes: `ERROR_NONE` when `transform` gives the pattern, otherwise why it
+/// gives an empty one
+///
+/// # Safety
+///
+/// `ptr` must point to a valid, NUL-terminated C string.
+#[no_mangle]
+pub unsafe extern "C" fn check(ptr: *const c_char, subdivision: u32) -> u32 {
+    parse(ptr, subdivision).err().map_or(ERROR_NONE, error_code)
+}
+
+/// The pattern in one of the `FORMAT_` formats, text formats as UTF-8 without a terminating NUL.
+/// An unknown format gives an empty buffer with a null pointer. The buffer is released with
+/// `battito_buffer_free`
+///
+/// # Safety
+///
+/// `ptr` must point to a valid, NUL-terminated C string.
+#[no_mangle]
+pub unsafe extern "C" fn encode(ptr: *const c_char, subdivision: u32, format: u32) -> Buffer {
+    let bytes = match output_format(format) {
+        Some(format) => pattern(ptr, subdivision).encode(format),
+        None => {
+            return Buffer {
+                data: std::ptr::null(),
+                length: 0,
+            }
+        }
+    };
+    // Without spare capacity, so that the length is enough to free it
+    let bytes = bytes.into_boxed_slice();
+    Buffer {
+        length: bytes.len() as u32,
+        data: Box::into_raw(bytes) as *const u8,
+    }
+}
+
+/// # Safety
+///
+/// `buffer` must have been returned by `encode` and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn battito_buffer_free(buffer: Buffer) {
+    if !buffer.data.is_null() {
+        let bytes = std::ptr::slice_from_raw_parts_mut(buffer.data as *mut u8, buffer.length as usize);
+        drop(Box::from_raw(bytes));
     }
 }